@@ -1,18 +1,23 @@
 use std::{
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, RwLock},
-    vec,
 };
 
 use crate::{
-    camera::{CameraController, StaticCamera},
+    camera::{CameraController, CameraMode, CameraView, Framing, IController, StaticCamera},
+    debug::DebugView,
     gpu::Gpu,
-    io::{GuiRenderer, IoEngine, Ui},
-    model, resource, texture, ModelEntry, Renderer, Resources,
+    hdr::{Msaa, ToneMapping},
+    io::{
+        fs::{export, normals::NormalMode, uv::UvMode, weld::WeldOptions},
+        GuiRenderer, IoEngine, Ui,
+    },
+    light::{Light, LightKind},
+    resource::{LoadOptions, Sky},
+    texture, Renderer, Resources,
 };
 use egui::{Align2, Context};
 use transform_gizmo_egui::*;
-use wgpu::util::DeviceExt;
 use winit::{
     event::*,
     event_loop::EventLoop,
@@ -25,18 +30,46 @@ pub struct App {
     gpu: Arc<Gpu>,
     renderer: Renderer,
     io_engine: IoEngine<Arc<RwLock<CameraController>>>,
+    /// Also owned by `io_engine`, gets the raw mouse motion.
+    camera_controller: Arc<RwLock<CameraController>>,
 }
 
-#[derive(Default)]
 struct Gui {
     gizmo: Gizmo,
     camera: Arc<RwLock<StaticCamera>>,
+    camera_controller: Arc<RwLock<CameraController>>,
+    resources: Arc<Resources>,
+    export_path: String,
+    /// Writes STL exports as ASCII.
+    export_ascii: bool,
 }
 
 impl Gui {
-    pub fn new(camera: Arc<RwLock<StaticCamera>>) -> Self {
+    pub fn new(
+        camera: Arc<RwLock<StaticCamera>>,
+        camera_controller: Arc<RwLock<CameraController>>,
+        resources: Arc<Resources>,
+    ) -> Self {
         let gizmo = Gizmo::default();
-        Self { gizmo, camera }
+        Self {
+            gizmo,
+            camera,
+            camera_controller,
+            resources,
+            export_path: "export.glb".to_string(),
+            export_ascii: false,
+        }
+    }
+
+    /// Writes every loaded model to `export_path`, the format follows the extension.
+    fn export_models(&self) -> anyhow::Result<()> {
+        let model_db = self.resources.model_db.read().unwrap();
+        let models = model_db
+            .get_all()
+            .map(|entry| (&entry.model, entry.instances.as_slice()))
+            .collect::<Vec<_>>();
+
+        export::export(Path::new(&self.export_path), self.export_ascii, &models)
     }
 
     pub fn update_gizmo(&mut self) {
@@ -49,36 +82,392 @@ impl Ui for Gui {
         egui::Window::new("Control Plane")
             .default_open(true)
             .resizable(true)
-            .show(ctx, |ui| if ui.button("Open Asset folder").clicked() {});
+            .show(ctx, |ui| {
+                if ui.button("Open Asset folder").clicked() {}
+
+                ui.separator();
+                let mut options = self.resources.load_options.write().unwrap();
+                let mut weld = options.weld.is_some();
+                ui.checkbox(&mut weld, "Weld vertices")
+                    .on_hover_text("Merges vertices at the same position, applied to models loaded from now on");
+                if weld != options.weld.is_some() {
+                    options.weld = weld.then(WeldOptions::default);
+                }
+                if let Some(weld) = &mut options.weld {
+                    ui.add(
+                        egui::DragValue::new(&mut weld.epsilon)
+                            .speed(1e-5)
+                            .clamp_range(0.0..=1.0)
+                            .prefix("Weld distance: "),
+                    );
+                    let mut degrees = weld.crease_angle.to_degrees();
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut degrees, 0.0..=180.0)
+                                .text("Crease angle")
+                                .suffix("°"),
+                        )
+                        .on_hover_text("Faces meeting at a larger angle keep a hard edge")
+                        .changed()
+                    {
+                        weld.crease_angle = degrees.to_radians();
+                    }
+                }
+                egui::ComboBox::from_label("Normals")
+                    .selected_text(options.normal_mode.to_string())
+                    .show_ui(ui, |ui| {
+                        for mode in NormalMode::ALL {
+                            ui.selectable_value(&mut options.normal_mode, mode, mode.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Applied to models loaded from now on");
+                egui::ComboBox::from_label("UV mapping")
+                    .selected_text(options.uv_mode.to_string())
+                    .show_ui(ui, |ui| {
+                        for mode in UvMode::ALL {
+                            ui.selectable_value(&mut options.uv_mode, mode, mode.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Used for models loaded from now on without texture coordinates",
+                    );
+                drop(options);
+
+                ui.separator();
+                let camera = *self.camera.read().unwrap();
+                let mut controller = self.camera_controller.write().unwrap();
+                let mut mode = controller.mode();
+                egui::ComboBox::from_label("Camera")
+                    .selected_text(mode.to_string())
+                    .show_ui(ui, |ui| {
+                        for camera_mode in CameraMode::ALL {
+                            ui.selectable_value(&mut mode, camera_mode, camera_mode.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text(match mode {
+                        CameraMode::Orbit => {
+                            "Left drag to orbit, middle drag to pan, wheel to zoom, click to select, double click to set the pivot"
+                        }
+                        CameraMode::Fly => "WASD to move, Q and E down and up, left drag to look, click to select",
+                        CameraMode::Keys => "W and S to dolly, A and D to turn around the target, click to select",
+                    });
+                controller.set_mode(mode);
+                if mode == CameraMode::Fly {
+                    ui.add(
+                        egui::Slider::new(&mut controller.fly.speed, 0.1..=1000.0)
+                            .text("Fly speed")
+                            .suffix(" /s")
+                            .logarithmic(true),
+                    );
+                }
+                ui.horizontal_wrapped(|ui| {
+                    for view in CameraView::ALL {
+                        if ui.button(view.to_string()).clicked() {
+                            controller.look_from(&camera, view);
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Frame all").on_hover_text("Home").clicked() {
+                        controller.request_frame(Framing::All);
+                    }
+                    if ui.button("Frame selection").on_hover_text("F").clicked() {
+                        controller.request_frame(Framing::Selection);
+                    }
+                });
+                ui.checkbox(&mut controller.orthographic, "Orthographic")
+                    .on_hover_text("Keeps the size of what is at the target");
+                ui.add_enabled_ui(!controller.orthographic, |ui| {
+                    let mut degrees = controller.fovy.to_degrees();
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut degrees, 10.0..=120.0)
+                                .text("Field of view")
+                                .suffix("°"),
+                        )
+                        .changed()
+                    {
+                        controller.fovy = degrees.to_radians();
+                    }
+                });
+                drop(controller);
+
+                ui.separator();
+                let mut settings = self.resources.render_settings.write().unwrap();
+                let environment = &mut settings.environment;
+                ui.add(
+                    egui::Slider::new(&mut environment.intensity, 0.0..=4.0)
+                        .text("Environment intensity"),
+                );
+                let mut degrees = environment.rotation.to_degrees();
+                if ui
+                    .add(
+                        egui::Slider::new(&mut degrees, -180.0..=180.0)
+                            .text("Environment rotation")
+                            .suffix("°"),
+                    )
+                    .changed()
+                {
+                    environment.rotation = degrees.to_radians();
+                }
+
+                egui::ComboBox::from_label("MSAA")
+                    .selected_text(settings.msaa.to_string())
+                    .show_ui(ui, |ui| {
+                        for msaa in Msaa::ALL {
+                            ui.selectable_value(&mut settings.msaa, msaa, msaa.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Limited to the sample counts supported by the GPU");
+
+                ui.separator();
+                let debug = &mut settings.debug;
+                egui::ComboBox::from_label("View")
+                    .selected_text(debug.view.to_string())
+                    .show_ui(ui, |ui| {
+                        for view in DebugView::ALL {
+                            ui.selectable_value(&mut debug.view, view, view.to_string());
+                        }
+                    });
+                match debug.view {
+                    DebugView::Wireframe => {
+                        ui.add(
+                            egui::Slider::new(&mut debug.wire_width, 0.5..=4.0)
+                                .text("Line width")
+                                .suffix(" px"),
+                        );
+                    }
+                    DebugView::VertexNormals => {
+                        ui.add(
+                            egui::Slider::new(&mut debug.normal_length, 0.001..=1.0)
+                                .text("Normal length")
+                                .logarithmic(true),
+                        );
+                    }
+                    _ => {}
+                }
+
+                ui.separator();
+                let post = &mut settings.post;
+                egui::ComboBox::from_label("Tone mapping")
+                    .selected_text(post.tone_mapping.to_string())
+                    .show_ui(ui, |ui| {
+                        for tone_mapping in ToneMapping::ALL {
+                            ui.selectable_value(
+                                &mut post.tone_mapping,
+                                tone_mapping,
+                                tone_mapping.to_string(),
+                            );
+                        }
+                    });
+                ui.add(
+                    egui::Slider::new(&mut post.exposure, -8.0..=8.0)
+                        .text("Exposure")
+                        .suffix(" EV"),
+                );
+                ui.checkbox(&mut post.auto_exposure, "Auto exposure");
+                ui.add_enabled_ui(post.auto_exposure, |ui| {
+                    ui.add(
+                        egui::Slider::new(&mut post.adaptation_speed, 0.1..=10.0)
+                            .text("Adaptation speed")
+                            .logarithmic(true),
+                    );
+                });
+                ui.checkbox(&mut post.bloom, "Bloom");
+                ui.add_enabled_ui(post.bloom, |ui| {
+                    ui.add(
+                        egui::Slider::new(&mut post.bloom_intensity, 0.0..=0.5)
+                            .text("Bloom intensity"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut post.bloom_radius, 0.001..=0.02)
+                            .text("Bloom radius"),
+                    );
+                });
+
+                ui.separator();
+                let shadow = &mut settings.shadow;
+                ui.checkbox(&mut shadow.enabled, "Shadows");
+                ui.add_enabled_ui(shadow.enabled, |ui| {
+                    ui.add(egui::Slider::new(&mut shadow.depth_bias, 0.0..=0.1).text("Depth bias"));
+                    ui.add(
+                        egui::Slider::new(&mut shadow.normal_bias, 0.0..=0.1).text("Normal bias"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut shadow.pcf_radius, 0.0..=4.0)
+                            .text("Filter radius")
+                            .suffix(" texels"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut shadow.distance, 1.0..=200.0)
+                            .text("Shadow distance")
+                            .logarithmic(true),
+                    )
+                    .on_hover_text("Range of the directional shadow in front of the camera");
+                });
+                drop(settings);
+
+                ui.separator();
+                let mut lights = self.resources.lights.write().unwrap();
+                ui.horizontal(|ui| {
+                    ui.label("Lights");
+                    for kind in LightKind::ALL {
+                        if ui.button(format!("Add {kind}")).clicked() {
+                            lights.push(Light::new(kind));
+                        }
+                    }
+                });
+                let mut removed = None;
+                for (i, light) in lights.iter_mut().enumerate() {
+                    egui::CollapsingHeader::new(format!("{} light {}", light.kind, i + 1))
+                        .id_source(("light", i))
+                        .show(ui, |ui| {
+                            if light_ui(ui, light) {
+                                removed = Some(i);
+                            }
+                        });
+                }
+                if let Some(i) = removed {
+                    lights.remove(i);
+                }
+                drop(lights);
+
+                ui.separator();
+                let model_db = self.resources.model_db.read().unwrap();
+                let mut selection = self.resources.selection.write().unwrap();
+                egui::CollapsingHeader::new(format!("Models ({})", model_db.data.len())).show(
+                    ui,
+                    |ui| {
+                        let mut ids = model_db.data.keys().copied().collect::<Vec<_>>();
+                        ids.sort();
+                        for id in ids {
+                            let name = model_db
+                                .get(id)
+                                .model
+                                .meshes
+                                .first()
+                                .map_or("Empty", |mesh| mesh.name.as_str());
+                            let selected = *selection == Some(id);
+                            if ui.selectable_label(selected, format!("{id} {name}")).clicked() {
+                                *selection = if selected { None } else { Some(id) };
+                            }
+                        }
+                    },
+                );
+                drop(selection);
+                drop(model_db);
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.export_path);
+                    ui.checkbox(&mut self.export_ascii, "ASCII STL");
+                    if ui.button("Export").clicked() {
+                        match self.export_models() {
+                            Ok(()) => log::info!("Exported {}", self.export_path),
+                            Err(msg) => log::error!("{msg}"),
+                        }
+                    }
+                });
+            });
     }
 }
 
+/// Edits `light`, returns true when it should be removed.
+fn light_ui(ui: &mut egui::Ui, light: &mut Light) -> bool {
+    egui::ComboBox::from_label("Kind")
+        .selected_text(light.kind.to_string())
+        .show_ui(ui, |ui| {
+            for kind in LightKind::ALL {
+                ui.selectable_value(&mut light.kind, kind, kind.to_string());
+            }
+        });
+
+    let vector_ui = |ui: &mut egui::Ui, label: &str, vector: &mut [f32; 3]| {
+        ui.horizontal(|ui| {
+            for value in vector.iter_mut() {
+                ui.add(egui::DragValue::new(value).speed(0.05));
+            }
+            ui.label(label);
+        });
+    };
+    if light.kind != LightKind::Directional {
+        vector_ui(ui, "Position", &mut light.position);
+    }
+    if light.kind != LightKind::Point {
+        vector_ui(ui, "Direction", &mut light.direction);
+    }
+
+    ui.horizontal(|ui| {
+        ui.color_edit_button_rgb(&mut light.color);
+        ui.label("Color");
+    });
+    ui.add(
+        egui::Slider::new(&mut light.intensity, 0.0..=100.0)
+            .text("Intensity")
+            .logarithmic(true),
+    );
+    if light.kind != LightKind::Directional {
+        ui.add(egui::Slider::new(&mut light.range, 0.1..=100.0).text("Range"));
+    }
+    if light.kind == LightKind::Spot {
+        let mut inner = light.inner_angle.to_degrees();
+        let mut outer = light.outer_angle.to_degrees();
+        ui.add(
+            egui::Slider::new(&mut inner, 0.0..=outer)
+                .text("Inner angle")
+                .suffix("°"),
+        );
+        ui.add(
+            egui::Slider::new(&mut outer, 1.0..=89.0)
+                .text("Outer angle")
+                .suffix("°"),
+        );
+        light.inner_angle = inner.to_radians();
+        light.outer_angle = outer.to_radians();
+    }
+    ui.checkbox(&mut light.cast_shadows, "Cast shadows")
+        .on_hover_text(
+            "Only the first point or spot light and the first directional light cast shadows",
+        );
+
+    ui.button("Remove").clicked()
+}
+
 impl App {
     pub async fn new(window: Window) -> Self {
         let window = Arc::new(window);
 
         let gpu = Arc::new(Gpu::new(Arc::clone(&window)).await);
-        let gui = Gui::default();
 
         let controller = Arc::new(RwLock::new(CameraController::default()));
         let camera = Arc::new(RwLock::new(StaticCamera::new()));
+        let resources = Arc::new(Resources::new());
+        let gui = Gui::new(
+            Arc::clone(&camera),
+            Arc::clone(&controller),
+            Arc::clone(&resources),
+        );
 
         let renderer = Renderer::new(
-            Arc::clone(&window),
+            Some(Arc::clone(&window)),
             Arc::clone(&gpu),
             Arc::clone(&controller),
             Arc::clone(&camera),
+            Sky::Default,
         )
         .await;
 
-        let resources = Arc::new(Resources::new());
-        let gui_renderer = GuiRenderer::new(Arc::clone(&gpu), None, 1, Arc::clone(&window), gui);
+        let gui_renderer = GuiRenderer::new(Arc::clone(&gpu), None, Arc::clone(&window), gui);
         let io_engine = IoEngine::new(
             Arc::clone(&gpu),
             Arc::clone(&resources),
             Arc::clone(&window),
             gui_renderer,
-            controller,
+            Arc::clone(&controller),
         );
 
         Self {
@@ -86,30 +475,19 @@ impl App {
             resources,
             gpu,
             io_engine,
+            camera_controller: controller,
         }
     }
 
+    /// Used for the models loaded from now on, also editable in the UI.
+    pub fn set_load_options(&self, options: LoadOptions) {
+        *self.resources.load_options.write().unwrap() = options;
+    }
+
+    /// Opens a mesh file, or every mesh file in a folder, as if dropped on
+    /// the window.
     pub async fn handle_file_drop(&mut self, path: &PathBuf) -> anyhow::Result<()> {
-        let model = resource::load_model(path.to_path_buf(), &self.gpu).await?;
-        let mut model_db = self.resources.model_db.write().unwrap();
-        let device = &self.gpu.device;
-        let instances = vec![model::Instance::default()];
-
-        let instance_data = instances
-            .iter()
-            .map(model::Instance::to_raw)
-            .collect::<Vec<_>>();
-        let model_entry = ModelEntry {
-            instances,
-            instance_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Model instance"),
-                contents: bytemuck::cast_slice(&instance_data),
-                usage: wgpu::BufferUsages::VERTEX,
-            }),
-            model,
-        };
-        model_db.insert(model_entry);
-        Ok(())
+        self.io_engine.handle_file_drop(path).await
     }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
@@ -118,7 +496,7 @@ impl App {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == self.renderer.window().id() => {
+            } if Some(window_id) == self.renderer.window().map(Window::id) => {
                 if !self.renderer.input(event) {
                     match event {
                         WindowEvent::CloseRequested
@@ -137,10 +515,10 @@ impl App {
                         WindowEvent::RedrawRequested => {
                             log::info!("Redraw");
 
-                            self.renderer.update();
+                            self.renderer.update(&self.resources);
 
                             let model_read = self.resources.model_db.read().unwrap();
-                            let models = model_read.get_all();
+                            let models = model_read.iter();
 
                             match self.renderer.render_models(models) {
                                 Ok(_) => {}
@@ -163,6 +541,7 @@ impl App {
                     self.io_engine.handle_event(event);
                 }
             }
+            Event::DeviceEvent { .. } => self.camera_controller.input(&event),
             _ => {}
         });
     }
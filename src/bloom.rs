@@ -0,0 +1,266 @@
+use wgpu::util::DeviceExt;
+
+use crate::{gpu::Gpu, texture};
+
+/// Levels of the mip chain, fewer for small targets.
+const MAX_MIPS: u32 = 6;
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+    filter_radius: f32,
+    _padding: [f32; 3],
+}
+
+/// Blurs the HDR target into a half resolution texture mixed back by the
+/// tone mapping.
+pub struct Bloom {
+    downsample_first: wgpu::RenderPipeline,
+    downsample: wgpu::RenderPipeline,
+    upsample: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    /// One view per mip level, the first is the blurred result.
+    mip_views: Vec<wgpu::TextureView>,
+    /// Read by the pass writing to the mip of the same index.
+    downsample_bind_groups: Vec<wgpu::BindGroup>,
+    /// Reads the mip below the one of the same index.
+    upsample_bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl Bloom {
+    pub fn new(gpu: &Gpu, hdr: &texture::Texture) -> Self {
+        let device = &gpu.device;
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom::layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Bloom::sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Bloom::uniform"),
+            contents: bytemuck::cast_slice(&[BloomUniform {
+                filter_radius: 0.005,
+                _padding: [0.0; 3],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("bloom.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom::pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point, blend| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: FORMAT,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        let mut bloom = Self {
+            downsample_first: pipeline("fs_downsample_first", None),
+            downsample: pipeline("fs_downsample", None),
+            upsample: pipeline(
+                "fs_upsample",
+                Some(wgpu::BlendState {
+                    color: additive,
+                    alpha: additive,
+                }),
+            ),
+            layout,
+            sampler,
+            uniform_buffer,
+            mip_views: Vec::new(),
+            downsample_bind_groups: Vec::new(),
+            upsample_bind_groups: Vec::new(),
+        };
+        bloom.resize(gpu, hdr);
+        bloom
+    }
+
+    /// Recreates the mip chain for `hdr`, after it was recreated.
+    pub fn resize(&mut self, gpu: &Gpu, hdr: &texture::Texture) {
+        let device = &gpu.device;
+
+        let width = (hdr.size.width / 2).max(1);
+        let height = (hdr.size.height / 2).max(1);
+        let mips = MAX_MIPS.min(width.min(height).ilog2() + 1);
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Bloom::texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: mips,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        self.mip_views = (0..mips)
+            .map(|mip| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Bloom::mip_view"),
+                    base_mip_level: mip,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let bind_group = |source: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Bloom::bind_group"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.uniform_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        self.downsample_bind_groups = std::iter::once(&hdr.view)
+            .chain(&self.mip_views[..self.mip_views.len() - 1])
+            .map(bind_group)
+            .collect();
+        self.upsample_bind_groups = self.mip_views[1..].iter().map(bind_group).collect();
+    }
+
+    /// `radius` is the spread of every upsampling step, in texture coordinates.
+    pub fn update(&self, queue: &wgpu::Queue, radius: f32) {
+        let uniform = BloomUniform {
+            filter_radius: radius,
+            _padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// The blurred HDR target, once [`Bloom::render`] ran.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.mip_views[0]
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        let pass = |encoder: &mut wgpu::CommandEncoder,
+                    pipeline: &wgpu::RenderPipeline,
+                    bind_group: &wgpu::BindGroup,
+                    target: &wgpu::TextureView,
+                    load: wgpu::LoadOp<wgpu::Color>| {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Bloom::render"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        };
+
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+        for (mip, (bind_group, target)) in self
+            .downsample_bind_groups
+            .iter()
+            .zip(&self.mip_views)
+            .enumerate()
+        {
+            let pipeline = match mip {
+                0 => &self.downsample_first,
+                _ => &self.downsample,
+            };
+            pass(encoder, pipeline, bind_group, target, clear);
+        }
+        for (bind_group, target) in self.upsample_bind_groups.iter().zip(&self.mip_views).rev() {
+            pass(
+                encoder,
+                &self.upsample,
+                bind_group,
+                target,
+                wgpu::LoadOp::Load,
+            );
+        }
+    }
+}
@@ -0,0 +1,115 @@
+// Physically based bloom: the HDR image is downsampled along a mip chain
+// with a 13 tap filter, then upsampled back with a 3x3 tent filter, every
+// level adding to the one above it.
+// Based on "Next Generation Post Processing in Call of Duty: Advanced Warfare"
+
+// Must match `bloom::BloomUniform`.
+struct Params {
+    // Radius of the upsampling filter, in texture coordinates.
+    filter_radius: f32,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+struct VertexOutput {
+    @location(0) uv: vec2<f32>,
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vi: u32) -> VertexOutput {
+    var out: VertexOutput;
+    // A triangle covering the whole target.
+    out.uv = vec2<f32>(
+        f32((vi << 1u) & 2u),
+        f32(vi & 2u),
+    );
+    out.clip_position = vec4<f32>(out.uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv.y = 1.0 - out.uv.y;
+    return out;
+}
+
+fn tap(uv: vec2<f32>, offset: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+    return textureSampleLevel(source, source_sampler, uv + offset * texel, 0.0).rgb;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Weight of a group of taps that keeps single very bright pixels from
+// flickering as they move.
+fn karis_weight(color: vec3<f32>) -> f32 {
+    return 1.0 / (1.0 + luminance(color));
+}
+
+fn downsample(uv: vec2<f32>, karis: bool) -> vec3<f32> {
+    let a = tap(uv, vec2(-2.0, 2.0));
+    let b = tap(uv, vec2(0.0, 2.0));
+    let c = tap(uv, vec2(2.0, 2.0));
+    let d = tap(uv, vec2(-2.0, 0.0));
+    let e = tap(uv, vec2(0.0, 0.0));
+    let f = tap(uv, vec2(2.0, 0.0));
+    let g = tap(uv, vec2(-2.0, -2.0));
+    let h = tap(uv, vec2(0.0, -2.0));
+    let i = tap(uv, vec2(2.0, -2.0));
+    let j = tap(uv, vec2(-1.0, 1.0));
+    let k = tap(uv, vec2(1.0, 1.0));
+    let l = tap(uv, vec2(-1.0, -1.0));
+    let m = tap(uv, vec2(1.0, -1.0));
+
+    // Five overlapping 2x2 boxes, the center one weighted the most.
+    var groups = array<vec3<f32>, 5>(
+        (j + k + l + m) * 0.25,
+        (a + b + d + e) * 0.25,
+        (b + c + e + f) * 0.25,
+        (d + e + g + h) * 0.25,
+        (e + f + h + i) * 0.25,
+    );
+    var weights = array<f32, 5>(0.5, 0.125, 0.125, 0.125, 0.125);
+
+    var color = vec3(0.0);
+    var total = 0.0;
+    for (var n = 0; n < 5; n++) {
+        var weight = weights[n];
+        if karis {
+            weight *= karis_weight(groups[n]);
+        }
+        color += groups[n] * weight;
+        total += weight;
+    }
+    return color / total;
+}
+
+// Reads the HDR image itself.
+@fragment
+fn fs_downsample_first(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(downsample(in.uv, true), 1.0);
+}
+
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(downsample(in.uv, false), 1.0);
+}
+
+// Added to the level above by the blend state.
+@fragment
+fn fs_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let r = params.filter_radius;
+    let s = source_sample(in.uv, vec2(-r, r)) + source_sample(in.uv, vec2(r, r))
+        + source_sample(in.uv, vec2(-r, -r)) + source_sample(in.uv, vec2(r, -r));
+    let e = source_sample(in.uv, vec2(0.0, r)) + source_sample(in.uv, vec2(-r, 0.0))
+        + source_sample(in.uv, vec2(r, 0.0)) + source_sample(in.uv, vec2(0.0, -r));
+    let center = source_sample(in.uv, vec2(0.0));
+    return vec4((s + e * 2.0 + center * 4.0) / 16.0, 1.0);
+}
+
+fn source_sample(uv: vec2<f32>, offset: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(source, source_sampler, uv + offset, 0.0).rgb;
+}
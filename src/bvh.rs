@@ -0,0 +1,261 @@
+//! Bounding volume hierarchy over the triangles of a mesh, for ray casts.
+
+use crate::{
+    camera::Ray,
+    io::fs::stats::Bounds,
+    model::ModelVertex,
+    pick::{ray_triangle, triangle_corners},
+};
+
+/// Most triangles in a leaf, unless they can't be told apart.
+const LEAF_SIZE: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    bounds: Bounds,
+    /// First triangle of a leaf in [`Bvh::triangles`], or first child of an
+    /// inner node, the second one following it.
+    start: u32,
+    /// Triangles of a leaf, 0 for inner nodes.
+    count: u32,
+}
+
+/// Nodes split at the median of the triangle centers along their longest
+/// side, built once by the first ray cast that reaches the mesh, see
+/// [`crate::model::Mesh::bvh`].
+pub struct Bvh {
+    /// The root first.
+    nodes: Vec<Node>,
+    /// Triangles, as their index in the index buffer divided by 3, those of
+    /// every leaf next to each other.
+    triangles: Vec<u32>,
+}
+
+/// Triangle hit by [`Bvh::cast_ray`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TriangleHit {
+    pub triangle: usize,
+    pub distance: f32,
+    /// Weights of the three corners at the hit.
+    pub barycentrics: na::Vector3<f32>,
+}
+
+impl Bvh {
+    /// Over the triangle list `indices` into `vertices`, `None` without
+    /// triangles or with an index out of range.
+    pub fn new(vertices: &[ModelVertex], indices: &[u32]) -> Option<Self> {
+        let boxes = (0..indices.len() / 3)
+            .map(|triangle| {
+                let corners = triangle_corners(vertices, indices, triangle)?;
+                Bounds::from_points(corners)
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if boxes.is_empty() {
+            return None;
+        }
+
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * boxes.len() / LEAF_SIZE + 1),
+            triangles: (0..boxes.len() as u32).collect(),
+        };
+        bvh.nodes.push(Node {
+            bounds: boxes[0],
+            start: 0,
+            count: 0,
+        });
+        bvh.split(0, 0..boxes.len(), &boxes);
+        Some(bvh)
+    }
+
+    /// Makes `node` a leaf with `range` of the triangles, or splits it when
+    /// they are too many.
+    fn split(&mut self, node: usize, range: std::ops::Range<usize>, boxes: &[Bounds]) {
+        let triangles = &mut self.triangles[range.clone()];
+        let bounds = triangles
+            .iter()
+            .map(|&t| boxes[t as usize])
+            .reduce(|a, b| a.union(&b))
+            .expect("nodes have triangles");
+        self.nodes[node] = Node {
+            bounds,
+            start: range.start as u32,
+            count: triangles.len() as u32,
+        };
+        if triangles.len() <= LEAF_SIZE {
+            return;
+        }
+
+        let centers = Bounds::from_points(triangles.iter().map(|&t| boxes[t as usize].center()))
+            .expect("nodes have triangles");
+        let axis = centers.size().imax();
+        if centers.size()[axis] <= 0.0 {
+            return;
+        }
+        let middle = triangles.len() / 2;
+        triangles.select_nth_unstable_by(middle, |&a, &b| {
+            let center = |t: u32| boxes[t as usize].center()[axis];
+            center(a).total_cmp(&center(b))
+        });
+
+        let first = self.nodes.len();
+        self.nodes.extend([self.nodes[node]; 2]);
+        self.nodes[node].start = first as u32;
+        self.nodes[node].count = 0;
+        let middle = range.start + middle;
+        self.split(first, range.start..middle, boxes);
+        self.split(first + 1, middle..range.end, boxes);
+    }
+
+    /// Closest triangle hit by `ray` before `max_distance`, from either side.
+    /// `vertices` and `indices` are those the hierarchy was built from.
+    pub fn cast_ray(
+        &self,
+        ray: &Ray,
+        vertices: &[ModelVertex],
+        indices: &[u32],
+        max_distance: f32,
+    ) -> Option<TriangleHit> {
+        let inverse = ray.direction.map(|d| 1.0 / d);
+        let mut closest: Option<TriangleHit> = None;
+        let mut max_distance = max_distance;
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            match ray_box(ray, &inverse, &node.bounds) {
+                Some(distance) if distance <= max_distance => {}
+                _ => continue,
+            }
+
+            if node.count == 0 {
+                let (first, second) = (node.start as usize, node.start as usize + 1);
+                let distance = |child: usize| {
+                    ray_box(ray, &inverse, &self.nodes[child].bounds).unwrap_or(f32::INFINITY)
+                };
+                // The nearest child is popped first, its hits skip the other.
+                if distance(first) < distance(second) {
+                    stack.extend([second, first]);
+                } else {
+                    stack.extend([first, second]);
+                }
+                continue;
+            }
+
+            let start = node.start as usize;
+            for &triangle in &self.triangles[start..start + node.count as usize] {
+                let triangle = triangle as usize;
+                let Some(corners) = triangle_corners(vertices, indices, triangle) else {
+                    continue;
+                };
+                let Some((distance, u, v)) = ray_triangle(ray, corners) else {
+                    continue;
+                };
+                if distance < max_distance {
+                    max_distance = distance;
+                    closest = Some(TriangleHit {
+                        triangle,
+                        distance,
+                        barycentrics: na::Vector3::new(1.0 - u - v, u, v),
+                    });
+                }
+            }
+        }
+        closest
+    }
+}
+
+/// Distance along `ray` to where it enters `bounds`, 0 from inside.
+/// `inverse` has the inverses of the components of its direction.
+pub fn ray_box(ray: &Ray, inverse: &na::Vector3<f32>, bounds: &Bounds) -> Option<f32> {
+    let to_min = (bounds.min - ray.origin).component_mul(inverse);
+    let to_max = (bounds.max - ray.origin).component_mul(inverse);
+    let enter = to_min.inf(&to_max).max().max(0.0);
+    let exit = to_min.sup(&to_max).min();
+    (enter <= exit).then_some(enter)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::fs::test_util::{cube, vertex};
+
+    /// Wavy grid of `n` by `n` quads over [0, 1]².
+    fn grid(n: u32) -> (Vec<ModelVertex>, Vec<u32>) {
+        let vertices = (0..=n)
+            .flat_map(|j| (0..=n).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let (x, y) = (i as f32 / n as f32, j as f32 / n as f32);
+                vertex([x, y, (x * 7.0).sin() * (y * 5.0).cos() * 0.2])
+            })
+            .collect();
+        let indices = (0..n)
+            .flat_map(|j| (0..n).map(move |i| j * (n + 1) + i))
+            .flat_map(|a| [a, a + 1, a + n + 2, a, a + n + 2, a + n + 1])
+            .collect();
+        (vertices, indices)
+    }
+
+    #[test]
+    fn hits_match_a_brute_force_search() {
+        let (vertices, indices) = grid(24);
+        let bvh = Bvh::new(&vertices, &indices).unwrap();
+        assert!(bvh.nodes.len() > 1);
+
+        for k in 0..50 {
+            let t = k as f32 / 50.0;
+            let ray = Ray {
+                origin: na::Point3::new(t, 1.0 - t * t, 2.0),
+                direction: na::Vector3::new(0.3 - t, 0.2, -1.0).normalize(),
+            };
+            let brute_force = indices
+                .chunks_exact(3)
+                .filter_map(|triangle| {
+                    let corners = [0, 1, 2]
+                        .map(|i| na::Point3::from(vertices[triangle[i] as usize].position));
+                    ray_triangle(&ray, corners).map(|(distance, _, _)| distance)
+                })
+                .reduce(f32::min);
+            let hit = bvh.cast_ray(&ray, &vertices, &indices, f32::INFINITY);
+            assert_eq!(hit.map(|hit| hit.distance), brute_force, "ray {k}");
+
+            if let Some(hit) = hit {
+                let position = (0..3)
+                    .map(|i| {
+                        let index = indices[hit.triangle * 3 + i] as usize;
+                        na::Vector3::from(vertices[index].position) * hit.barycentrics[i]
+                    })
+                    .sum::<na::Vector3<f32>>();
+                assert!((ray.at(hit.distance).coords - position).norm() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn misses_and_limits() {
+        let (vertices, indices) = grid(4);
+        let bvh = Bvh::new(&vertices, &indices).unwrap();
+        let down = Ray {
+            origin: na::Point3::new(0.5, 0.5, 2.0),
+            direction: -na::Vector3::z(),
+        };
+        assert!(bvh
+            .cast_ray(&down, &vertices, &indices, f32::INFINITY)
+            .is_some());
+        assert!(bvh.cast_ray(&down, &vertices, &indices, 1.0).is_none());
+        let beside = Ray {
+            origin: na::Point3::new(1.5, 0.5, 2.0),
+            ..down
+        };
+        assert!(bvh
+            .cast_ray(&beside, &vertices, &indices, f32::INFINITY)
+            .is_none());
+        assert!(Bvh::new(&vertices, &[]).is_none());
+    }
+
+    #[test]
+    fn indices_out_of_range_are_rejected() {
+        let mut cube = cube(6);
+        assert!(Bvh::new(&cube.vertices, &cube.indices).is_some());
+        cube.indices[5] = cube.vertices.len() as u32;
+        assert!(Bvh::new(&cube.vertices, &cube.indices).is_none());
+    }
+}
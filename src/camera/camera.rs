@@ -1,12 +1,28 @@
 use nalgebra as na;
 
+use std::{
+    fmt::Display,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 use winit::{
-    event::KeyEvent,
+    event::{ElementState, Event, KeyEvent, MouseButton, WindowEvent},
     keyboard::{KeyCode, PhysicalKey},
 };
 
-use super::ICamera;
+use crate::io::fs::stats::{BoundingSphere, Bounds};
+
+use super::{
+    CameraView, FpsCamera, FpsController, ICamera, IController, OrbitController, Projection,
+    Transition, DEFAULT_FOVY,
+};
+
+/// Seconds taken by [`CameraController::animate_to`].
+const TRANSITION_SECONDS: f32 = 0.4;
+/// Farthest the cursor may move between the press and the release of a click.
+const CLICK_PIXELS: f32 = 4.0;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct StaticCamera {
     pub position: na::Point3<f32>,
     pub target: na::Point3<f32>,
@@ -42,7 +58,260 @@ impl ICamera for StaticCamera {
     }
 }
 
+/// How the mouse and the keys move the camera.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Around a target, see [`OrbitController`].
+    #[default]
+    Orbit,
+    /// Through the scene, see [`FpsController`].
+    Fly,
+    /// Around the target with the keys only, see [`KeyController`].
+    Keys,
+}
+
+impl CameraMode {
+    pub const ALL: [CameraMode; 3] = [Self::Orbit, Self::Fly, Self::Keys];
+}
+
+impl Display for CameraMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Orbit => "Orbit",
+            Self::Fly => "Fly",
+            Self::Keys => "Keys",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// What [`CameraController::frame`] is asked to fit in the view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// Every model, with Home and after a file is dropped.
+    All,
+    /// The selected model, or every model without a selection, with F.
+    Selection,
+}
+
+/// Moves a [`StaticCamera`] with the controller of the current
+/// [`CameraMode`]. Switching keeps the view.
 pub struct CameraController {
+    mode: CameraMode,
+    pub orbit: OrbitController,
+    pub fly: FpsController,
+    pub keys: KeyController,
+    /// Vertical field of view in radians, of the perspective projection.
+    pub fovy: f32,
+    /// Whether the projection is orthographic, as large at the target as the
+    /// perspective one.
+    pub orthographic: bool,
+    transition: Option<Transition>,
+    frame_request: Option<Framing>,
+    /// Cursor position in pixels, `None` out of the window, and where the
+    /// left button was pressed.
+    cursor: Option<na::Point2<f32>>,
+    press: Option<na::Point2<f32>>,
+    select_request: Option<na::Point2<f32>>,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::default(),
+            orbit: OrbitController::default(),
+            fly: FpsController::default(),
+            keys: KeyController::default(),
+            fovy: DEFAULT_FOVY,
+            orthographic: false,
+            transition: None,
+            frame_request: None,
+            cursor: None,
+            press: None,
+            select_request: None,
+        }
+    }
+}
+
+impl CameraController {
+    pub fn mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode != self.mode {
+            // Nothing held or gliding carries over.
+            self.orbit.reset();
+            self.fly.reset();
+            self.keys.reset();
+            self.mode = mode;
+        }
+    }
+
+    /// Projection of `camera` for a view of `width` by `height` pixels, with
+    /// clip planes around `scene` when there is one.
+    pub fn projection(
+        &self,
+        width: f32,
+        height: f32,
+        camera: &StaticCamera,
+        scene: Option<&Bounds>,
+    ) -> Projection {
+        let mut projection = Projection::with_fovy(width, height, self.fovy);
+        if self.orthographic {
+            projection = projection.orthographic((camera.target - camera.position).norm());
+        }
+        match scene {
+            Some(bounds) => projection.fit_depth(&camera.build_view_matrix(), bounds),
+            None => projection,
+        }
+    }
+
+    /// Turns `camera` around its target to look from the side `view`.
+    pub fn look_from(&mut self, camera: &StaticCamera, view: CameraView) {
+        self.animate_to(camera, &view.orient(camera));
+    }
+
+    /// Moves from the view `from` to `to` over the next updates.
+    pub fn animate_to(&mut self, from: &StaticCamera, to: &StaticCamera) {
+        self.orbit.reset();
+        self.fly.reset();
+        self.keys.reset();
+        self.transition = Some(Transition::new(from, to, TRANSITION_SECONDS));
+    }
+
+    /// Moves `camera` along its view direction and onto the center of
+    /// `sphere`, close enough for it to fill `projection`.
+    pub fn frame(
+        &mut self,
+        camera: &StaticCamera,
+        sphere: &BoundingSphere,
+        projection: &Projection,
+    ) {
+        let back = (camera.position - camera.target)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(na::Vector3::z);
+        let framed = StaticCamera {
+            position: sphere.center + back * projection.fit_distance(sphere.radius),
+            target: sphere.center,
+            up: camera.up,
+        };
+        self.animate_to(camera, &framed);
+    }
+
+    /// Asks for [`CameraController::frame`] around what `framing` covers,
+    /// which only the owner of the scene knows.
+    pub fn request_frame(&mut self, framing: Framing) {
+        self.frame_request = Some(framing);
+    }
+
+    pub fn take_frame_request(&mut self) -> Option<Framing> {
+        self.frame_request.take()
+    }
+
+    /// Cursor position of the last click, left press and release without a
+    /// drag in between, to select what is under it.
+    pub fn take_select_request(&mut self) -> Option<na::Point2<f32>> {
+        self.select_request.take()
+    }
+
+    /// Cursor position in pixels, `None` when out of the window.
+    pub fn cursor(&self) -> Option<na::Point2<f32>> {
+        self.cursor
+    }
+
+    pub fn process_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => match key {
+                KeyCode::KeyF => self.request_frame(Framing::Selection),
+                KeyCode::Home => self.request_frame(Framing::All),
+                _ => {}
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = Some(na::Point2::new(position.x as f32, position.y as f32));
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor = None,
+            WindowEvent::MouseInput {
+                button: MouseButton::Left,
+                state,
+                ..
+            } => {
+                if state.is_pressed() {
+                    self.press = self.cursor;
+                } else if let (Some(press), Some(cursor)) = (self.press.take(), self.cursor) {
+                    if na::distance(&press, &cursor) <= CLICK_PIXELS {
+                        self.select_request = Some(cursor);
+                    }
+                }
+            }
+            // Loaded by now, and likely out of view.
+            WindowEvent::DroppedFile(_) => self.request_frame(Framing::All),
+            _ => {}
+        }
+
+        match self.mode {
+            CameraMode::Orbit => self.orbit.process_event(event),
+            CameraMode::Fly => self.fly.handle_window_event(event),
+            CameraMode::Keys => {
+                if let WindowEvent::KeyboardInput { event, .. } = event {
+                    self.keys.process_key(event);
+                }
+            }
+        }
+    }
+
+    /// Applies the input since the last update, `dt` seconds ago, for a view
+    /// of `viewport` pixels.
+    pub fn update_camera(
+        &mut self,
+        camera: &mut StaticCamera,
+        projection: &Projection,
+        viewport: na::Vector2<f32>,
+        dt: f32,
+    ) {
+        if let Some(transition) = &mut self.transition {
+            if transition.update(camera, dt) {
+                self.transition = None;
+            }
+            return;
+        }
+
+        match self.mode {
+            CameraMode::Orbit => self.orbit.update_camera(camera, projection, viewport, dt),
+            CameraMode::Fly => {
+                // Taken from the view every time, so that it follows changes
+                // made by anything else. The target stays as far ahead.
+                let distance = (camera.target - camera.position).norm();
+                let mut fly_camera = FpsCamera::from_view(camera);
+                self.fly
+                    .update_camera(&mut fly_camera, Duration::from_secs_f32(dt.max(0.0)));
+                fly_camera.write_view(camera, distance);
+            }
+            CameraMode::Keys => self.keys.update_camera(camera),
+        }
+    }
+
+    /// See [`OrbitController::take_pivot_request`], only in orbit mode.
+    pub fn take_pivot_request(&mut self) -> Option<na::Point2<f32>> {
+        match self.mode {
+            CameraMode::Orbit => self.orbit.take_pivot_request(),
+            CameraMode::Fly | CameraMode::Keys => None,
+        }
+    }
+}
+
+/// Orbits a [`StaticCamera`] around its target by a fixed step every update
+/// while W, A, S or D is held: W and S dolly, A and D turn.
+pub struct KeyController {
     speed: f32,
     is_forward_pressed: bool,
     is_backward_pressed: bool,
@@ -50,13 +319,13 @@ pub struct CameraController {
     is_right_pressed: bool,
 }
 
-impl Default for CameraController {
+impl Default for KeyController {
     fn default() -> Self {
         Self::new(0.2)
     }
 }
 
-impl CameraController {
+impl KeyController {
     pub fn new(speed: f32) -> Self {
         Self {
             speed,
@@ -67,6 +336,11 @@ impl CameraController {
         }
     }
 
+    /// Forgets the keys held.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.speed);
+    }
+
     pub fn process_key(&mut self, key_event: &KeyEvent) {
         if let KeyEvent {
             state,
@@ -74,7 +348,7 @@ impl CameraController {
             ..
         } = key_event
         {
-            self.process_events(&key_code, state.is_pressed());
+            self.process_events(key_code, state.is_pressed());
         }
     }
 
@@ -130,3 +404,133 @@ impl CameraController {
         }
     }
 }
+
+impl IController for Arc<RwLock<CameraController>> {
+    fn input(&self, event: &Event<()>) {
+        let mut controller = self.write().unwrap();
+        if let (Event::DeviceEvent { event, .. }, CameraMode::Fly) = (event, controller.mode) {
+            controller.fly.handle_device_event(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn switching_modes_keeps_the_view() {
+        let mut controller = CameraController::default();
+        let projection = Projection::with_aspect(4.0, 3.0);
+        let viewport = na::Vector2::new(800.0, 600.0);
+        let mut camera = StaticCamera {
+            position: na::Point3::new(3.0, 2.0, 4.0),
+            target: na::Point3::new(0.0, 0.5, 0.0),
+            up: na::Vector3::y(),
+        };
+        let view = camera.build_view_matrix();
+
+        controller.set_mode(CameraMode::Fly);
+        controller.update_camera(&mut camera, &projection, viewport, 0.1);
+        controller.set_mode(CameraMode::Orbit);
+        controller.update_camera(&mut camera, &projection, viewport, 0.1);
+        assert!((camera.build_view_matrix() - view).norm() < 1e-4);
+        assert!((camera.target - na::Point3::new(0.0, 0.5, 0.0)).norm() < 1e-4);
+    }
+
+    #[test]
+    fn keys_orbit_in_fixed_steps() {
+        let mut controller = CameraController::default();
+        controller.set_mode(CameraMode::Keys);
+        let projection = Projection::with_aspect(4.0, 3.0);
+        let viewport = na::Vector2::new(800.0, 600.0);
+        let mut camera = StaticCamera::new();
+        let distance = (camera.target - camera.position).norm();
+
+        controller.keys.process_events(&KeyCode::KeyD, true);
+        controller.update_camera(&mut camera, &projection, viewport, 0.1);
+        assert!(camera.position.x < 0.0);
+        assert!(((camera.target - camera.position).norm() - distance).abs() < 1e-5);
+
+        controller.keys.process_events(&KeyCode::KeyD, false);
+        controller.keys.process_events(&KeyCode::KeyW, true);
+        controller.update_camera(&mut camera, &projection, viewport, 0.1);
+        assert!(((camera.target - camera.position).norm() - (distance - 0.2)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn orthographic_keeps_the_size_at_the_target() {
+        let mut controller = CameraController::default();
+        let camera = StaticCamera::new();
+        let edge = camera.target + na::Vector3::x() * 0.5;
+        let clip_x = |controller: &CameraController| {
+            let view_proj = controller
+                .projection(4.0, 3.0, &camera, None)
+                .build_matrix()
+                * camera.build_view_matrix();
+            let clip = view_proj * edge.to_homogeneous();
+            clip.x / clip.w
+        };
+
+        let perspective = clip_x(&controller);
+        controller.orthographic = true;
+        assert!((clip_x(&controller) - perspective).abs() < 1e-5);
+    }
+
+    #[test]
+    fn standard_views_are_animated() {
+        let mut controller = CameraController::default();
+        let projection = Projection::with_aspect(4.0, 3.0);
+        let viewport = na::Vector2::new(800.0, 600.0);
+        let mut camera = StaticCamera::new();
+        controller.look_from(&camera, CameraView::Top);
+
+        controller.update_camera(&mut camera, &projection, viewport, 0.1);
+        assert!(camera.position.y < 2.0, "not there at once");
+        for _ in 0..10 {
+            controller.update_camera(&mut camera, &projection, viewport, 0.1);
+        }
+        let distance = StaticCamera::new().position.coords.norm();
+        assert!((camera.position - na::Point3::new(0.0, distance, 0.0)).norm() < 1e-4);
+    }
+
+    #[test]
+    fn framing_fits_the_sphere() {
+        let sphere = BoundingSphere {
+            center: na::Point3::new(5.0, 1.0, 0.0),
+            radius: 2.0,
+        };
+        let viewport = na::Vector2::new(400.0, 300.0);
+        for orthographic in [false, true] {
+            let mut controller = CameraController {
+                orthographic,
+                ..Default::default()
+            };
+            let mut camera = StaticCamera::new();
+            let projection = controller.projection(4.0, 3.0, &camera, None);
+            controller.frame(&camera, &sphere, &projection);
+            controller.update_camera(&mut camera, &projection, viewport, 1.0);
+            assert!((camera.target - sphere.center).norm() < 1e-4);
+
+            let view = camera.build_view_matrix();
+            let view_proj = controller
+                .projection(4.0, 3.0, &camera, None)
+                .build_matrix()
+                * view;
+            let inv_view = view.try_inverse().unwrap();
+            let ndc = |side: na::Vector3<f32>| {
+                let edge = sphere.center + inv_view.transform_vector(&side) * sphere.radius;
+                let clip = view_proj * edge.to_homogeneous();
+                clip.xy() / clip.w
+            };
+            // The outline touches the top and bottom of the landscape view,
+            // the top of the sphere seen from its framing distance lies a bit
+            // inside it.
+            let (right, top) = (ndc(na::Vector3::x()).x, ndc(na::Vector3::y()).y);
+            assert!(
+                right < top && (top - (DEFAULT_FOVY / 2.0).cos()).abs() < 1e-3,
+                "{orthographic} {top}"
+            );
+        }
+    }
+}
@@ -1,23 +1,79 @@
-use std::sync::{Arc, RwLock};
 use std::{f64::consts::FRAC_PI_2, time::Duration};
 
-use winit::event::{Event, MouseButton};
 use winit::{
     dpi::PhysicalPosition,
-    event::{ElementState, KeyEvent, MouseScrollDelta, WindowEvent},
+    event::{DeviceEvent, ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
     keyboard::{KeyCode, PhysicalKey},
 };
 
-use super::{ICamera, IController};
+use super::{ICamera, StaticCamera};
 
 const SAFE_FRAC_PI_2: f64 = FRAC_PI_2 - 0.001;
+/// Pixels scrolled on a touchpad worth one wheel notch.
+const PIXELS_PER_NOTCH: f64 = 50.0;
+/// Seconds of flight at full speed per wheel notch.
+const SECONDS_PER_NOTCH: f64 = 0.25;
 
+/// Camera looking along `yaw` from +x towards +z and `pitch` up from the
+/// horizon, with +y up.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct FpsCamera {
-    positon: na::Point3<f64>,
-    yaw: f64,
-    pitch: f64,
+    pub position: na::Point3<f64>,
+    pub yaw: f64,
+    pub pitch: f64,
 }
 
+impl FpsCamera {
+    pub fn new(position: na::Point3<f64>, yaw: f64, pitch: f64) -> Self {
+        Self {
+            position,
+            yaw,
+            pitch: pitch.clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2),
+        }
+    }
+
+    /// Same position and view direction as `camera`.
+    pub fn from_view(camera: &StaticCamera) -> Self {
+        let forward = (camera.target - camera.position)
+            .cast::<f64>()
+            .try_normalize(f64::EPSILON)
+            .unwrap_or_else(|| -na::Vector3::z());
+        Self::new(
+            camera.position.cast(),
+            forward.z.atan2(forward.x),
+            forward.y.asin(),
+        )
+    }
+
+    pub fn forward(&self) -> na::Vector3<f64> {
+        let (yaw_sin, yaw_cos) = self.yaw.sin_cos();
+        let (pitch_sin, pitch_cos) = self.pitch.sin_cos();
+        na::Vector3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin)
+    }
+
+    /// Writes this view to `camera`, its target `distance` ahead.
+    pub fn write_view(&self, camera: &mut StaticCamera, distance: f32) {
+        camera.position = self.position.cast();
+        camera.target = camera.position + self.forward().cast::<f32>() * distance;
+        camera.up = na::Vector3::y();
+    }
+}
+
+impl ICamera for FpsCamera {
+    fn build_view_matrix(&self) -> na::Matrix4<f32> {
+        let target = self.position + self.forward();
+        na::Isometry3::look_at_rh(&self.position, &target, &na::Vector3::y())
+            .to_matrix()
+            .cast()
+    }
+
+    fn position(&self) -> na::Point3<f32> {
+        self.position.cast()
+    }
+}
+
+/// Flies an [`FpsCamera`] with WASD, Q and E down and up, and looks around
+/// with the mouse while the left button is held.
 pub struct FpsController {
     amount_left: f64,
     amount_right: f64,
@@ -28,13 +84,16 @@ pub struct FpsController {
     rotate_horizontal: f64,
     rotate_vertical: f64,
     scroll: f64,
-    speed: f64,
-    sensitivity: f64,
+    looking: bool,
+    /// Units per second.
+    pub speed: f64,
+    /// Radians turned per pixel of mouse motion.
+    pub sensitivity: f64,
 }
 
 impl Default for FpsController {
     fn default() -> Self {
-        Self::new(4.0, 0.4)
+        Self::new(4.0, 0.003)
     }
 }
 
@@ -50,11 +109,17 @@ impl FpsController {
             rotate_horizontal: 0.0,
             rotate_vertical: 0.0,
             scroll: 0.0,
+            looking: false,
             speed,
             sensitivity,
         }
     }
 
+    /// Forgets the keys and buttons held and the motion not applied yet.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.speed, self.sensitivity);
+    }
+
     pub fn process_keyboard(&mut self, key: KeyCode, state: ElementState) -> bool {
         use KeyCode::*;
         let amount = if state.is_pressed() { 1.0 } else { 0.0 };
@@ -75,49 +140,53 @@ impl FpsController {
                 self.amount_backward = amount;
                 true
             }
+            KeyE => {
+                self.amount_up = amount;
+                true
+            }
+            KeyQ => {
+                self.amount_down = amount;
+                true
+            }
             _ => false,
         }
     }
 
+    /// Raw mouse motion, only turns the camera while looking around.
     pub fn process_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
-        self.rotate_horizontal = mouse_dx;
-        self.rotate_vertical = mouse_dy;
+        if self.looking {
+            self.rotate_horizontal += mouse_dx;
+            self.rotate_vertical += mouse_dy;
+        }
     }
 
     pub fn process_scroll(&mut self, mouse_delta: &MouseScrollDelta) {
         use MouseScrollDelta::*;
-        self.scroll = -match mouse_delta {
-            LineDelta(_, scroll) => (scroll * 100.0).into(),
-            PixelDelta(PhysicalPosition { y: scroll, .. }) => *scroll,
+        self.scroll += match mouse_delta {
+            LineDelta(_, scroll) => *scroll as f64,
+            PixelDelta(PhysicalPosition { y: scroll, .. }) => *scroll / PIXELS_PER_NOTCH,
         }
     }
 
+    /// Applies the input since the last update, `dt` ago. Mouse motion and
+    /// scrolling are distances, only the keys depend on `dt`.
     pub fn update_camera(&mut self, camera: &mut FpsCamera, dt: Duration) {
         let dt = dt.as_secs_f64();
         let (yaw_sin, yaw_cos) = camera.yaw.sin_cos();
-        let forward = na::Vector3::new(yaw_cos, 0.0, yaw_sin).normalize();
-        let right = na::Vector3::new(-yaw_sin, 0.0, yaw_cos).normalize();
-        camera.positon += forward * (self.amount_forward - self.amount_backward) * self.speed * dt;
-        camera.positon += right * (self.amount_right - self.amount_left) * self.speed * dt;
-
-        let (pitch_cos, pitch_sin) = camera.pitch.sin_cos();
-        let scrollward = na::Vector3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin);
-        camera.positon += scrollward * self.scroll * self.speed * self.sensitivity * dt;
-        self.scroll = 0.0;
+        let forward = na::Vector3::new(yaw_cos, 0.0, yaw_sin);
+        let right = na::Vector3::new(-yaw_sin, 0.0, yaw_cos);
+        camera.position += forward * (self.amount_forward - self.amount_backward) * self.speed * dt;
+        camera.position += right * (self.amount_right - self.amount_left) * self.speed * dt;
+        camera.position.y += (self.amount_up - self.amount_down) * self.speed * dt;
 
-        camera.positon.y = (self.amount_up - self.amount_down) * self.speed * dt;
-
-        camera.yaw += self.rotate_horizontal * self.sensitivity * dt;
-        camera.pitch += -self.rotate_vertical * self.sensitivity * dt;
+        camera.position += camera.forward() * self.scroll * self.speed * SECONDS_PER_NOTCH;
+        self.scroll = 0.0;
 
+        camera.yaw += self.rotate_horizontal * self.sensitivity;
+        camera.pitch -= self.rotate_vertical * self.sensitivity;
+        camera.pitch = camera.pitch.clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2);
         self.rotate_vertical = 0.0;
         self.rotate_horizontal = 0.0;
-
-        if camera.pitch < SAFE_FRAC_PI_2 {
-            camera.pitch = -SAFE_FRAC_PI_2;
-        } else if camera.pitch > SAFE_FRAC_PI_2 {
-            camera.pitch = SAFE_FRAC_PI_2;
-        }
     }
 
     pub fn handle_window_event(&mut self, event: &WindowEvent) {
@@ -135,28 +204,65 @@ impl FpsController {
             } => {
                 self.process_keyboard(*key_code, *state);
             }
+            MouseInput {
+                button: MouseButton::Left,
+                state,
+                ..
+            } => {
+                self.looking = state.is_pressed();
+            }
             MouseWheel { delta, .. } => {
                 self.process_scroll(delta);
             }
+            // The releases go to another window.
+            Focused(false) => self.reset(),
             _ => {}
         }
     }
+
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.process_mouse(delta.0, delta.1);
+        }
+    }
 }
 
-impl IController for Arc<RwLock<FpsController>> {
-    fn input(&self, event: &Event<()>) {
-        use winit::event::DeviceEvent::*;
-        use Event::*;
-        let mut controller = self.write().unwrap();
+#[cfg(test)]
+mod test {
+    use super::*;
 
-        match event {
-            DeviceEvent { event, .. } => match event {
-                MouseMotion { delta } => {
-                    controller.process_mouse(delta.0, delta.1);
-                }
-                _ => {}
-            },
-            _ => {}
-        }
+    #[test]
+    fn view_round_trips() {
+        let camera = StaticCamera {
+            position: na::Point3::new(1.0, 2.0, 3.0),
+            target: na::Point3::new(-2.0, 0.0, 1.0),
+            up: na::Vector3::y(),
+        };
+        let fps = FpsCamera::from_view(&camera);
+        let mut view = StaticCamera::new();
+        fps.write_view(&mut view, (camera.target - camera.position).norm());
+        assert!((view.position - camera.position).norm() < 1e-5);
+        assert!((view.target - camera.target).norm() < 1e-5);
+        assert!((fps.build_view_matrix() - camera.build_view_matrix()).norm() < 1e-5);
+    }
+
+    #[test]
+    fn flies_up_and_clamps_pitch() {
+        let mut controller = FpsController::default();
+        let mut camera = FpsCamera::new(na::Point3::new(0.0, 1.0, 0.0), 0.0, 0.0);
+        controller.process_keyboard(KeyCode::KeyE, ElementState::Pressed);
+        controller.process_keyboard(KeyCode::KeyW, ElementState::Pressed);
+        controller.update_camera(&mut camera, Duration::from_millis(500));
+        assert!((camera.position - na::Point3::new(2.0, 3.0, 0.0)).norm() < 1e-9);
+
+        // Ignored until a button is held.
+        controller.process_mouse(0.0, -1e6);
+        controller.update_camera(&mut camera, Duration::ZERO);
+        assert_eq!(camera.pitch, 0.0);
+
+        controller.looking = true;
+        controller.process_mouse(0.0, -1e6);
+        controller.update_camera(&mut camera, Duration::ZERO);
+        assert_eq!(camera.pitch, SAFE_FRAC_PI_2);
     }
 }
@@ -1,31 +1,203 @@
 use na::Matrix4;
 use winit::event::Event;
 
+use crate::io::fs::stats::Bounds;
+
 mod camera;
 mod fps;
+mod orbit;
+mod view;
 pub use camera::*;
+pub use fps::*;
+pub use orbit::*;
+pub use view::*;
+
+/// Half line from `origin` along the unit vector `direction`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: na::Point3<f32>,
+    pub direction: na::Vector3<f32>,
+}
+
+impl Ray {
+    pub fn at(&self, distance: f32) -> na::Point3<f32> {
+        self.origin + self.direction * distance
+    }
+}
+
+/// Normalized device coordinates, +y up, of a `cursor` position in pixels
+/// from the top left corner of a `viewport` of that many pixels.
+pub fn cursor_to_ndc(cursor: na::Point2<f32>, viewport: na::Vector2<f32>) -> na::Point2<f32> {
+    na::Point2::new(
+        2.0 * cursor.x / viewport.x - 1.0,
+        1.0 - 2.0 * cursor.y / viewport.y,
+    )
+}
 
+/// Vertical field of view of [`Projection::with_aspect`], about 58°. It used
+/// to be passed as `45.0`, which the projection takes as radians, this is the
+/// same view with an angle the field of view slider can show.
+pub const DEFAULT_FOVY: f32 = 45.0 - 14.0 * std::f32::consts::PI;
+/// Closest the near plane of [`Projection::fit_depth`] gets to the camera,
+/// relative to the size of the scene.
+const MIN_NEAR: f32 = 1e-4;
+
+/// Projection with reversed depth, 1 on the near plane and 0 on the far one,
+/// for a better precision far away. The far plane of perspective
+/// projections is at infinity, [`Projection::zfar`] only tells how far the
+/// scene goes.
 pub struct Projection {
     aspect: f32,
     fovy: f32,
     znear: f32,
     zfar: f32,
+    /// Height of the view volume when orthographic.
+    ortho_height: Option<f32>,
 }
 
 impl Projection {
     pub fn with_aspect(width: f32, height: f32) -> Self {
-        Self::new(width, height, 45.0, 0.1, 100.0)
+        Self::with_fovy(width, height, DEFAULT_FOVY)
+    }
+    pub fn with_fovy(width: f32, height: f32, fovy: f32) -> Self {
+        Self::new(width, height, fovy, 0.1, 100.0)
     }
+    /// Perspective with a vertical field of view of `fovy` radians.
     pub fn new(width: f32, height: f32, fovy: f32, znear: f32, zfar: f32) -> Self {
         Self {
             aspect: width / height,
             fovy,
             znear,
             zfar,
+            ortho_height: None,
+        }
+    }
+
+    /// Orthographic, things `distance` ahead keep the size they have in
+    /// perspective.
+    pub fn orthographic(self, distance: f32) -> Self {
+        Self {
+            ortho_height: Some(2.0 * distance * (self.fovy / 2.0).tan()),
+            ..self
+        }
+    }
+
+    pub fn is_orthographic(&self) -> bool {
+        self.ortho_height.is_some()
+    }
+
+    /// Moves the clip planes around `bounds`, seen through `view`. The near
+    /// plane of an orthographic projection may be behind the camera.
+    pub fn fit_depth(self, view: &na::Matrix4<f32>, bounds: &Bounds) -> Self {
+        let depth = -view.transform_point(&bounds.center()).z;
+        // A margin for what is drawn around the models, as vertex normals.
+        let radius = bounds.radius().max(f32::EPSILON) * 1.01;
+        let (near, far) = (depth - radius, depth + radius);
+        let near = if self.is_orthographic() {
+            near
+        } else {
+            near.max(radius * MIN_NEAR)
+        };
+        Self {
+            znear: near,
+            // Past the near plane when the scene is behind the camera.
+            zfar: far.max(near + radius),
+            ..self
         }
     }
+
     pub fn build_matrix(&self) -> na::Matrix4<f32> {
-        *na::Perspective3::new(self.aspect, self.fovy, self.znear, self.zfar).as_matrix()
+        let (near, far) = (self.znear, self.zfar);
+        #[rustfmt::skip]
+        let matrix = match self.ortho_height {
+            Some(height) => {
+                let (x, y) = (height * self.aspect / 2.0, height / 2.0);
+                na::Matrix4::new(
+                    1.0 / x, 0.0, 0.0, 0.0,
+                    0.0, 1.0 / y, 0.0, 0.0,
+                    0.0, 0.0, 1.0 / (far - near), far / (far - near),
+                    0.0, 0.0, 0.0, 1.0,
+                )
+            }
+            None => {
+                let f = 1.0 / (self.fovy / 2.0).tan();
+                na::Matrix4::new(
+                    f / self.aspect, 0.0, 0.0, 0.0,
+                    0.0, f, 0.0, 0.0,
+                    0.0, 0.0, 0.0, near,
+                    0.0, 0.0, -1.0, 0.0,
+                )
+            }
+        };
+        matrix
+    }
+
+    /// Distance from which a sphere of `radius` touches the narrowest sides
+    /// of the view, orthographic ones once sized for that distance.
+    pub fn fit_distance(&self, radius: f32) -> f32 {
+        let tan_half_y = (self.fovy / 2.0).tan();
+        let tan_half_fov = tan_half_y.min(tan_half_y * self.aspect);
+        radius.max(f32::EPSILON) / tan_half_fov.atan().sin()
+    }
+
+    /// Height of the view, in world units, at the view depth `distance`.
+    pub fn height_at(&self, distance: f32) -> f32 {
+        self.ortho_height
+            .unwrap_or_else(|| 2.0 * distance * (self.fovy / 2.0).tan())
+    }
+
+    pub fn znear(&self) -> f32 {
+        self.znear
+    }
+
+    pub fn zfar(&self) -> f32 {
+        self.zfar
+    }
+
+    /// [`Projection::znear`], in front of the camera, for what needs
+    /// positive view depths.
+    pub fn positive_znear(&self) -> f32 {
+        self.znear.max(self.zfar * 1e-3)
+    }
+
+    /// World space corners of the part of the view frustum between the view
+    /// depths `near` and `far`, for a camera with view matrix `view`.
+    pub fn slice_corners(
+        &self,
+        view: &na::Matrix4<f32>,
+        near: f32,
+        far: f32,
+    ) -> [na::Point3<f32>; 8] {
+        let inv_view = view.try_inverse().unwrap_or_else(na::Matrix4::identity);
+
+        let mut corners = [na::Point3::origin(); 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let depth = if i < 4 { near } else { far };
+            let half_y = self.height_at(depth) / 2.0;
+            let half_x = half_y * self.aspect;
+            let x = if i & 1 == 0 { -half_x } else { half_x };
+            let y = if i & 2 == 0 { -half_y } else { half_y };
+            *corner = inv_view.transform_point(&na::Point3::new(x, y, -depth));
+        }
+        corners
+    }
+
+    /// Ray from the camera with view matrix `view` through the point `ndc` of
+    /// the screen, in normalized device coordinates.
+    pub fn ray(&self, view: &na::Matrix4<f32>, ndc: na::Point2<f32>) -> Ray {
+        let inv_view = view.try_inverse().unwrap_or_else(na::Matrix4::identity);
+        // Through the point of the screen one unit ahead.
+        let half_y = self.height_at(1.0) / 2.0;
+        let screen = na::Vector3::new(ndc.x * half_y * self.aspect, ndc.y * half_y, -1.0);
+        let (origin, direction) = if self.is_orthographic() {
+            (na::Point3::new(screen.x, screen.y, 0.0), -na::Vector3::z())
+        } else {
+            (na::Point3::origin(), screen)
+        };
+        Ray {
+            origin: inv_view.transform_point(&origin),
+            direction: inv_view.transform_vector(&direction).normalize(),
+        }
     }
 }
 
@@ -66,6 +238,117 @@ impl CameraUniform {
         self.view = view.into();
         self.view_proj = view_proj.into();
         self.inv_proj = proj.try_inverse().unwrap().into();
-        self.inv_view = view.transpose().into();
+        self.inv_view = view.try_inverse().unwrap().into();
+    }
+
+    pub fn view_proj(&self) -> Matrix4<f32> {
+        self.view_proj.into()
+    }
+
+    /// Ray through the point `ndc` of the screen, in normalized device
+    /// coordinates, from the near plane.
+    pub fn ray(&self, ndc: na::Point2<f32>) -> Ray {
+        let inv_proj = Matrix4::from(self.inv_proj);
+        let inv_view = Matrix4::from(self.inv_view);
+        // In view space, where the points are close to the origin and their
+        // difference precise. The reversed depth is 1 on the near plane and
+        // 0.5 farther away, twice as far in perspective.
+        let unproject = |depth: f32| {
+            let point = inv_proj * na::Vector4::new(ndc.x, ndc.y, depth, 1.0);
+            na::Point3::from(point.xyz() / point.w)
+        };
+        let near = unproject(1.0);
+        let direction = unproject(0.5) - near;
+        Ray {
+            origin: inv_view.transform_point(&near),
+            direction: inv_view.transform_vector(&direction).normalize(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ndc_depth(projection: &Projection, distance: f32) -> f32 {
+        let clip = projection.build_matrix() * na::Vector4::new(0.0, 0.0, -distance, 1.0);
+        clip.z / clip.w
+    }
+
+    #[test]
+    fn depth_is_reversed() {
+        let perspective = Projection::new(4.0, 3.0, DEFAULT_FOVY, 0.5, 10.0);
+        assert!((ndc_depth(&perspective, 0.5) - 1.0).abs() < 1e-6);
+        assert!(ndc_depth(&perspective, 10.0) > ndc_depth(&perspective, 1e6));
+        assert!(ndc_depth(&perspective, 1e6) > 0.0);
+
+        // The sky is unprojected from the near plane.
+        let inverse = perspective.build_matrix().try_inverse().unwrap();
+        let near = inverse * na::Vector4::new(0.0, 0.0, 1.0, 1.0);
+        assert!((near.z / near.w + 0.5).abs() < 1e-5);
+
+        let orthographic = perspective.orthographic(2.0);
+        assert!((ndc_depth(&orthographic, 0.5) - 1.0).abs() < 1e-6);
+        assert!(ndc_depth(&orthographic, 10.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn uniform_rays_match_the_projection() {
+        let camera = StaticCamera {
+            position: na::Point3::new(100.0, 20.0, -40.0),
+            target: na::Point3::new(98.0, 21.0, -39.0),
+            up: na::Vector3::y(),
+        };
+        let view = camera.build_view_matrix();
+        let ndc = na::Point2::new(0.3, -0.6);
+        for orthographic in [false, true] {
+            let mut projection = Projection::new(16.0, 9.0, DEFAULT_FOVY, 1e-3, 50.0);
+            if orthographic {
+                projection = projection.orthographic(3.0);
+            }
+            let mut uniform = CameraUniform::new();
+            uniform.update_view_projection(&projection, &camera);
+            let expected = projection.ray(&view, ndc);
+            let ray = uniform.ray(ndc);
+
+            assert!(
+                (ray.direction - expected.direction).norm() < 1e-4,
+                "{orthographic}"
+            );
+            // From the near plane, on the same line.
+            let offset = ray.origin - expected.origin;
+            assert!(
+                offset.cross(&expected.direction).norm() < 1e-3,
+                "{orthographic}"
+            );
+        }
+    }
+
+    #[test]
+    fn clip_planes_fit_the_scene() {
+        let bounds = Bounds {
+            min: na::Point3::new(-1.0, -1.0, -1.0),
+            max: na::Point3::new(1.0, 1.0, 1.0),
+        };
+        let radius = bounds.radius() * 1.01;
+        let view = na::Isometry3::look_at_rh(
+            &na::Point3::new(0.0, 0.0, 10.0),
+            &na::Point3::origin(),
+            &na::Vector3::y(),
+        )
+        .to_homogeneous();
+
+        let projection = Projection::with_aspect(1.0, 1.0).fit_depth(&view, &bounds);
+        assert!((projection.znear() - (10.0 - radius)).abs() < 1e-4);
+        assert!((projection.zfar() - (10.0 + radius)).abs() < 1e-4);
+
+        // From inside, the near plane stays in front of the camera.
+        let inside = na::Matrix4::identity();
+        let projection = Projection::with_aspect(1.0, 1.0).fit_depth(&inside, &bounds);
+        assert!(projection.znear() > 0.0 && projection.znear() < 1e-3);
+        let projection = Projection::with_aspect(1.0, 1.0)
+            .orthographic(1.0)
+            .fit_depth(&inside, &bounds);
+        assert!((projection.znear() + radius).abs() < 1e-4);
     }
 }
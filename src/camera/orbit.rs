@@ -0,0 +1,440 @@
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+
+use winit::{
+    event::{KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+use super::{cursor_to_ndc, ICamera, Projection, StaticCamera};
+
+/// Longest time between the two clicks of a double click.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(300);
+/// Farthest the cursor may move between the two clicks of a double click.
+const DOUBLE_CLICK_PIXELS: f32 = 4.0;
+/// Pixels scrolled on a touchpad worth one wheel notch.
+const PIXELS_PER_NOTCH: f32 = 50.0;
+/// Wheel notches per second while W or S is held.
+const KEY_NOTCHES_PER_SECOND: f32 = 6.0;
+/// Closest angle between the view and the up vector, past it the view flips.
+const MIN_POLAR: f32 = 0.01;
+/// How fast, per second, the measured drag velocity follows the cursor.
+const VELOCITY_RATE: f32 = 20.0;
+/// How fast, per second, wheel notches are played out.
+const ZOOM_RATE: f32 = 15.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Drag {
+    Orbit,
+    Pan,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Keys {
+    forward: bool,
+    backward: bool,
+    left: bool,
+    right: bool,
+}
+
+/// Turns a [`StaticCamera`] around its target while dragging with the left
+/// mouse button, pans with the middle one and dollies towards the cursor
+/// with the wheel. W and S dolly, A and D turn.
+///
+/// A double click asks for the target to move to the surface under the
+/// cursor, see [`OrbitController::take_pivot_request`].
+pub struct OrbitController {
+    /// Radians turned per pixel dragged.
+    pub rotate_speed: f32,
+    /// Radians turned per second while A or D is held.
+    pub key_rotate_speed: f32,
+    /// Fraction of the distance to the target covered per wheel notch.
+    pub zoom_speed: f32,
+    /// How fast, per second, the motion left after a drag dies out.
+    pub damping: f32,
+    cursor: na::Point2<f32>,
+    drag: Option<Drag>,
+    keys: Keys,
+    /// Yaw and pitch in radians, not applied to the camera yet.
+    orbit: na::Vector2<f32>,
+    /// Pixels panned, not applied to the camera yet.
+    pan: na::Vector2<f32>,
+    /// Wheel notches not played out yet, towards the target when positive.
+    zoom: f32,
+    /// Measured while dragging, kept for a while after the release.
+    orbit_velocity: na::Vector2<f32>,
+    pan_velocity: na::Vector2<f32>,
+    last_click: Option<(Instant, na::Point2<f32>)>,
+    pivot_request: Option<na::Point2<f32>>,
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        Self::new(0.005, 0.1)
+    }
+}
+
+impl OrbitController {
+    pub fn new(rotate_speed: f32, zoom_speed: f32) -> Self {
+        Self {
+            rotate_speed,
+            key_rotate_speed: 1.5,
+            zoom_speed,
+            damping: 6.0,
+            cursor: na::Point2::origin(),
+            drag: None,
+            keys: Keys::default(),
+            orbit: na::Vector2::zeros(),
+            pan: na::Vector2::zeros(),
+            zoom: 0.0,
+            orbit_velocity: na::Vector2::zeros(),
+            pan_velocity: na::Vector2::zeros(),
+            last_click: None,
+            pivot_request: None,
+        }
+    }
+
+    /// Forgets the keys and buttons held and the motion not applied yet.
+    pub fn reset(&mut self) {
+        *self = Self {
+            key_rotate_speed: self.key_rotate_speed,
+            damping: self.damping,
+            cursor: self.cursor,
+            ..Self::new(self.rotate_speed, self.zoom_speed)
+        };
+    }
+
+    pub fn process_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_moved(na::Point2::new(position.x as f32, position.y as f32))
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.mouse_input(*button, state.is_pressed(), Instant::now())
+            }
+            WindowEvent::MouseWheel { delta, .. } => self.scroll(match delta {
+                MouseScrollDelta::LineDelta(_, lines) => *lines,
+                MouseScrollDelta::PixelDelta(pixels) => pixels.y as f32 / PIXELS_PER_NOTCH,
+            }),
+            WindowEvent::KeyboardInput { event, .. } => self.process_key(event),
+            // The releases go to another window.
+            WindowEvent::Focused(false) => self.reset(),
+            _ => {}
+        }
+    }
+
+    pub fn process_key(&mut self, key_event: &KeyEvent) {
+        let KeyEvent {
+            state,
+            physical_key: PhysicalKey::Code(key_code),
+            ..
+        } = key_event
+        else {
+            return;
+        };
+        let pressed = state.is_pressed();
+        match key_code {
+            KeyCode::KeyW => self.keys.forward = pressed,
+            KeyCode::KeyS => self.keys.backward = pressed,
+            KeyCode::KeyA => self.keys.left = pressed,
+            KeyCode::KeyD => self.keys.right = pressed,
+            _ => {}
+        }
+    }
+
+    /// `position` in pixels from the top left corner of the window.
+    pub fn cursor_moved(&mut self, position: na::Point2<f32>) {
+        let delta = position - self.cursor;
+        self.cursor = position;
+        match self.drag {
+            Some(Drag::Orbit) => self.orbit += delta * self.rotate_speed,
+            Some(Drag::Pan) => self.pan += delta,
+            None => {}
+        }
+    }
+
+    pub fn mouse_input(&mut self, button: MouseButton, pressed: bool, now: Instant) {
+        let drag = match button {
+            MouseButton::Left => Drag::Orbit,
+            MouseButton::Middle => Drag::Pan,
+            _ => return,
+        };
+        if !pressed {
+            if self.drag == Some(drag) {
+                self.drag = None;
+            }
+            return;
+        }
+
+        if drag == Drag::Orbit {
+            let double_click = self.last_click.take().is_some_and(|(time, position)| {
+                now.duration_since(time) <= DOUBLE_CLICK_TIME
+                    && (self.cursor - position).norm() <= DOUBLE_CLICK_PIXELS
+            });
+            if double_click {
+                self.pivot_request = Some(self.cursor);
+                return;
+            }
+            self.last_click = Some((now, self.cursor));
+        }
+        // Grabbing the view stops what is left of the last drag.
+        self.drag = Some(drag);
+        self.orbit_velocity = na::Vector2::zeros();
+        self.pan_velocity = na::Vector2::zeros();
+    }
+
+    /// `notches` of the wheel, towards the target when positive.
+    pub fn scroll(&mut self, notches: f32) {
+        self.zoom += notches;
+    }
+
+    /// Cursor position of the last double click, to move the target to the
+    /// surface under it with [`OrbitController::set_pivot`].
+    pub fn take_pivot_request(&mut self) -> Option<na::Point2<f32>> {
+        self.pivot_request.take()
+    }
+
+    /// Turns the camera towards `pivot` and orbits around it from now on.
+    pub fn set_pivot(&mut self, camera: &mut StaticCamera, pivot: na::Point3<f32>) {
+        if (pivot - camera.position).norm() > f32::EPSILON {
+            camera.target = pivot;
+        }
+        self.orbit_velocity = na::Vector2::zeros();
+        self.pan_velocity = na::Vector2::zeros();
+    }
+
+    /// Applies the input since the last update, `dt` seconds ago, for a view
+    /// of `viewport` pixels.
+    pub fn update_camera(
+        &mut self,
+        camera: &mut StaticCamera,
+        projection: &Projection,
+        viewport: na::Vector2<f32>,
+        dt: f32,
+    ) {
+        let dt = dt.max(0.0);
+        let mut orbit = std::mem::replace(&mut self.orbit, na::Vector2::zeros());
+        let mut pan = std::mem::replace(&mut self.pan, na::Vector2::zeros());
+        if self.drag.is_some() {
+            if dt > 0.0 {
+                let follow = 1.0 - (-VELOCITY_RATE * dt).exp();
+                self.orbit_velocity += (orbit / dt - self.orbit_velocity) * follow;
+                self.pan_velocity += (pan / dt - self.pan_velocity) * follow;
+            }
+        } else {
+            // Exact integral of the decaying velocity, the same motion
+            // whatever the frame rate.
+            let decay = (-self.damping * dt).exp();
+            let glide = if self.damping > 0.0 {
+                (1.0 - decay) / self.damping
+            } else {
+                dt
+            };
+            orbit += self.orbit_velocity * glide;
+            pan += self.pan_velocity * glide;
+            self.orbit_velocity *= decay;
+            self.pan_velocity *= decay;
+        }
+
+        let key_axis =
+            |positive: bool, negative: bool| positive as i8 as f32 - negative as i8 as f32;
+        orbit.x += key_axis(self.keys.right, self.keys.left) * self.key_rotate_speed * dt;
+        self.zoom += key_axis(self.keys.forward, self.keys.backward) * KEY_NOTCHES_PER_SECOND * dt;
+
+        let notches = if self.zoom.abs() < 1e-4 {
+            std::mem::take(&mut self.zoom)
+        } else {
+            self.zoom * (1.0 - (-ZOOM_RATE * dt).exp())
+        };
+        self.zoom -= notches;
+
+        orbit_camera(camera, orbit.x, orbit.y);
+        if viewport.x > 0.0 && viewport.y > 0.0 {
+            pan_camera(camera, projection, viewport, pan);
+            let scale = (1.0 - self.zoom_speed).powf(notches);
+            dolly_camera(camera, projection, viewport, self.cursor, scale);
+        }
+    }
+}
+
+/// Turns the camera around its target, `yaw` radians about the up vector
+/// and `pitch` radians towards it, without going over the poles.
+fn orbit_camera(camera: &mut StaticCamera, yaw: f32, pitch: f32) {
+    let Some(up) = na::Unit::try_new(camera.up, f32::EPSILON) else {
+        return;
+    };
+    let offset = camera.position - camera.target;
+    let polar = up.angle(&offset);
+    let delta_polar = (polar - pitch).clamp(MIN_POLAR, PI - MIN_POLAR) - polar;
+
+    let offset = match na::Unit::try_new(up.cross(&offset), f32::EPSILON) {
+        Some(axis) => na::UnitQuaternion::from_axis_angle(&axis, delta_polar) * offset,
+        None => offset,
+    };
+    let offset = na::UnitQuaternion::from_axis_angle(&up, -yaw) * offset;
+    camera.position = camera.target + offset;
+}
+
+/// Moves the camera and its target across the view, by as many `pixels` as
+/// the scene moves under the cursor at the depth of the target.
+fn pan_camera(
+    camera: &mut StaticCamera,
+    projection: &Projection,
+    viewport: na::Vector2<f32>,
+    pixels: na::Vector2<f32>,
+) {
+    let forward = camera.target - camera.position;
+    let world_per_pixel = projection.height_at(forward.norm()) / viewport.y;
+    let Some(right) = forward.cross(&camera.up).try_normalize(f32::EPSILON) else {
+        return;
+    };
+    let up = right.cross(&forward).normalize();
+
+    let shift = (up * pixels.y - right * pixels.x) * world_per_pixel;
+    camera.position += shift;
+    camera.target += shift;
+}
+
+/// Scales the distances of the camera and its target to the point under
+/// `cursor`, at the depth of the target, by `scale`. That point stays
+/// under the cursor.
+fn dolly_camera(
+    camera: &mut StaticCamera,
+    projection: &Projection,
+    viewport: na::Vector2<f32>,
+    cursor: na::Point2<f32>,
+    scale: f32,
+) {
+    if scale == 1.0 {
+        return;
+    }
+    let forward = camera.target - camera.position;
+    let ray = projection.ray(&camera.build_view_matrix(), cursor_to_ndc(cursor, viewport));
+    let along = ray.direction.dot(&forward.normalize());
+    if along <= f32::EPSILON {
+        return;
+    }
+
+    let focus = ray.at(forward.norm() / along);
+    camera.position = focus + (camera.position - focus) * scale;
+    camera.target = focus + (camera.target - focus) * scale;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const VIEWPORT: na::Vector2<f32> = na::Vector2::new(800.0, 600.0);
+
+    fn camera() -> StaticCamera {
+        StaticCamera {
+            position: na::Point3::new(0.0, 0.0, 5.0),
+            target: na::Point3::origin(),
+            up: na::Vector3::y(),
+        }
+    }
+
+    fn projection() -> Projection {
+        Projection::with_aspect(VIEWPORT.x, VIEWPORT.y)
+    }
+
+    fn screen_position(camera: &StaticCamera, point: na::Point3<f32>) -> na::Point2<f32> {
+        let clip =
+            projection().build_matrix() * camera.build_view_matrix() * point.to_homogeneous();
+        let ndc = clip.xy() / clip.w;
+        na::Point2::new(
+            (ndc.x + 1.0) / 2.0 * VIEWPORT.x,
+            (1.0 - ndc.y) / 2.0 * VIEWPORT.y,
+        )
+    }
+
+    #[test]
+    fn orbit_keeps_the_distance_and_stops_at_the_poles() {
+        let mut camera = camera();
+        orbit_camera(&mut camera, PI / 2.0, 0.0);
+        assert!((camera.position - na::Point3::new(-5.0, 0.0, 0.0)).norm() < 1e-4);
+
+        orbit_camera(&mut camera, 0.3, 10.0);
+        assert!(((camera.position - camera.target).norm() - 5.0).abs() < 1e-4);
+        let polar = camera.up.angle(&(camera.position - camera.target));
+        assert!((polar - MIN_POLAR).abs() < 1e-4);
+    }
+
+    #[test]
+    fn pan_moves_the_target_with_the_cursor() {
+        let mut camera = camera();
+        let before = screen_position(&camera, na::Point3::origin());
+        pan_camera(
+            &mut camera,
+            &projection(),
+            VIEWPORT,
+            na::Vector2::new(40.0, -25.0),
+        );
+        let after = screen_position(&camera, na::Point3::origin());
+        assert!((after - before - na::Vector2::new(40.0, -25.0)).norm() < 0.1);
+        assert!((camera.target - camera.position - na::Vector3::new(0.0, 0.0, -5.0)).norm() < 1e-4);
+    }
+
+    #[test]
+    fn dolly_keeps_the_point_under_the_cursor() {
+        let mut camera = camera();
+        let cursor = na::Point2::new(600.0, 200.0);
+        let ray = projection().ray(&camera.build_view_matrix(), cursor_to_ndc(cursor, VIEWPORT));
+        let focus = ray.at(5.0 / -ray.direction.z);
+
+        dolly_camera(&mut camera, &projection(), VIEWPORT, cursor, 0.5);
+        assert!((screen_position(&camera, focus) - cursor).norm() < 0.1);
+        assert!(((camera.target - camera.position).norm() - 2.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn drag_glides_the_same_at_any_frame_rate() {
+        let glide = |frames: usize| {
+            let mut controller = OrbitController::default();
+            let mut camera = camera();
+            controller.mouse_input(MouseButton::Left, true, Instant::now());
+            for i in 1..=30 {
+                controller.cursor_moved(na::Point2::new(i as f32 * 2.0, 0.0));
+                controller.update_camera(&mut camera, &projection(), VIEWPORT, 1.0 / 60.0);
+            }
+            controller.mouse_input(MouseButton::Left, false, Instant::now());
+            let released = camera.position;
+            for _ in 0..frames {
+                controller.update_camera(&mut camera, &projection(), VIEWPORT, 2.0 / frames as f32);
+            }
+            (released, camera.position)
+        };
+
+        let (released, slow) = glide(20);
+        let (_, fast) = glide(240);
+        assert!(
+            (released - slow).norm() > 0.1,
+            "keeps turning after the release"
+        );
+        assert!((slow - fast).norm() < 1e-3);
+    }
+
+    #[test]
+    fn double_click_requests_a_pivot() {
+        let mut controller = OrbitController::default();
+        let now = Instant::now();
+        controller.cursor_moved(na::Point2::new(100.0, 50.0));
+        controller.mouse_input(MouseButton::Left, true, now);
+        controller.mouse_input(MouseButton::Left, false, now);
+        assert_eq!(controller.take_pivot_request(), None);
+
+        controller.mouse_input(MouseButton::Left, true, now + Duration::from_millis(150));
+        assert_eq!(
+            controller.take_pivot_request(),
+            Some(na::Point2::new(100.0, 50.0))
+        );
+        assert_eq!(controller.drag, None);
+
+        // Too slow for a double click.
+        controller.mouse_input(MouseButton::Left, false, now);
+        controller.mouse_input(MouseButton::Left, true, now + Duration::from_secs(1));
+        controller.mouse_input(MouseButton::Left, false, now + Duration::from_secs(1));
+        controller.mouse_input(MouseButton::Left, true, now + Duration::from_secs(2));
+        assert_eq!(controller.take_pivot_request(), None);
+    }
+}
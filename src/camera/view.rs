@@ -0,0 +1,213 @@
+use crate::io::fs::stats::Bounds;
+
+use super::{Projection, StaticCamera};
+
+/// Side the scene is looked at from, with +y up and +z towards the front.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraView {
+    Front,
+    Back,
+    Left,
+    Right,
+    Top,
+    Bottom,
+    Iso,
+}
+
+impl CameraView {
+    pub const ALL: [CameraView; 7] = [
+        Self::Front,
+        Self::Back,
+        Self::Left,
+        Self::Right,
+        Self::Top,
+        Self::Bottom,
+        Self::Iso,
+    ];
+
+    /// From the target towards the camera, and the up vector.
+    pub fn direction(self) -> (na::Vector3<f32>, na::Vector3<f32>) {
+        let y = na::Vector3::y();
+        match self {
+            Self::Front => (na::Vector3::z(), y),
+            Self::Back => (-na::Vector3::z(), y),
+            Self::Left => (-na::Vector3::x(), y),
+            Self::Right => (na::Vector3::x(), y),
+            Self::Top => (y, -na::Vector3::z()),
+            Self::Bottom => (-y, na::Vector3::z()),
+            Self::Iso => (na::Vector3::new(1.0, 1.0, 1.0).normalize(), y),
+        }
+    }
+
+    /// `camera` turned around its target to look from this side.
+    pub fn orient(self, camera: &StaticCamera) -> StaticCamera {
+        let (direction, up) = self.direction();
+        let distance = (camera.position - camera.target).norm();
+        StaticCamera {
+            position: camera.target + direction * distance,
+            target: camera.target,
+            up,
+        }
+    }
+
+    /// Camera looking at `bounds` from this side, far enough for its bounding
+    /// sphere to fit in `projection`.
+    pub fn frame(self, bounds: &Bounds, projection: &Projection) -> StaticCamera {
+        let (direction, up) = self.direction();
+        let target = bounds.center();
+        StaticCamera {
+            position: target + direction * projection.fit_distance(bounds.radius()),
+            target,
+            up,
+        }
+    }
+}
+
+impl std::fmt::Display for CameraView {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Front => "Front",
+            Self::Back => "Back",
+            Self::Left => "Left",
+            Self::Right => "Right",
+            Self::Top => "Top",
+            Self::Bottom => "Bottom",
+            Self::Iso => "Iso",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Target, distance to it and orientation of a camera, the parts of a view
+/// that are interpolated.
+#[derive(Clone, Copy, Debug)]
+struct Pose {
+    target: na::Point3<f32>,
+    distance: f32,
+    /// From camera space, looking along -z with +y up, to world space.
+    orientation: na::UnitQuaternion<f32>,
+}
+
+impl Pose {
+    fn new(camera: &StaticCamera) -> Self {
+        let back = camera.position - camera.target;
+        // Any up vector across the view will do when it is along it.
+        let up = [camera.up, na::Vector3::y(), na::Vector3::x()]
+            .into_iter()
+            .find(|up| back.cross(up).norm() > 1e-6 * back.norm())
+            .unwrap_or(camera.up);
+        Self {
+            target: camera.target,
+            distance: back.norm(),
+            orientation: na::UnitQuaternion::face_towards(&back, &up),
+        }
+    }
+
+    fn camera(&self) -> StaticCamera {
+        StaticCamera {
+            position: self.target + self.orientation * na::Vector3::z() * self.distance,
+            target: self.target,
+            up: self.orientation * na::Vector3::y(),
+        }
+    }
+
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            target: self.target.lerp(&other.target, t),
+            // Geometric so that zooming in and out look alike.
+            distance: if self.distance > 0.0 && other.distance > 0.0 {
+                self.distance * (other.distance / self.distance).powf(t)
+            } else {
+                self.distance + (other.distance - self.distance) * t
+            },
+            orientation: self
+                .orientation
+                .try_slerp(&other.orientation, t, 1e-6)
+                .unwrap_or(other.orientation),
+        }
+    }
+}
+
+/// Eased move of a camera from one view to another, turning the shortest
+/// way around.
+#[derive(Clone, Copy, Debug)]
+pub struct Transition {
+    from: Pose,
+    to: Pose,
+    /// Seconds.
+    elapsed: f32,
+    duration: f32,
+}
+
+impl Transition {
+    pub fn new(from: &StaticCamera, to: &StaticCamera, duration: f32) -> Self {
+        Self {
+            from: Pose::new(from),
+            to: Pose::new(to),
+            elapsed: 0.0,
+            duration,
+        }
+    }
+
+    /// Moves `camera` `dt` seconds further, returns whether it got there.
+    pub fn update(&mut self, camera: &mut StaticCamera, dt: f32) -> bool {
+        self.elapsed += dt.max(0.0);
+        let t = if self.duration > 0.0 {
+            (self.elapsed / self.duration).min(1.0)
+        } else {
+            1.0
+        };
+        let eased = t * t * (3.0 - 2.0 * t);
+        *camera = self.from.lerp(&self.to, eased).camera();
+        t >= 1.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::camera::ICamera;
+
+    #[test]
+    fn framed_bounds_fit_the_view() {
+        let bounds = Bounds {
+            min: na::Point3::new(-1.0, -2.0, -1.0),
+            max: na::Point3::new(3.0, 2.0, 1.0),
+        };
+        let projection = Projection::with_aspect(2.0, 1.0);
+        for view in CameraView::ALL {
+            let camera = view.frame(&bounds, &projection);
+            assert_eq!(camera.target, na::Point3::new(1.0, 0.0, 0.0));
+
+            let view_proj = projection.build_matrix() * camera.build_view_matrix();
+            for corner in bounds.corners() {
+                let clip = view_proj * corner.to_homogeneous();
+                assert!(clip.x.abs() <= clip.w && clip.y.abs() <= clip.w, "{view:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn transitions_end_at_the_view() {
+        let start = StaticCamera {
+            position: na::Point3::new(0.0, 0.0, 4.0),
+            target: na::Point3::origin(),
+            up: na::Vector3::y(),
+        };
+        for view in CameraView::ALL {
+            let end = view.orient(&start);
+            let mut camera = start;
+            let mut transition = Transition::new(&start, &end, 0.5);
+
+            assert!(!transition.update(&mut camera, 0.25));
+            assert!(((camera.position - camera.target).norm() - 4.0).abs() < 1e-4);
+            assert!(camera.position.iter().all(|v| v.is_finite()), "{view:?}");
+
+            assert!(transition.update(&mut camera, 0.25));
+            assert!(
+                (camera.build_view_matrix() - end.build_view_matrix()).norm() < 1e-4,
+                "{view:?}"
+            );
+        }
+    }
+}
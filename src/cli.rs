@@ -0,0 +1,253 @@
+//! Command line of the `void` binary: files to open in the viewer, or a
+//! subcommand that runs without a window.
+
+use std::path::{Path, PathBuf};
+
+use clap::{builder::PossibleValue, Args, Parser, Subcommand, ValueEnum};
+
+use crate::{
+    camera::{CameraView, Projection, StaticCamera},
+    headless::HeadlessRenderer,
+    io::fs::{export, stats::MeshStats, weld::WeldOptions},
+    resource::{self, LoadOptions},
+};
+
+#[derive(Debug, Parser)]
+#[command(name = "void", version, about = "Viewer for 3D models")]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    /// Mesh files or folders opened in the viewer at startup.
+    pub files: Vec<PathBuf>,
+    #[command(flatten)]
+    pub load: LoadArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Processing of the loaded meshes, for the viewer and every subcommand.
+#[derive(Debug, Args)]
+pub struct LoadArgs {
+    /// Keeps the vertices as stored in the files instead of welding them.
+    #[arg(long, global = true)]
+    pub no_weld: bool,
+    /// Vertices closer than this are welded.
+    #[arg(long, global = true, default_value_t = WeldOptions::default().epsilon)]
+    pub weld_epsilon: f32,
+    /// Welded faces meeting at a larger angle, in degrees, keep a hard edge.
+    #[arg(long, global = true, default_value_t = default_crease_angle())]
+    pub crease_angle: f32,
+}
+
+/// That of [`WeldOptions::default`] in degrees, converted in `f64` to show
+/// the whole degrees it was given in.
+fn default_crease_angle() -> f32 {
+    (WeldOptions::default().crease_angle as f64).to_degrees() as f32
+}
+
+impl LoadArgs {
+    pub fn options(&self) -> LoadOptions {
+        LoadOptions {
+            weld: (!self.no_weld).then_some(WeldOptions {
+                epsilon: self.weld_epsilon,
+                crease_angle: self.crease_angle.to_radians(),
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Renders models offscreen, to EXR for the linear HDR frame.
+    Render {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// PNG or JPEG for the tone mapped frame, EXR for the HDR one.
+        #[arg(short, long)]
+        out: PathBuf,
+        /// Resolution, as WIDTHxHEIGHT.
+        #[arg(long, default_value = "512x512", value_parser = parse_size)]
+        size: (u32, u32),
+        /// Side the models are looked at from, framed to fit.
+        #[arg(long, value_enum, default_value_t = CameraView::Iso)]
+        camera: CameraView,
+    },
+    /// Converts a mesh file to the format given by the extension of `output`.
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+        /// Writes STL files as ASCII instead of binary.
+        #[arg(long)]
+        ascii: bool,
+    },
+    /// Prints triangle count, bounds and watertightness of mesh files.
+    Info {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
+impl ValueEnum for CameraView {
+    fn value_variants<'a>() -> &'a [Self] {
+        &Self::ALL
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        let name = match self {
+            Self::Front => "front",
+            Self::Back => "back",
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::Top => "top",
+            Self::Bottom => "bottom",
+            Self::Iso => "iso",
+        };
+        Some(PossibleValue::new(name))
+    }
+}
+
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let parse = |value: &str| match value.trim().parse::<u32>() {
+        Ok(0) | Err(_) => Err(format!("invalid size `{size}`, expected WIDTHxHEIGHT")),
+        Ok(value) => Ok(value),
+    };
+    let (width, height) = size
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("invalid size `{size}`, expected WIDTHxHEIGHT"))?;
+    Ok((parse(width)?, parse(height)?))
+}
+
+/// Runs a subcommand, none of them needs a window.
+pub async fn run(command: Command, options: LoadOptions) -> anyhow::Result<()> {
+    match command {
+        Command::Render {
+            files,
+            out,
+            size: (width, height),
+            camera,
+        } => render(&files, &out, width, height, camera, options).await,
+        Command::Convert {
+            input,
+            output,
+            ascii,
+        } => convert(&input, &output, ascii, options),
+        Command::Info { files } => {
+            for file in files {
+                let (meshes, _) = resource::load_world_meshes(file.clone(), options)?;
+                println!("{}", file.display());
+                for line in MeshStats::new(&meshes).to_string().lines() {
+                    println!("  {line}");
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn render(
+    files: &[PathBuf],
+    out: &Path,
+    width: u32,
+    height: u32,
+    camera: CameraView,
+    options: LoadOptions,
+) -> anyhow::Result<()> {
+    let mut renderer = HeadlessRenderer::new(width, height).await?;
+    renderer.set_load_options(options);
+    for file in files {
+        renderer.load_model(file).await?;
+    }
+
+    let bounds = renderer
+        .bounds()
+        .ok_or_else(|| anyhow::anyhow!("Nothing to render"))?;
+    let projection = Projection::with_aspect(width as f32, height as f32);
+    let StaticCamera {
+        position,
+        target,
+        up,
+    } = camera.frame(&bounds, &projection);
+    renderer.set_camera(position, target, up);
+
+    renderer.render_to_file(out, width, height)?;
+    log::info!("Rendered {}", out.display());
+    Ok(())
+}
+
+fn convert(input: &Path, output: &Path, ascii: bool, options: LoadOptions) -> anyhow::Result<()> {
+    // Fails before loading when the extension is not supported.
+    let format = export::ExportFormat::from_path(output, ascii)?;
+    let (meshes, materials) = resource::load_world_meshes(input.to_path_buf(), options)?;
+    export::export_meshes(output, format, meshes, materials)?;
+    log::info!("Converted {} to {}", input.display(), output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sizes_parse() {
+        assert_eq!(parse_size("512x256"), Ok((512, 256)));
+        assert_eq!(parse_size("64X64"), Ok((64, 64)));
+        assert!(parse_size("512").is_err());
+        assert!(parse_size("0x512").is_err());
+        assert!(parse_size("ax512").is_err());
+    }
+
+    #[test]
+    fn files_or_subcommand() {
+        let cli = Cli::try_parse_from(["void", "a.stl", "b.obj"]).unwrap();
+        assert_eq!(cli.files.len(), 2);
+        assert!(cli.command.is_none());
+
+        let cli = Cli::try_parse_from([
+            "void",
+            "render",
+            "model.stl",
+            "--out",
+            "thumb.png",
+            "--size",
+            "128x64",
+            "--camera",
+            "top",
+        ])
+        .unwrap();
+        let Some(Command::Render {
+            files,
+            size,
+            camera,
+            ..
+        }) = cli.command
+        else {
+            panic!("render subcommand");
+        };
+        assert_eq!(files, [PathBuf::from("model.stl")]);
+        assert_eq!(size, (128, 64));
+        assert_eq!(camera, CameraView::Top);
+
+        assert!(Cli::try_parse_from(["void", "render", "model.stl"]).is_err());
+
+        let cli = Cli::try_parse_from(["void", "convert", "in.obj", "out.stl", "--ascii"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Convert { ascii: true, .. })
+        ));
+    }
+
+    #[test]
+    fn weld_options_apply_everywhere() {
+        let cli = Cli::try_parse_from(["void", "model.stl", "--crease-angle", "90"]).unwrap();
+        let weld = cli.load.options().weld.unwrap();
+        assert_eq!(weld.crease_angle, 90f32.to_radians());
+        assert_eq!(weld.epsilon, WeldOptions::default().epsilon);
+
+        let cli = Cli::try_parse_from(["void", "model.stl"]).unwrap();
+        let weld = cli.load.options().weld.unwrap();
+        assert_eq!(weld.crease_angle, WeldOptions::default().crease_angle);
+
+        let cli = Cli::try_parse_from(["void", "info", "model.stl", "--no-weld"]).unwrap();
+        assert!(cli.load.options().weld.is_none());
+    }
+}
@@ -31,9 +31,13 @@ impl<T> DB<T> {
     pub fn get_all<'a>(&'a self) -> impl Iterator<Item = &'a T> {
         self.data.values()
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Id, &T)> {
+        self.data.iter().map(|(id, val)| (*id, val))
+    }
 }
 
-#[derive(Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Hash, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Id(pub usize);
 
 impl Display for Id {
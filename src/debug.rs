@@ -0,0 +1,410 @@
+use std::fmt::Display;
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::Projection,
+    gpu::Gpu,
+    id_buffer::{DrawIds, IdBuffer},
+    model::{self, InstanceRaw, Vertex},
+    texture, ModelEntry,
+};
+
+/// How the viewport shows the models.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DebugView {
+    #[default]
+    Shaded,
+    /// Shaded with the triangle edges drawn over.
+    Wireframe,
+    /// Flat color per face from its geometric normal.
+    FaceNormals,
+    /// Shaded with every vertex normal drawn as a line.
+    VertexNormals,
+    UvChecker,
+    Depth,
+    /// Faces seen from behind in red.
+    BackFaces,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 7] = [
+        Self::Shaded,
+        Self::Wireframe,
+        Self::FaceNormals,
+        Self::VertexNormals,
+        Self::UvChecker,
+        Self::Depth,
+        Self::BackFaces,
+    ];
+
+    /// Whether the view is drawn instead of the shaded models, without tone
+    /// mapping.
+    pub fn replaces_shading(self) -> bool {
+        matches!(
+            self,
+            Self::FaceNormals | Self::UvChecker | Self::Depth | Self::BackFaces
+        )
+    }
+}
+
+impl Display for DebugView {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Shaded => "Shaded",
+            Self::Wireframe => "Wireframe",
+            Self::FaceNormals => "Face normals",
+            Self::VertexNormals => "Vertex normals",
+            Self::UvChecker => "UV checker",
+            Self::Depth => "Depth",
+            Self::BackFaces => "Back faces",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Debug view of the viewport and its options.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebugSettings {
+    pub view: DebugView,
+    /// Width of the wireframe lines, in pixels.
+    pub wire_width: f32,
+    /// Length of the vertex normal lines, in world units.
+    pub normal_length: f32,
+}
+
+impl Default for DebugSettings {
+    fn default() -> Self {
+        Self {
+            view: DebugView::default(),
+            wire_width: 1.0,
+            normal_length: 0.05,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DebugUniform {
+    wire_color: [f32; 4],
+    wire_width: f32,
+    normal_length: f32,
+    near: f32,
+    far: f32,
+}
+
+/// Vertices and indices of a mesh read as storage buffers by `debug.wgsl`,
+/// see [`geometry_bind_group`].
+pub const GEOMETRY_BIND_GROUP_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor<'static> =
+    wgpu::BindGroupLayoutDescriptor {
+        label: Some("Debug Geometry Bind Group Layout"),
+        entries: &[geometry_entry(0), geometry_entry(1)],
+    };
+
+const fn geometry_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+/// Binds the buffers of a mesh for the debug views, `None` when they are too
+/// large to be bound as storage buffers.
+pub fn geometry_bind_group(
+    device: &wgpu::Device,
+    vertex_buffer: &wgpu::Buffer,
+    index_buffer: &wgpu::Buffer,
+) -> Option<wgpu::BindGroup> {
+    let max_size = device.limits().max_storage_buffer_binding_size as u64;
+    if vertex_buffer.size() > max_size || index_buffer.size() > max_size {
+        return None;
+    }
+    let layout = device.create_bind_group_layout(&GEOMETRY_BIND_GROUP_LAYOUT_DESCRIPTOR);
+    Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Debug Geometry Bind Group"),
+        layout: &layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: vertex_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: index_buffer.as_entire_binding(),
+            },
+        ],
+    }))
+}
+
+/// Pipelines of the debug views, rebuilt with the MSAA sample count.
+pub struct DebugPipelines {
+    wireframe: wgpu::RenderPipeline,
+    face_normals: wgpu::RenderPipeline,
+    vertex_normals: wgpu::RenderPipeline,
+    uv_checker: wgpu::RenderPipeline,
+    depth: wgpu::RenderPipeline,
+    back_faces: wgpu::RenderPipeline,
+    selection: wgpu::RenderPipeline,
+    hover: wgpu::RenderPipeline,
+}
+
+/// How [`DebugRenderer::draw_highlight`] marks a model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Highlight {
+    /// Under the cursor, a light tint.
+    Hovered,
+    /// Tinted and outlined.
+    Selected,
+}
+
+/// Draws the debug views over, or instead of, the shaded models.
+pub struct DebugRenderer {
+    pipeline_layout: wgpu::PipelineLayout,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    settings: DebugSettings,
+}
+
+impl DebugRenderer {
+    pub fn new(gpu: &Gpu, camera_layout: &wgpu::BindGroupLayout) -> Self {
+        let device = &gpu.device;
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Debug Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let geometry_layout =
+            device.create_bind_group_layout(&GEOMETRY_BIND_GROUP_LAYOUT_DESCRIPTOR);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &geometry_layout, &layout],
+            push_constant_ranges: &[],
+        });
+
+        let settings = DebugSettings::default();
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Debug Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[Self::uniform(&settings, 0.1, 100.0)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Debug Bind Group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            pipeline_layout,
+            uniform_buffer,
+            bind_group,
+            settings,
+        }
+    }
+
+    fn uniform(settings: &DebugSettings, near: f32, far: f32) -> DebugUniform {
+        DebugUniform {
+            wire_color: [0.0, 0.0, 0.0, 1.0],
+            wire_width: settings.wire_width,
+            normal_length: settings.normal_length,
+            near,
+            far,
+        }
+    }
+
+    pub fn create_pipelines(
+        &self,
+        gpu: &Gpu,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> DebugPipelines {
+        let device = &gpu.device;
+        let shader = device.create_shader_module(wgpu::include_wgsl!("debug.wgsl"));
+
+        let pipeline = |vs_entry, fs_entry, topology, cull_mode, overlay: bool| {
+            // Views replacing the shading are picked like it.
+            let ids = vs_entry == "vs_ids";
+            let buffers = [InstanceRaw::desc(), DrawIds::desc()];
+            let id_target = IdBuffer::color_target(sample_count, ids);
+            let has_ids = id_target.is_some();
+            let targets = [
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: overlay.then_some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                id_target,
+            ];
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(fs_entry),
+                layout: Some(&self.pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: vs_entry,
+                    buffers: if ids { &buffers[..] } else { &buffers[..1] },
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: fs_entry,
+                    // The ids have a pass of their own with MSAA.
+                    targets: if has_ids { &targets[..] } else { &targets[..1] },
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology,
+                    cull_mode,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: !overlay,
+                    depth_compare: texture::Texture::DEPTH_COMPARE,
+                    stencil: wgpu::StencilState::default(),
+                    // Pulls the overlay in front of the shaded surface it lies
+                    // on, towards larger reversed depths.
+                    bias: if overlay {
+                        wgpu::DepthBiasState {
+                            constant: 2,
+                            slope_scale: 1.0,
+                            clamp: 0.0,
+                        }
+                    } else {
+                        wgpu::DepthBiasState::default()
+                    },
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    ..Default::default()
+                },
+                multiview: None,
+            })
+        };
+
+        let triangles = wgpu::PrimitiveTopology::TriangleList;
+        let back = Some(wgpu::Face::Back);
+        DebugPipelines {
+            wireframe: pipeline("vs_main", "fs_wireframe", triangles, None, true),
+            face_normals: pipeline("vs_ids", "fs_face_normals", triangles, back, false),
+            vertex_normals: pipeline(
+                "vs_normals",
+                "fs_normals",
+                wgpu::PrimitiveTopology::LineList,
+                None,
+                false,
+            ),
+            uv_checker: pipeline("vs_ids", "fs_uv_checker", triangles, back, false),
+            depth: pipeline("vs_ids", "fs_depth", triangles, back, false),
+            back_faces: pipeline("vs_ids", "fs_back_faces", triangles, None, false),
+            selection: pipeline("vs_main", "fs_selection", triangles, None, true),
+            hover: pipeline("vs_main", "fs_hover", triangles, None, true),
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        settings: &DebugSettings,
+        projection: &Projection,
+    ) {
+        self.settings = *settings;
+        let uniform = Self::uniform(settings, projection.positive_znear(), projection.zfar());
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Whether `mesh` is drawn by [`DebugRenderer::draw`] instead of shaded.
+    pub fn replaces(&self, mesh: &model::Mesh) -> bool {
+        self.settings.view.replaces_shading() && Self::drawable(mesh)
+    }
+
+    fn drawable(mesh: &model::Mesh) -> bool {
+        mesh.topology == wgpu::PrimitiveTopology::TriangleList && mesh.geometry_bind_group.is_some()
+    }
+
+    /// Marks `entry` as `highlight`, whatever the debug view.
+    pub fn draw_highlight<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        pipelines: &'a DebugPipelines,
+        entry: &'a ModelEntry,
+        highlight: Highlight,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(match highlight {
+            Highlight::Hovered => &pipelines.hover,
+            Highlight::Selected => &pipelines.selection,
+        });
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, entry.instance_buffer.slice(..));
+        for mesh in &entry.model.meshes {
+            if let (true, Some(geometry)) = (Self::drawable(mesh), &mesh.geometry_bind_group) {
+                render_pass.set_bind_group(1, geometry, &[]);
+                render_pass.draw(0..mesh.num_elements, 0..entry.instances.len() as u32);
+            }
+        }
+    }
+
+    /// Draws the current debug view of `models`, nothing for
+    /// [`DebugView::Shaded`]. Views replacing the shading write the ids of
+    /// [`IdBuffer::write_draws`] for the same `models`.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        pipelines: &'a DebugPipelines,
+        models: &[&'a ModelEntry],
+        ids: &'a IdBuffer,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        let pipeline = match self.settings.view {
+            DebugView::Shaded => return,
+            DebugView::Wireframe => &pipelines.wireframe,
+            DebugView::FaceNormals => &pipelines.face_normals,
+            DebugView::VertexNormals => &pipelines.vertex_normals,
+            DebugView::UvChecker => &pipelines.uv_checker,
+            DebugView::Depth => &pipelines.depth,
+            DebugView::BackFaces => &pipelines.back_faces,
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.bind_group, &[]);
+
+        let replaces_shading = self.settings.view.replaces_shading();
+        for (model, entry) in models.iter().enumerate() {
+            render_pass.set_vertex_buffer(0, entry.instance_buffer.slice(..));
+            let instances = 0..entry.instances.len() as u32;
+            for (index, mesh) in entry.model.meshes.iter().enumerate() {
+                let Some(geometry) = mesh.geometry_bind_group.as_ref() else {
+                    continue;
+                };
+                if !Self::drawable(mesh) {
+                    continue;
+                }
+                let vertices = match self.settings.view {
+                    DebugView::VertexNormals => 0..mesh.vertices.len() as u32 * 2,
+                    _ => 0..mesh.num_elements,
+                };
+                if replaces_shading {
+                    render_pass.set_vertex_buffer(1, ids.draw_ids(model, index));
+                }
+                render_pass.set_bind_group(1, geometry, &[]);
+                render_pass.draw(vertices, instances.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+// Debug views of the geometry. Vertices are read from storage buffers by
+// index so that every triangle gets barycentric coordinates without
+// `NON_FILL_POLYGON_MODE`.
+
+// Must match `camera::CameraUniform`.
+struct CameraUniform {
+    view_pos: vec4<f32>,
+    view: mat4x4<f32>,
+    view_proj: mat4x4<f32>,
+    inv_proj: mat4x4<f32>,
+    inv_view: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// `model::ModelVertex` as floats: position, tex_coord, normal, color, tangent.
+const VERTEX_STRIDE: u32 = 16u;
+
+@group(1) @binding(0)
+var<storage, read> vertices: array<f32>;
+@group(1) @binding(1)
+var<storage, read> indices: array<u32>;
+
+// Must match `debug::DebugUniform`.
+struct Params {
+    wire_color: vec4<f32>,
+    // In pixels.
+    wire_width: f32,
+    // In world units.
+    normal_length: f32,
+    near: f32,
+    far: f32,
+}
+@group(2) @binding(0)
+var<uniform> params: Params;
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+
+    @location(9) normal_matrix_0: vec3<f32>,
+    @location(10) normal_matrix_1: vec3<f32>,
+    @location(11) normal_matrix_2: vec3<f32>,
+};
+
+struct Vertex {
+    position: vec3<f32>,
+    tex_coord: vec2<f32>,
+    normal: vec3<f32>,
+}
+
+fn load_vertex(index: u32) -> Vertex {
+    let i = index * VERTEX_STRIDE;
+    var v: Vertex;
+    v.position = vec3(vertices[i], vertices[i + 1u], vertices[i + 2u]);
+    v.tex_coord = vec2(vertices[i + 3u], vertices[i + 4u]);
+    v.normal = vec3(vertices[i + 5u], vertices[i + 6u], vertices[i + 7u]);
+    return v;
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) tex_coord: vec2<f32>,
+    @location(3) barycentric: vec3<f32>,
+    // Written by `vs_ids` only.
+    @location(4) @interpolate(flat) ids: vec4<u32>,
+}
+
+fn model_matrix(instance: InstanceInput) -> mat4x4<f32> {
+    return mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+}
+
+fn normal_matrix(instance: InstanceInput) -> mat3x3<f32> {
+    return mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+}
+
+// Drawn without index buffer, one invocation per index.
+fn vertex(vi: u32, instance: InstanceInput) -> VertexOutput {
+    let v = load_vertex(indices[vi]);
+    let world_position = model_matrix(instance) * vec4(v.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * world_position;
+    out.world_position = world_position.xyz;
+    out.world_normal = normal_matrix(instance) * v.normal;
+    out.tex_coord = v.tex_coord;
+    let corner = vi % 3u;
+    out.barycentric = vec3(f32(corner == 0u), f32(corner == 1u), f32(corner == 2u));
+    return out;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vi: u32, instance: InstanceInput) -> VertexOutput {
+    return vertex(vi, instance);
+}
+
+// Same as `vs_main` for the views replacing the shading, which write the ids
+// of `shader.wgsl`. Without index buffer the triangle is known.
+@vertex
+fn vs_ids(
+    @builtin(vertex_index) vi: u32,
+    @builtin(instance_index) instance_index: u32,
+    instance: InstanceInput,
+    @location(12) ids: vec2<u32>,
+) -> VertexOutput {
+    var out = vertex(vi, instance);
+    out.ids = vec4(ids, instance_index, vi / 3u);
+    return out;
+}
+
+struct IdOutput {
+    @location(0) color: vec4<f32>,
+    // Ignored with MSAA, see `id_buffer::IdBuffer::color_target`.
+    @location(1) ids: vec4<u32>,
+}
+
+// Shading independent of the lights, from a light at the camera.
+fn headlight(in: VertexOutput, normal: vec3<f32>) -> f32 {
+    let v = normalize(camera.view_pos.xyz - in.world_position);
+    return 0.2 + 0.8 * abs(dot(normalize(normal), v));
+}
+
+// Drawn over the shaded model.
+@fragment
+fn fs_wireframe(in: VertexOutput) -> @location(0) vec4<f32> {
+    let d = fwidth(in.barycentric);
+    let edge = smoothstep(vec3(0.0), d * params.wire_width, in.barycentric);
+    let coverage = 1.0 - min(min(edge.x, edge.y), edge.z);
+    if coverage <= 0.0 {
+        discard;
+    }
+    return vec4(params.wire_color.rgb, params.wire_color.a * coverage);
+}
+
+// Drawn over the selected model, in the linear scene colors.
+const SELECTION_COLOR: vec3<f32> = vec3(1.0, 0.45, 0.05);
+
+@fragment
+fn fs_selection(in: VertexOutput) -> @location(0) vec4<f32> {
+    let d = fwidth(in.barycentric);
+    let edge = smoothstep(vec3(0.0), d, in.barycentric);
+    let wire = 1.0 - min(min(edge.x, edge.y), edge.z);
+    return vec4(SELECTION_COLOR, mix(0.2, 0.6, wire));
+}
+
+// Drawn over the model under the cursor, lighter than the selection.
+@fragment
+fn fs_hover(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(SELECTION_COLOR, 0.12);
+}
+
+@fragment
+fn fs_face_normals(in: VertexOutput) -> IdOutput {
+    // Screen space y grows downwards.
+    let normal = normalize(cross(dpdy(in.world_position), dpdx(in.world_position)));
+    return IdOutput(vec4(normal * 0.5 + 0.5, 1.0), in.ids);
+}
+
+@fragment
+fn fs_uv_checker(in: VertexOutput) -> IdOutput {
+    let cell = vec2<i32>(floor(in.tex_coord * 8.0));
+    let checker = select(0.25, 1.0, (cell.x + cell.y) % 2 == 0);
+    // Tinted by the coordinates to tell apart the cells.
+    let tint = vec3(fract(in.tex_coord), 1.0);
+    let color = mix(vec3(checker), tint, 0.35) * headlight(in, in.world_normal);
+    return IdOutput(vec4(color, 1.0), in.ids);
+}
+
+@fragment
+fn fs_depth(in: VertexOutput) -> IdOutput {
+    let depth = -(camera.view * vec4(in.world_position, 1.0)).z;
+    // Logarithmic so that near and far details stay visible.
+    let t = log(max(depth, params.near) / params.near) / log(params.far / params.near);
+    return IdOutput(vec4(vec3(1.0 - saturate(t)), 1.0), in.ids);
+}
+
+@fragment
+fn fs_back_faces(in: VertexOutput, @builtin(front_facing) front: bool) -> IdOutput {
+    let shade = headlight(in, in.world_normal);
+    let color = select(vec3(0.9, 0.1, 0.1), vec3(0.8), front);
+    return IdOutput(vec4(color * shade, 1.0), in.ids);
+}
+
+struct LineOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+}
+
+// Two vertices per mesh vertex, from its position along its normal.
+@vertex
+fn vs_normals(@builtin(vertex_index) vi: u32, instance: InstanceInput) -> LineOutput {
+    let v = load_vertex(vi / 2u);
+    let normal = normalize(normal_matrix(instance) * v.normal);
+    var world_position = (model_matrix(instance) * vec4(v.position, 1.0)).xyz;
+    if vi % 2u == 1u {
+        world_position += normal * params.normal_length;
+    }
+
+    var out: LineOutput;
+    out.clip_position = camera.view_proj * vec4(world_position, 1.0);
+    out.color = normal * 0.5 + 0.5;
+    return out;
+}
+
+@fragment
+fn fs_normals(in: LineOutput) -> @location(0) vec4<f32> {
+    return vec4(in.color, 1.0);
+}
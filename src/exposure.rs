@@ -0,0 +1,217 @@
+use wgpu::util::DeviceExt;
+
+use crate::{gpu::Gpu, texture};
+
+const BINS: u64 = 256;
+const WORKGROUP_SIZE: u32 = 16;
+/// Log2 luminance range metered by the histogram.
+const MIN_LOG_LUMINANCE: f32 = -10.0;
+const MAX_LOG_LUMINANCE: f32 = 6.0;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ExposureUniform {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    adaptation: f32,
+    _padding: f32,
+}
+
+/// Adapted scene luminance, kept on the GPU between frames.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ExposureState {
+    luminance: f32,
+}
+
+/// Measures the average luminance of the HDR target with a histogram, for
+/// the tone mapping to expose for.
+pub struct AutoExposure {
+    histogram_pipeline: wgpu::ComputePipeline,
+    average_pipeline: wgpu::ComputePipeline,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    histogram: wgpu::Buffer,
+    state: wgpu::Buffer,
+    uniform_buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+}
+
+impl AutoExposure {
+    pub fn new(gpu: &Gpu, hdr: &texture::Texture) -> Self {
+        let device = &gpu.device;
+
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("AutoExposure::layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                storage_entry(1),
+                storage_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let histogram = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("AutoExposure::histogram"),
+            size: BINS * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        // A luminance of 0 makes the first measure apply without adaptation.
+        let state = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("AutoExposure::state"),
+            contents: bytemuck::cast_slice(&[ExposureState { luminance: 0.0 }]),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("AutoExposure::uniform"),
+            contents: bytemuck::cast_slice(&[ExposureUniform {
+                min_log_luminance: MIN_LOG_LUMINANCE,
+                log_luminance_range: MAX_LOG_LUMINANCE - MIN_LOG_LUMINANCE,
+                adaptation: 1.0,
+                _padding: 0.0,
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("exposure.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("AutoExposure::pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+        let histogram_pipeline = pipeline("compute_histogram");
+        let average_pipeline = pipeline("compute_average");
+
+        let bind_group =
+            Self::create_bind_group(device, &layout, hdr, &histogram, &state, &uniform_buffer);
+
+        Self {
+            histogram_pipeline,
+            average_pipeline,
+            layout,
+            bind_group,
+            histogram,
+            state,
+            uniform_buffer,
+            width: hdr.size.width,
+            height: hdr.size.height,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        hdr: &texture::Texture,
+        histogram: &wgpu::Buffer,
+        state: &wgpu::Buffer,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("AutoExposure::bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&hdr.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: histogram.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: state.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Meters `hdr` from now on, after it was recreated.
+    pub fn resize(&mut self, gpu: &Gpu, hdr: &texture::Texture) {
+        self.bind_group = Self::create_bind_group(
+            &gpu.device,
+            &self.layout,
+            hdr,
+            &self.histogram,
+            &self.state,
+            &self.uniform_buffer,
+        );
+        self.width = hdr.size.width;
+        self.height = hdr.size.height;
+    }
+
+    /// Adapts a fraction `1 - e^(-speed * dt)` of the way to the luminance
+    /// measured next, `dt` being in seconds.
+    pub fn update(&self, queue: &wgpu::Queue, speed: f32, dt: f32) {
+        let uniform = ExposureUniform {
+            min_log_luminance: MIN_LOG_LUMINANCE,
+            log_luminance_range: MAX_LOG_LUMINANCE - MIN_LOG_LUMINANCE,
+            adaptation: 1.0 - (-speed * dt).exp(),
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Buffer holding the adapted luminance as a single `f32`.
+    pub fn luminance_buffer(&self) -> &wgpu::Buffer {
+        &self.state
+    }
+
+    pub fn compute(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("AutoExposure::compute"),
+            timestamp_writes: None,
+        });
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_pipeline(&self.histogram_pipeline);
+        pass.dispatch_workgroups(
+            self.width.div_ceil(WORKGROUP_SIZE),
+            self.height.div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+        pass.set_pipeline(&self.average_pipeline);
+        pass.dispatch_workgroups(1, 1, 1);
+    }
+}
@@ -0,0 +1,94 @@
+// Auto exposure: a histogram of the log luminance of the scene, averaged and
+// adapted over time into the luminance the tone mapping exposes for.
+
+const BINS: u32 = 256u;
+
+// Must match `exposure::ExposureUniform`.
+struct Params {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    // Fraction of the way to the measured luminance covered this frame.
+    adaptation: f32,
+    _padding: f32,
+}
+
+// Must match `exposure::ExposureState`.
+struct State {
+    luminance: f32,
+}
+
+@group(0) @binding(0)
+var hdr_image: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read_write> histogram: array<atomic<u32>, BINS>;
+@group(0) @binding(2)
+var<storage, read_write> state: State;
+@group(0) @binding(3)
+var<uniform> params: Params;
+
+var<workgroup> local_bins: array<atomic<u32>, BINS>;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Bin 0 holds the pixels too dark to meter.
+fn bin(color: vec3<f32>) -> u32 {
+    let lum = luminance(color);
+    if lum < 1e-5 {
+        return 0u;
+    }
+    let t = saturate((log2(lum) - params.min_log_luminance) / params.log_luminance_range);
+    return u32(t * f32(BINS - 2u) + 1.0);
+}
+
+@compute @workgroup_size(16, 16)
+fn compute_histogram(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    atomicStore(&local_bins[index], 0u);
+    workgroupBarrier();
+
+    let size = textureDimensions(hdr_image);
+    if id.x < size.x && id.y < size.y {
+        let color = textureLoad(hdr_image, id.xy, 0).rgb;
+        atomicAdd(&local_bins[bin(color)], 1u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram[index], atomicLoad(&local_bins[index]));
+}
+
+var<workgroup> weighted: array<f32, BINS>;
+
+@compute @workgroup_size(256)
+fn compute_average(@builtin(local_invocation_index) index: u32) {
+    // Reset for the next frame while reading.
+    let count = atomicExchange(&histogram[index], 0u);
+    weighted[index] = f32(count) * f32(index);
+    workgroupBarrier();
+
+    for (var stride = BINS / 2u; stride > 0u; stride >>= 1u) {
+        if index < stride {
+            weighted[index] += weighted[index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if index == 0u {
+        let size = textureDimensions(hdr_image);
+        let metered = max(f32(size.x * size.y) - f32(count), 1.0);
+        // Mean bin of the metered pixels, back to a luminance.
+        let mean = max(weighted[0] / metered - 1.0, 0.0);
+        let log_luminance = mean / f32(BINS - 2u) * params.log_luminance_range + params.min_log_luminance;
+        let target_luminance = exp2(log_luminance);
+
+        let previous = state.luminance;
+        if previous > 0.0 {
+            state.luminance = previous + (target_luminance - previous) * params.adaptation;
+        } else {
+            state.luminance = target_luminance;
+        }
+    }
+}
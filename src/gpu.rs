@@ -9,12 +9,22 @@ use winit::window::Window;
 
 static CMD_ID: OnceLock<AtomicUsize> = OnceLock::new();
 
+/// Format of the target of a headless [`Gpu`], the surfaces are sRGB too.
+pub const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
 pub struct Gpu {
+    pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
-    pub surface: Arc<wgpu::Surface>,
+    /// `None` for a headless `Gpu`, see [`Gpu::headless`].
+    pub surface: Option<Arc<wgpu::Surface<'static>>>,
     pub config: Arc<RwLock<wgpu::SurfaceConfiguration>>,
     current_texture_view: RwLock<OnceCell<wgpu::SurfaceTexture>>,
+    /// Drawn to instead of the surface by a headless `Gpu`, sized by `config`.
+    offscreen: RwLock<Option<wgpu::Texture>>,
+    /// Sample count of the passes drawing to the surface and, with MSAA, the
+    /// multisampled texture they draw to, see [`Gpu::set_msaa_samples`].
+    msaa: RwLock<(u32, Option<wgpu::Texture>)>,
     cmds: RwLock<BTreeMap<usize, wgpu::CommandBuffer>>,
 }
 
@@ -29,7 +39,7 @@ impl Gpu {
             ..Default::default()
         });
 
-        let surface = Arc::new(unsafe { instance.create_surface(&window) }.unwrap());
+        let surface = Arc::new(instance.create_surface(Arc::clone(&window)).unwrap());
 
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
@@ -40,19 +50,7 @@ impl Gpu {
             .await
             .unwrap();
 
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    features: wgpu::Features::empty(),
-                    // WebGL doesn't support all of wgpu's features, so if
-                    // we're building for the web we'll have to disable some.
-                    limits: wgpu::Limits::default(),
-                },
-                None, // Trace path
-            )
-            .await
-            .unwrap();
+        let (device, queue) = request_device(&adapter).await.unwrap();
 
         let surface_caps = surface.get_capabilities(&adapter);
         // Shader code in this tutorial assumes an Srgb surface texture. Using a different
@@ -73,19 +71,105 @@ impl Gpu {
             present_mode: surface_caps.present_modes[0],
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
+            desired_maximum_frame_latency: 2,
         };
 
-        surface.configure(&device, &config);
+        let gpu = Self::from_parts(adapter, device, queue, Some(surface), config);
+        gpu.configure();
+        gpu
+    }
+
+    /// A `Gpu` without window nor surface, drawing to a `width` by `height`
+    /// texture of [`OFFSCREEN_FORMAT`] read back with [`Gpu::read_offscreen`].
+    /// Falls back to a software adapter when there is no GPU.
+    pub async fn headless(width: u32, height: u32) -> anyhow::Result<Self> {
+        anyhow::ensure!(width > 0 && height > 0, "Empty {width}x{height} target");
+        CMD_ID.get_or_init(|| AtomicUsize::new(0));
+
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+
+        let options = |force_fallback_adapter| wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter,
+        };
+        let adapter = match instance.request_adapter(&options(false)).await {
+            Some(adapter) => adapter,
+            None => instance
+                .request_adapter(&options(true))
+                .await
+                .ok_or_else(|| anyhow::anyhow!("No graphics adapter, not even a software one"))?,
+        };
+        log::info!("Headless rendering on {:?}", adapter.get_info());
 
-        let config = Arc::new(RwLock::new(config));
+        let (device, queue) = request_device(&adapter).await?;
 
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: OFFSCREEN_FORMAT,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+
+        let gpu = Self::from_parts(adapter, device, queue, None, config);
+        gpu.configure();
+        Ok(gpu)
+    }
+
+    fn from_parts(
+        adapter: wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        surface: Option<Arc<wgpu::Surface<'static>>>,
+        config: wgpu::SurfaceConfiguration,
+    ) -> Self {
         Self {
+            adapter,
             device,
             queue,
             surface,
             cmds: RwLock::new(BTreeMap::default()),
             current_texture_view: RwLock::new(OnceCell::new()),
-            config,
+            offscreen: RwLock::new(None),
+            msaa: RwLock::new((1, None)),
+            config: Arc::new(RwLock::new(config)),
+        }
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.surface.is_none()
+    }
+
+    /// Applies `config` to the surface, or recreates the offscreen target of
+    /// a headless `Gpu`.
+    pub fn configure(&self) {
+        let config = self.get_config();
+        match &self.surface {
+            Some(surface) => surface.configure(&self.device, &config),
+            None => {
+                let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("Offscreen Texture"),
+                    size: wgpu::Extent3d {
+                        width: config.width,
+                        height: config.height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: config.format,
+                    usage: config.usage,
+                    view_formats: &[],
+                });
+                *self.offscreen.write().unwrap() = Some(texture);
+            }
         }
     }
 
@@ -102,13 +186,78 @@ impl Gpu {
     }
 
     pub fn get_current_view(&self) -> TextureView {
+        let Some(surface) = &self.surface else {
+            let offscreen = self.offscreen.read().unwrap();
+            return offscreen
+                .as_ref()
+                .expect("Headless Gpu is configured on creation")
+                .create_view(&wgpu::TextureViewDescriptor::default());
+        };
         let surface_tex = self.current_texture_view.read().unwrap();
-        let surface_tex = surface_tex.get_or_init(|| self.surface.get_current_texture().unwrap());
+        let surface_tex = surface_tex.get_or_init(|| surface.get_current_texture().unwrap());
         surface_tex
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default())
     }
 
+    /// Largest sample count up to `samples` that every format in `formats`
+    /// supports.
+    pub fn clamp_msaa_samples(&self, samples: u32, formats: &[wgpu::TextureFormat]) -> u32 {
+        let device_features = self.device.features();
+        let supported = |count: u32| {
+            formats.iter().all(|&format| {
+                let features = if device_features
+                    .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+                {
+                    self.adapter.get_texture_format_features(format)
+                } else {
+                    format.guaranteed_format_features(device_features)
+                };
+                features.flags.sample_count_supported(count)
+            })
+        };
+        [8, 4, 2]
+            .into_iter()
+            .find(|&count| count <= samples && supported(count))
+            .unwrap_or(1)
+    }
+
+    pub fn msaa_samples(&self) -> u32 {
+        self.msaa.read().unwrap().0
+    }
+
+    /// Sets the sample count of the passes drawing to the surface and
+    /// (re)creates their multisampled texture, also needed after a resize.
+    pub fn set_msaa_samples(&self, samples: u32) {
+        let texture = (samples > 1).then(|| {
+            let config = self.get_config();
+            self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Surface MSAA Texture"),
+                size: wgpu::Extent3d {
+                    width: config.width,
+                    height: config.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: samples,
+                dimension: wgpu::TextureDimension::D2,
+                // Resolved into views of the surface texture.
+                format: config.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+        });
+        *self.msaa.write().unwrap() = (samples, texture);
+    }
+
+    /// View of the multisampled texture resolved into the surface, if MSAA is on.
+    pub fn get_msaa_view(&self) -> Option<TextureView> {
+        let msaa = self.msaa.read().unwrap();
+        msaa.1
+            .as_ref()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+    }
+
     pub fn submit_cmd(&self, cmd: wgpu::CommandBuffer) {
         let id = CMD_ID
             .get()
@@ -132,7 +281,116 @@ impl Gpu {
         let cmds = cmds.into_values().into_iter();
         self.queue.submit(cmds);
         let mut current_surface_tex = self.current_texture_view.write().unwrap();
-        let current_surface_tex = current_surface_tex.take().unwrap();
-        current_surface_tex.present();
+        // Nothing to present when headless.
+        if let Some(current_surface_tex) = current_surface_tex.take() {
+            current_surface_tex.present();
+        }
+    }
+
+    /// Reads back the offscreen target of a headless `Gpu` as tightly packed
+    /// sRGB RGBA rows, once the frame drawn to it was submitted by
+    /// [`Gpu::finish`].
+    pub fn read_offscreen(&self) -> anyhow::Result<Vec<u8>> {
+        let offscreen = self.offscreen.read().unwrap();
+        let texture = offscreen
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Only a headless Gpu has an offscreen target"))?;
+        self.read_texture(texture, 4)
+    }
+
+    /// Copies `texture`, which needs `COPY_SRC`, back to memory as tightly
+    /// packed rows of `bytes_per_pixel` wide texels. Blocks until the GPU is
+    /// done with it.
+    pub fn read_texture(
+        &self,
+        texture: &wgpu::Texture,
+        bytes_per_pixel: u32,
+    ) -> anyhow::Result<Vec<u8>> {
+        let width = texture.width();
+        let height = texture.height();
+        let row_size = width * bytes_per_pixel;
+        let padded_row_size = row_size.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: padded_row_size as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.create_cmd_encoder();
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        self.queue.submit([encoder.finish()]);
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let data = slice.get_mapped_range();
+        let pixels = data
+            .chunks_exact(padded_row_size as usize)
+            .flat_map(|row| &row[..row_size as usize])
+            .copied()
+            .collect();
+        drop(data);
+        buffer.unmap();
+        Ok(pixels)
+    }
+}
+
+async fn request_device(
+    adapter: &wgpu::Adapter,
+) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                // Sample counts other than 1 and 4 depend on the adapter, and
+                // picking tells the triangles apart with primitive indices.
+                required_features: adapter.features()
+                    & (wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                        | wgpu::Features::SHADER_PRIMITIVE_INDEX),
+                // WebGL doesn't support all of wgpu's features, so if
+                // we're building for the web we'll have to disable some.
+                required_limits: wgpu::Limits::default(),
+            },
+            None, // Trace path
+        )
+        .await
+}
+
+/// Color attachment drawing to the surface through `msaa_view`, see
+/// [`Gpu::get_msaa_view`], or directly when it is `None`.
+pub fn surface_attachment<'a>(
+    surface_view: &'a TextureView,
+    msaa_view: Option<&'a TextureView>,
+    load: wgpu::LoadOp<wgpu::Color>,
+) -> wgpu::RenderPassColorAttachment<'a> {
+    let (view, resolve_target) = match msaa_view {
+        Some(msaa_view) => (msaa_view, Some(surface_view)),
+        None => (surface_view, None),
+    };
+    wgpu::RenderPassColorAttachment {
+        view,
+        resolve_target,
+        ops: wgpu::Operations {
+            load,
+            store: wgpu::StoreOp::Store,
+        },
     }
 }
@@ -1,15 +1,158 @@
-use wgpu::{util::RenderEncoder, Operations};
+use std::{fmt::Display, time::Instant};
 
-use crate::{create_render_pipeline, gpu::Gpu, texture};
+use wgpu::util::{DeviceExt, RenderEncoder};
+
+use crate::{
+    bloom::Bloom,
+    create_render_pipeline,
+    exposure::AutoExposure,
+    gpu::{self, Gpu},
+    texture,
+};
+
+/// Multisample anti-aliasing of the scene and the GUI.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Msaa {
+    Off,
+    X2,
+    #[default]
+    X4,
+    X8,
+}
+
+impl Msaa {
+    pub const ALL: [Msaa; 4] = [Self::Off, Self::X2, Self::X4, Self::X8];
+
+    /// Requested sample count, the adapter may support fewer.
+    pub fn samples(self) -> u32 {
+        match self {
+            Self::Off => 1,
+            Self::X2 => 2,
+            Self::X4 => 4,
+            Self::X8 => 8,
+        }
+    }
+}
+
+impl Display for Msaa {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Off => write!(f, "Off"),
+            _ => write!(f, "{}x", self.samples()),
+        }
+    }
+}
+
+/// Operator mapping the exposed HDR image to the displayable range.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneMapping {
+    /// Clamps to the displayable range.
+    None,
+    Reinhard,
+    #[default]
+    Aces,
+    AgX,
+    /// John Hable's curve from Uncharted 2.
+    Filmic,
+}
+
+impl ToneMapping {
+    pub const ALL: [ToneMapping; 5] = [
+        Self::None,
+        Self::Reinhard,
+        Self::Aces,
+        Self::AgX,
+        Self::Filmic,
+    ];
+}
+
+impl Display for ToneMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::None => "None",
+            Self::Reinhard => "Reinhard",
+            Self::Aces => "ACES",
+            Self::AgX => "AgX",
+            Self::Filmic => "Filmic",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// User controls of the post processing applied by [`HdrPipeline::process`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PostSettings {
+    pub tone_mapping: ToneMapping,
+    /// Exposure compensation in stops, on top of auto exposure when enabled.
+    pub exposure: f32,
+    pub auto_exposure: bool,
+    /// How fast auto exposure adapts to a change of brightness, per second.
+    pub adaptation_speed: f32,
+    pub bloom: bool,
+    /// Fraction of the image replaced by its blurred version.
+    pub bloom_intensity: f32,
+    /// Spread of the bloom at every mip level, in texture coordinates.
+    pub bloom_radius: f32,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self {
+            tone_mapping: ToneMapping::default(),
+            exposure: 0.0,
+            auto_exposure: false,
+            adaptation_speed: 1.5,
+            bloom: true,
+            bloom_intensity: 0.04,
+            bloom_radius: 0.005,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostUniform {
+    exposure: f32,
+    auto_exposure: u32,
+    bloom_intensity: f32,
+    /// [`ToneMapping`] discriminant.
+    tone_mapping: u32,
+}
+
+impl From<&PostSettings> for PostUniform {
+    fn from(settings: &PostSettings) -> Self {
+        Self {
+            exposure: settings.exposure.exp2(),
+            auto_exposure: settings.auto_exposure as u32,
+            bloom_intensity: if settings.bloom {
+                settings.bloom_intensity
+            } else {
+                0.0
+            },
+            tone_mapping: settings.tone_mapping as u32,
+        }
+    }
+}
 
 pub struct HdrPipeline {
     pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
     bind_group: wgpu::BindGroup,
+    /// Resolved target of the scene, read by the tone mapping.
     texture: texture::Texture,
+    /// Multisampled target of the scene when MSAA is on.
+    msaa_view: Option<wgpu::TextureView>,
+    samples: u32,
     width: u32,
     height: u32,
     format: wgpu::TextureFormat,
     layout: wgpu::BindGroupLayout,
+    bloom: Bloom,
+    exposure: AutoExposure,
+    uniform_buffer: wgpu::Buffer,
+    settings: PostSettings,
+    /// Time of the last [`HdrPipeline::update`], auto exposure adapts with it.
+    last_update: Instant,
 }
 
 impl HdrPipeline {
@@ -24,13 +167,17 @@ impl HdrPipeline {
 
         let texture = texture::Texture::create_2d_texture(
             gpu,
-            width,
             height,
+            width,
             format,
-            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
-            wgpu::FilterMode::Nearest,
+            wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                // Read back by headless renders.
+                | wgpu::TextureUsages::COPY_SRC,
+            wgpu::FilterMode::Linear,
             Some("Hdr::texture"),
         );
+        drop(config);
 
         let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Hdr::layout"),
@@ -51,106 +198,270 @@ impl HdrPipeline {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
-            ],
-        });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Hdr::bind_group"),
-            layout: &layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
                 },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
             ],
         });
 
-        let shader = wgpu::include_wgsl!("hdr.wgsl");
+        let settings = PostSettings::default();
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Hdr::uniform"),
+            contents: bytemuck::cast_slice(&[PostUniform::from(&settings)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bloom = Bloom::new(gpu, &texture);
+        let exposure = AutoExposure::new(gpu, &texture);
+        let bind_group = Self::create_bind_group(
+            device,
+            &layout,
+            &texture,
+            &bloom,
+            &exposure,
+            &uniform_buffer,
+        );
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
             bind_group_layouts: &[&layout],
             push_constant_ranges: &[],
         });
-        let pipeline = create_render_pipeline(
-            gpu,
-            &pipeline_layout,
-            config.format.add_srgb_suffix(),
-            None,
-            &[],
-            wgpu::PrimitiveTopology::TriangleList,
-            shader,
-        );
+        let pipeline = Self::create_pipeline(gpu, &pipeline_layout, 1);
 
         Self {
             pipeline,
+            pipeline_layout,
             bind_group,
             texture,
+            msaa_view: None,
+            samples: 1,
             width,
             height,
             format,
             layout,
+            bloom,
+            exposure,
+            uniform_buffer,
+            settings,
+            last_update: Instant::now(),
         }
     }
 
-    pub fn resize(&mut self, gpu: &Gpu, width: u32, height: u32) {
-        let device = &gpu.device;
-
-        self.texture = texture::Texture::create_2d_texture(
-            gpu,
-            height,
-            width,
-            wgpu::TextureFormat::Rgba16Float,
-            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
-            wgpu::FilterMode::Nearest,
-            Some("Hdr::texture"),
-        );
-
-        self.bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        texture: &texture::Texture,
+        bloom: &Bloom,
+        exposure: &AutoExposure,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Hdr::bind_group"),
-            layout: &self.layout,
+            layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&self.texture.view),
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&self.texture.sampler),
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(bloom.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: exposure.luminance_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: uniform_buffer.as_entire_binding(),
                 },
             ],
-        });
+        })
+    }
+
+    /// Tone mapping to the surface, drawn with the sample count of
+    /// [`Gpu::msaa_samples`].
+    fn create_pipeline(
+        gpu: &Gpu,
+        layout: &wgpu::PipelineLayout,
+        samples: u32,
+    ) -> wgpu::RenderPipeline {
+        let format = gpu.get_config_read(|config| config.format.add_srgb_suffix());
+        create_render_pipeline(
+            gpu,
+            layout,
+            format,
+            None,
+            &[],
+            wgpu::PrimitiveTopology::TriangleList,
+            None,
+            samples,
+            wgpu::include_wgsl!("hdr.wgsl"),
+            "fs_main",
+            None,
+        )
+    }
+
+    fn create_msaa_view(&self, gpu: &Gpu) -> Option<wgpu::TextureView> {
+        (self.samples > 1).then(|| {
+            gpu.device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some("Hdr::msaa_texture"),
+                    size: wgpu::Extent3d {
+                        width: self.width,
+                        height: self.height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: self.samples,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: self.format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        })
+    }
+
+    /// Recreates the multisampled target and the tone mapping pipeline for
+    /// `samples`, 1 turns MSAA off.
+    pub fn set_msaa_samples(&mut self, gpu: &Gpu, samples: u32) {
+        self.samples = samples;
+        self.msaa_view = self.create_msaa_view(gpu);
+        self.pipeline = Self::create_pipeline(gpu, &self.pipeline_layout, samples);
+    }
+
+    pub fn resize(&mut self, gpu: &Gpu, width: u32, height: u32) {
+        self.texture = texture::Texture::create_2d_texture(
+            gpu,
+            height,
+            width,
+            wgpu::TextureFormat::Rgba16Float,
+            wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                // Read back by headless renders.
+                | wgpu::TextureUsages::COPY_SRC,
+            wgpu::FilterMode::Linear,
+            Some("Hdr::texture"),
+        );
+        self.bloom.resize(gpu, &self.texture);
+        self.exposure.resize(gpu, &self.texture);
+        self.bind_group = Self::create_bind_group(
+            &gpu.device,
+            &self.layout,
+            &self.texture,
+            &self.bloom,
+            &self.exposure,
+            &self.uniform_buffer,
+        );
 
         self.width = width;
         self.height = height;
+        self.msaa_view = self.create_msaa_view(gpu);
+    }
+
+    /// Applies `settings` to the next [`HdrPipeline::process`].
+    pub fn update(&mut self, queue: &wgpu::Queue, settings: &PostSettings) {
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_update).as_secs_f32();
+        self.last_update = now;
+
+        self.settings = *settings;
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[PostUniform::from(settings)]),
+        );
+        self.bloom.update(queue, settings.bloom_radius);
+        self.exposure.update(queue, settings.adaptation_speed, dt);
     }
 
-    pub fn view(&self) -> &wgpu::TextureView {
-        &self.texture.view
+    /// Attachment the scene is drawn to, resolved into the tone mapped
+    /// texture when MSAA is on.
+    pub fn color_attachment(
+        &self,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> wgpu::RenderPassColorAttachment<'_> {
+        let (view, resolve_target) = match &self.msaa_view {
+            Some(msaa_view) => (msaa_view, Some(&self.texture.view)),
+            None => (&self.texture.view, None),
+        };
+        wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        }
     }
 
     pub fn format(&self) -> wgpu::TextureFormat {
         self.format
     }
 
-    pub fn process(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+    /// The scene before post processing, resolved when MSAA is on.
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture.texture
+    }
+
+    /// Meters, blooms and tone maps the scene to `output`, through
+    /// `msaa_output` when MSAA is on, see [`Gpu::get_msaa_view`].
+    pub fn process(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        msaa_output: Option<&wgpu::TextureView>,
+    ) {
+        if self.settings.auto_exposure {
+            self.exposure.compute(encoder);
+        }
+        if self.settings.bloom {
+            self.bloom.render(encoder);
+        }
+
         let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Hdr::process"),
             occlusion_query_set: None,
             timestamp_writes: None,
             depth_stencil_attachment: None,
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &output,
-                resolve_target: None,
-                ops: Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
+            color_attachments: &[Some(gpu::surface_attachment(
+                output,
+                msaa_output,
+                wgpu::LoadOp::Load,
+            ))],
         });
 
         pass.set_pipeline(&self.pipeline);
@@ -18,6 +18,92 @@ fn aces_tone_map(hdr: vec3<f32>) -> vec3<f32> {
     return clamp(m2 * (a / b), vec3(0.0), vec3(1.0));
 }
 
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+fn reinhard_tone_map(hdr: vec3<f32>) -> vec3<f32> {
+    return hdr / (1.0 + luminance(hdr));
+}
+
+// John Hable's filmic curve from Uncharted 2
+fn hable(x: vec3<f32>) -> vec3<f32> {
+    let a = 0.15;
+    let b = 0.50;
+    let c = 0.10;
+    let d = 0.20;
+    let e = 0.02;
+    let f = 0.30;
+    return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
+fn filmic_tone_map(hdr: vec3<f32>) -> vec3<f32> {
+    let white = 11.2;
+    return hable(hdr * 2.0) / hable(vec3(white));
+}
+
+// Polynomial fit of the AgX base contrast curve
+// Based on https://iolite-engine.com/blog_posts/minimal_agx_implementation
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2
+        - 40.14 * x4 * x
+        + 31.96 * x4
+        - 6.868 * x2 * x
+        + 0.4298 * x2
+        + 0.1191 * x
+        - 0.00232;
+}
+
+fn agx_tone_map(hdr: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104,
+    );
+    let outset = mat3x3(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116,
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var v = inset * max(hdr, vec3(1e-10));
+    v = clamp((log2(v) - min_ev) / (max_ev - min_ev), vec3(0.0), vec3(1.0));
+    v = agx_contrast(v);
+    // Back to linear values, the curve outputs gamma encoded ones.
+    return pow(max(outset * v, vec3(0.0)), vec3(2.2));
+}
+
+// Must match `hdr::TONE_MAPPING_*`.
+const TONE_MAPPING_NONE: u32 = 0u;
+const TONE_MAPPING_REINHARD: u32 = 1u;
+const TONE_MAPPING_ACES: u32 = 2u;
+const TONE_MAPPING_AGX: u32 = 3u;
+const TONE_MAPPING_FILMIC: u32 = 4u;
+
+fn tone_map(hdr: vec3<f32>, mode: u32) -> vec3<f32> {
+    switch mode {
+        case TONE_MAPPING_REINHARD: {
+            return reinhard_tone_map(hdr);
+        }
+        case TONE_MAPPING_ACES: {
+            return aces_tone_map(hdr);
+        }
+        case TONE_MAPPING_AGX: {
+            return agx_tone_map(hdr);
+        }
+        case TONE_MAPPING_FILMIC: {
+            return filmic_tone_map(hdr);
+        }
+        default: {
+            return clamp(hdr, vec3(0.0), vec3(1.0));
+        }
+    }
+}
+
 struct VertexOutput {
     @location(0) uv: vec2<f32>,
     @builtin(position) clip_position: vec4<f32>,
@@ -40,6 +126,16 @@ fn vs_main(
     return out;
 }
 
+// Must match `hdr::PostUniform`.
+struct Post {
+    // Exposure multiplier from the manual exposure compensation.
+    exposure: f32,
+    // 1 to expose for the luminance measured by auto exposure.
+    auto_exposure: u32,
+    bloom_intensity: f32,
+    tone_mapping: u32,
+}
+
 @group(0)
 @binding(0)
 var hdr_image: texture_2d<f32>;
@@ -48,9 +144,32 @@ var hdr_image: texture_2d<f32>;
 @binding(1)
 var hdr_sampler: sampler;
 
+@group(0)
+@binding(2)
+var bloom_image: texture_2d<f32>;
+
+// Adapted scene luminance written by `exposure.wgsl`.
+@group(0)
+@binding(3)
+var<storage, read> scene_luminance: f32;
+
+@group(0)
+@binding(4)
+var<uniform> post: Post;
+
+// Luminance mapped to middle grey by auto exposure.
+const KEY_VALUE: f32 = 0.18;
+
 @fragment
 fn fs_main(vs: VertexOutput) -> @location(0) vec4<f32> {
     let hdr = textureSample(hdr_image, hdr_sampler, vs.uv);
-    let sdr = aces_tone_map(hdr.rgb);
+    let bloom = textureSample(bloom_image, hdr_sampler, vs.uv).rgb;
+    let color = mix(hdr.rgb, bloom, post.bloom_intensity);
+
+    var exposure = post.exposure;
+    if post.auto_exposure != 0u {
+        exposure *= KEY_VALUE / max(scene_luminance, 1e-4);
+    }
+    let sdr = tone_map(color * exposure, post.tone_mapping);
     return vec4(sdr, hdr.a);
 }
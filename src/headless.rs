@@ -0,0 +1,239 @@
+//! Rendering without a window, for thumbnails in CI or on servers.
+
+use std::{
+    ffi::OsStr,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use crate::{
+    camera::{CameraController, StaticCamera},
+    gpu::Gpu,
+    io::fs::{exr, stats::Bounds},
+    resource::{self, LoadOptions},
+    ModelEntry, RenderSettings, Renderer, Resources,
+};
+
+pub use crate::resource::Sky;
+
+/// What [`HeadlessRenderer::render`] reads back.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderOutput {
+    /// The final sRGB frame, as shown in the window.
+    #[default]
+    ToneMapped,
+    /// The linear scene before bloom, exposure and tone mapping.
+    Hdr,
+}
+
+impl RenderOutput {
+    /// `Hdr` for `.exr` files, `ToneMapped` otherwise.
+    pub fn for_path(path: &Path) -> Self {
+        let is_exr = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("exr"));
+        if is_exr {
+            Self::Hdr
+        } else {
+            Self::ToneMapped
+        }
+    }
+}
+
+/// A frame read back from the GPU.
+pub enum Frame {
+    ToneMapped(image::RgbaImage),
+    /// Linear RGBA, row by row from the top.
+    Hdr {
+        width: u32,
+        height: u32,
+        pixels: Vec<[f32; 4]>,
+    },
+}
+
+impl Frame {
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            Self::ToneMapped(image) => image.dimensions(),
+            Self::Hdr { width, height, .. } => (*width, *height),
+        }
+    }
+
+    /// Writes a tone mapped frame as PNG (or JPEG, following the extension)
+    /// and an HDR frame as EXR.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        match (self, RenderOutput::for_path(path)) {
+            (Self::ToneMapped(image), RenderOutput::ToneMapped) => Ok(image.save(path)?),
+            (
+                Self::Hdr {
+                    width,
+                    height,
+                    pixels,
+                },
+                RenderOutput::Hdr,
+            ) => exr::write_exr(path, *width, *height, pixels),
+            (Self::ToneMapped(_), RenderOutput::Hdr) => {
+                anyhow::bail!("Tone mapped frames can't be saved as EXR")
+            }
+            (Self::Hdr { .. }, RenderOutput::ToneMapped) => {
+                anyhow::bail!("HDR frames can only be saved as EXR")
+            }
+        }
+    }
+}
+
+/// Loads models and renders them offscreen from a camera placed by code.
+pub struct HeadlessRenderer {
+    gpu: Arc<Gpu>,
+    resources: Resources,
+    renderer: Renderer,
+    camera: Arc<RwLock<StaticCamera>>,
+}
+
+impl HeadlessRenderer {
+    /// `width` and `height` only size the first target, every render sets
+    /// its own.
+    pub async fn new(width: u32, height: u32) -> anyhow::Result<Self> {
+        Self::with_sky(width, height, Sky::Default).await
+    }
+
+    pub async fn with_sky(width: u32, height: u32, sky: Sky<'_>) -> anyhow::Result<Self> {
+        let gpu = Arc::new(Gpu::headless(width, height).await?);
+        let camera = Arc::new(RwLock::new(StaticCamera::new()));
+        let renderer = Renderer::new(
+            None,
+            Arc::clone(&gpu),
+            Arc::new(RwLock::new(CameraController::default())),
+            Arc::clone(&camera),
+            sky,
+        )
+        .await;
+
+        Ok(Self {
+            gpu,
+            resources: Resources::new(),
+            renderer,
+            camera,
+        })
+    }
+
+    /// Adds the models of a mesh file, placed by its scene graph.
+    pub async fn load_model(&self, path: &Path) -> anyhow::Result<()> {
+        let options = *self.resources.load_options.read().unwrap();
+        let scene = resource::load_scene(path.to_path_buf(), &self.gpu, options).await?;
+        let mut model_db = self.resources.model_db.write().unwrap();
+        for (model, instances) in scene {
+            model_db.insert(ModelEntry::new(&self.gpu.device, model, instances));
+        }
+        Ok(())
+    }
+
+    /// Bounds of every loaded model in world space, `None` before any was loaded.
+    pub fn bounds(&self) -> Option<Bounds> {
+        let model_db = self.resources.model_db.read().unwrap();
+        let points = model_db.get_all().flat_map(|entry| {
+            entry.instances.iter().flat_map(move |instance| {
+                entry.model.meshes.iter().flat_map(move |mesh| {
+                    mesh.vertices
+                        .iter()
+                        .map(move |v| instance.isometry * na::Point3::from(v.position))
+                })
+            })
+        });
+        Bounds::from_points(points)
+    }
+
+    pub fn set_camera(
+        &self,
+        position: na::Point3<f32>,
+        target: na::Point3<f32>,
+        up: na::Vector3<f32>,
+    ) {
+        *self.camera.write().unwrap() = StaticCamera {
+            position,
+            target,
+            up,
+        };
+    }
+
+    /// Used for the models loaded from now on.
+    pub fn set_load_options(&self, options: LoadOptions) {
+        *self.resources.load_options.write().unwrap() = options;
+    }
+
+    pub fn set_render_settings(&self, settings: RenderSettings) {
+        *self.resources.render_settings.write().unwrap() = settings;
+    }
+
+    pub fn render(
+        &mut self,
+        width: u32,
+        height: u32,
+        output: RenderOutput,
+    ) -> anyhow::Result<Frame> {
+        self.renderer
+            .render_to_image(&self.resources, width, height, output)
+    }
+
+    /// Renders and saves to `path`, as EXR for `.exr` files and tone mapped
+    /// otherwise.
+    pub fn render_to_file(&mut self, path: &Path, width: u32, height: u32) -> anyhow::Result<()> {
+        self.render(width, height, RenderOutput::for_path(path))?
+            .save(path)
+    }
+}
+
+/// Widens an IEEE 754 half, as read back from `Rgba16Float` textures.
+pub(crate) fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half as u32) & 0x8000) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        // Subnormal, becomes a normal float.
+        (0, _) => {
+            let shift = mantissa.leading_zeros() - 21;
+            let mantissa = (mantissa << shift) & 0x3ff;
+            sign | ((113 - shift) << 23) | (mantissa << 13)
+        }
+        (0x1f, _) => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn half_floats_widen_exactly() {
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x03ff), 1023.0 * 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn output_follows_the_extension() {
+        assert_eq!(
+            RenderOutput::for_path(Path::new("a.EXR")),
+            RenderOutput::Hdr
+        );
+        assert_eq!(
+            RenderOutput::for_path(Path::new("a.png")),
+            RenderOutput::ToneMapped
+        );
+        assert_eq!(
+            RenderOutput::for_path(Path::new("a")),
+            RenderOutput::ToneMapped
+        );
+    }
+}
@@ -0,0 +1,391 @@
+use std::f32::consts::PI;
+
+use wgpu::util::DeviceExt;
+
+use crate::{gpu::Gpu, texture};
+
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+/// Roughness goes from 0 at the first to 1 at the last level.
+const PREFILTERED_MIPS: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 256;
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// User controls for how the environment lights the scene.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnvironmentSettings {
+    /// Multiplies the sky and the light it casts.
+    pub intensity: f32,
+    /// Rotation of the sky around the up axis, in radians.
+    pub rotation: f32,
+}
+
+impl Default for EnvironmentSettings {
+    fn default() -> Self {
+        Self {
+            intensity: 1.0,
+            rotation: 0.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct EnvironmentUniform {
+    intensity: f32,
+    rotation: f32,
+    /// Highest mip level of the prefiltered cube.
+    max_lod: f32,
+    _padding: f32,
+}
+
+impl EnvironmentUniform {
+    fn new(settings: &EnvironmentSettings) -> Self {
+        Self {
+            intensity: settings.intensity,
+            rotation: settings.rotation.rem_euclid(2.0 * PI),
+            max_lod: (PREFILTERED_MIPS - 1) as f32,
+            _padding: 0.0,
+        }
+    }
+}
+
+const fn cube_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::Cube,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    }
+}
+
+/// Image based lighting derived from the sky cube.
+pub struct Environment {
+    irradiance: texture::CubeTexture,
+    prefiltered: texture::CubeTexture,
+    brdf_lut: texture::Texture,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    settings: EnvironmentSettings,
+}
+
+impl Environment {
+    /// Bind group of [`Environment::bind_group`], `uniform` is also used by the sky.
+    pub const BIND_GROUP_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor<'static> =
+        wgpu::BindGroupLayoutDescriptor {
+            label: Some("Environment Bind Group Layout"),
+            entries: &[
+                cube_entry(0),
+                cube_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        };
+
+    /// Irradiance cube, prefiltered specular cube, BRDF lookup table, their
+    /// sampler and the [`EnvironmentSettings`] uniform.
+    pub fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Environment Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(self.irradiance.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(self.prefiltered.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&self.brdf_lut.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    pub fn uniform_buffer(&self) -> &wgpu::Buffer {
+        &self.uniform_buffer
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, settings: EnvironmentSettings) {
+        if settings == self.settings {
+            return;
+        }
+        self.settings = settings;
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[EnvironmentUniform::new(&settings)]),
+        );
+    }
+}
+
+/// Compute pipelines turning a sky cube into an [`Environment`].
+pub struct IblBaker {
+    cube_layout: wgpu::BindGroupLayout,
+    lut_layout: wgpu::BindGroupLayout,
+    irradiance: wgpu::ComputePipeline,
+    prefiltered: wgpu::ComputePipeline,
+    brdf_lut: wgpu::ComputePipeline,
+}
+
+impl IblBaker {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let module = device.create_shader_module(wgpu::include_wgsl!("ibl.wgsl"));
+
+        let cube_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("IblBaker::cube_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let lut_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("IblBaker::lut_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            }],
+        });
+
+        let pipeline = |layout: &wgpu::BindGroupLayout, entry_point| {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point,
+            })
+        };
+
+        Self {
+            irradiance: pipeline(&cube_layout, "compute_irradiance"),
+            prefiltered: pipeline(&cube_layout, "compute_prefiltered"),
+            brdf_lut: pipeline(&lut_layout, "compute_brdf_lut"),
+            cube_layout,
+            lut_layout,
+        }
+    }
+
+    pub fn bake(
+        &self,
+        gpu: &Gpu,
+        sky: &texture::CubeTexture,
+        settings: EnvironmentSettings,
+    ) -> Environment {
+        let device = &gpu.device;
+        let usage = wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING;
+
+        let irradiance = texture::CubeTexture::create_2d(
+            device,
+            IRRADIANCE_SIZE,
+            IRRADIANCE_SIZE,
+            FORMAT,
+            1,
+            usage,
+            wgpu::FilterMode::Linear,
+            Some("Irradiance"),
+        );
+        let prefiltered = texture::CubeTexture::create_2d(
+            device,
+            PREFILTERED_SIZE,
+            PREFILTERED_SIZE,
+            FORMAT,
+            PREFILTERED_MIPS,
+            usage,
+            wgpu::FilterMode::Linear,
+            Some("Prefiltered"),
+        );
+        let brdf_lut = texture::Texture::create_2d_texture(
+            gpu,
+            BRDF_LUT_SIZE,
+            BRDF_LUT_SIZE,
+            FORMAT,
+            usage,
+            wgpu::FilterMode::Linear,
+            Some("BRDF LUT"),
+        );
+
+        let src_view = sky.texture().create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        // Every level is a pipeline, its target mip and the roughness read by
+        // `compute_prefiltered`.
+        let levels = std::iter::once((&self.irradiance, &irradiance, 0, 0.0)).chain(
+            (0..PREFILTERED_MIPS).map(|mip| {
+                let roughness = mip as f32 / (PREFILTERED_MIPS - 1) as f32;
+                (&self.prefiltered, &prefiltered, mip, roughness)
+            }),
+        );
+        let bind_groups = levels
+            .map(|(pipeline, dst, mip, roughness)| {
+                let dst_view = dst.texture().create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2Array),
+                    base_mip_level: mip,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                });
+                let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents: bytemuck::cast_slice(&[roughness, 0.0, 0.0, 0.0]),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &self.cube_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&src_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(&dst_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: params.as_entire_binding(),
+                        },
+                    ],
+                });
+                let size = (dst.texture().width() >> mip).max(1);
+                (pipeline, bind_group, size)
+            })
+            .collect::<Vec<_>>();
+
+        let lut_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.lut_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&brdf_lut.view),
+            }],
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+
+        for (pipeline, bind_group, size) in &bind_groups {
+            let num_workgroups = (size + 7) / 8;
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.dispatch_workgroups(num_workgroups, num_workgroups, 6);
+        }
+
+        let num_workgroups = (BRDF_LUT_SIZE + 7) / 8;
+        pass.set_pipeline(&self.brdf_lut);
+        pass.set_bind_group(0, &lut_bind_group, &[]);
+        pass.dispatch_workgroups(num_workgroups, num_workgroups, 1);
+
+        drop(pass);
+        gpu.queue.submit([encoder.finish()]);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Environment Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Environment Uniform"),
+            contents: bytemuck::cast_slice(&[EnvironmentUniform::new(&settings)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Environment {
+            irradiance,
+            prefiltered,
+            brdf_lut,
+            sampler,
+            uniform_buffer,
+            settings,
+        }
+    }
+}
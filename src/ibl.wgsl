@@ -0,0 +1,176 @@
+// Precomputes the image based lighting terms from the sky cube.
+
+const PI: f32 = 3.1415926535897932384626433832795;
+const SAMPLE_COUNT: u32 = 512u;
+
+struct Params {
+    roughness: f32,
+}
+
+// The sky cube, `textureLoad` does not accept cube textures.
+@group(0)
+@binding(0)
+var src: texture_2d_array<f32>;
+
+@group(0)
+@binding(1)
+var dst: texture_storage_2d_array<rgba16float, write>;
+
+@group(0)
+@binding(2)
+var<uniform> params: Params;
+
+@group(0)
+@binding(3)
+var lut: texture_storage_2d<rgba16float, write>;
+
+// Direction through the center of a texel of a cube face, following the
+// face orientation samplers use.
+fn cube_direction(texel: vec2<u32>, face: u32, size: vec2<u32>) -> vec3<f32> {
+    let uv = (vec2<f32>(texel) + 0.5) / vec2<f32>(size) * 2.0 - 1.0;
+    switch face {
+        case 0u: { return normalize(vec3(1.0, -uv.y, -uv.x)); }
+        case 1u: { return normalize(vec3(-1.0, -uv.y, uv.x)); }
+        case 2u: { return normalize(vec3(uv.x, 1.0, uv.y)); }
+        case 3u: { return normalize(vec3(uv.x, -1.0, -uv.y)); }
+        case 4u: { return normalize(vec3(uv.x, -uv.y, 1.0)); }
+        default: { return normalize(vec3(-uv.x, -uv.y, -1.0)); }
+    }
+}
+
+// Nearest texel of the sky cube in direction `dir`.
+fn sample_sky(dir: vec3<f32>) -> vec3<f32> {
+    let a = abs(dir);
+    var face: u32;
+    var uv: vec2<f32>;
+    if a.x >= a.y && a.x >= a.z {
+        face = select(1u, 0u, dir.x > 0.0);
+        uv = vec2(select(dir.z, -dir.z, dir.x > 0.0), -dir.y) / a.x;
+    } else if a.y >= a.z {
+        face = select(3u, 2u, dir.y > 0.0);
+        uv = vec2(dir.x, select(-dir.z, dir.z, dir.y > 0.0)) / a.y;
+    } else {
+        face = select(5u, 4u, dir.z > 0.0);
+        uv = vec2(select(-dir.x, dir.x, dir.z > 0.0), -dir.y) / a.z;
+    }
+    let size = textureDimensions(src);
+    let texel = min(vec2<u32>((uv * 0.5 + 0.5) * vec2<f32>(size)), size - 1u);
+    return textureLoad(src, texel, face, 0).rgb;
+}
+
+// Orthonormal basis with `n` as z axis.
+fn tangent_to_world(v: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    let up = select(vec3(1.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0), abs(n.z) < 0.999);
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return tangent * v.x + bitangent * v.y + n * v.z;
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// GGX distributed half vector around +z.
+fn importance_sample_ggx(xi: vec2<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+// Cosine weighted integral of the sky over the hemisphere around every direction.
+@compute
+@workgroup_size(8, 8, 1)
+fn compute_irradiance(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let size = textureDimensions(dst);
+    if gid.x >= size.x || gid.y >= size.y {
+        return;
+    }
+
+    let n = cube_direction(gid.xy, gid.z, size);
+    let delta = 0.05;
+    var irradiance = vec3(0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += delta) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += delta) {
+            let v = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            irradiance += sample_sky(tangent_to_world(v, n)) * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+
+    textureStore(dst, gid.xy, gid.z, vec4(PI * irradiance / count, 1.0));
+}
+
+// Sky convolved with the GGX lobe of `params.roughness`, one mip level per roughness.
+@compute
+@workgroup_size(8, 8, 1)
+fn compute_prefiltered(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let size = textureDimensions(dst);
+    if gid.x >= size.x || gid.y >= size.y {
+        return;
+    }
+
+    // Assumes the view direction equals the normal, as the split sum does.
+    let n = cube_direction(gid.xy, gid.z, size);
+    if params.roughness <= 0.0 {
+        textureStore(dst, gid.xy, gid.z, vec4(sample_sky(n), 1.0));
+        return;
+    }
+
+    var color = vec3(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        let h = tangent_to_world(importance_sample_ggx(hammersley(i, SAMPLE_COUNT), params.roughness), n);
+        let l = normalize(2.0 * dot(n, h) * h - n);
+        let n_dot_l = dot(n, l);
+        if n_dot_l > 0.0 {
+            color += sample_sky(l) * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+
+    textureStore(dst, gid.xy, gid.z, vec4(color / max(weight, 1e-4), 1.0));
+}
+
+fn geometry_schlick_ggx(n_dot_x: f32, roughness: f32) -> f32 {
+    // Image based lighting uses a different remapping than analytic lights.
+    let k = roughness * roughness / 2.0;
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+// Scale and bias to f0 of the specular term, by n·v along x and roughness along y.
+@compute
+@workgroup_size(8, 8, 1)
+fn compute_brdf_lut(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let size = textureDimensions(lut);
+    if gid.x >= size.x || gid.y >= size.y {
+        return;
+    }
+
+    let uv = (vec2<f32>(gid.xy) + 0.5) / vec2<f32>(size);
+    let n_dot_v = uv.x;
+    let roughness = uv.y;
+    let v = vec3(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        let h = importance_sample_ggx(hammersley(i, SAMPLE_COUNT), roughness);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = max(l.z, 0.0);
+        let n_dot_h = max(h.z, 0.0);
+        let v_dot_h = max(dot(v, h), 0.0);
+        if n_dot_l > 0.0 {
+            let g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+            let g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+            let fc = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fc) * g_vis;
+            bias += fc * g_vis;
+        }
+    }
+
+    let count = f32(SAMPLE_COUNT);
+    textureStore(lut, gid.xy, vec4(scale / count, bias / count, 0.0, 1.0));
+}
@@ -0,0 +1,29 @@
+// Copies the ids around the cursor out of the id target, to read them back.
+
+// Must match `id_buffer::REGION`.
+const REGION: u32 = 9u;
+
+@group(0) @binding(0)
+var t_ids: texture_2d<u32>;
+
+struct Region {
+    // Top left pixel of the region, may lie outside of the target.
+    origin: vec2<i32>,
+}
+@group(0) @binding(1)
+var<uniform> region: Region;
+@group(0) @binding(2)
+var<storage, read_write> texels: array<vec4<u32>>;
+
+@compute
+@workgroup_size(9, 9)
+fn cs_main(@builtin(local_invocation_id) id: vec3<u32>) {
+    let pixel = region.origin + vec2<i32>(id.xy);
+    let size = vec2<i32>(textureDimensions(t_ids));
+    // Background outside of the target.
+    var texel = vec4(0u);
+    if all(pixel >= vec2(0)) && all(pixel < size) {
+        texel = textureLoad(t_ids, pixel, 0);
+    }
+    texels[id.y * REGION + id.x] = texel;
+}
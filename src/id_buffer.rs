@@ -0,0 +1,651 @@
+//! Ids of the models under the cursor. Without MSAA the render pass draws
+//! them to a second color target, with it they are drawn by a pass of their
+//! own around the cursor, as integer targets can't be resolved. The region
+//! around the cursor is copied out of the target and read back a frame or two
+//! later, without waiting for the GPU.
+
+use std::{mem, sync::mpsc};
+
+use crate::{
+    db,
+    gpu::Gpu,
+    model::{InstanceRaw, ModelVertex, Vertex},
+    texture, ModelEntry,
+};
+
+/// Width and height of the region read around the cursor, in pixels.
+const REGION: u32 = 9;
+/// Farthest from the cursor a pick reaches, in pixels.
+pub const RADIUS: f32 = (REGION / 2) as f32;
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Uint;
+/// Bytes per texel of `FORMAT`.
+const TEXEL_SIZE: u32 = 16;
+/// Bytes per row of the region in [`IdBuffer::readback`].
+const ROW_SIZE: u32 = REGION * TEXEL_SIZE;
+/// Triangle written without `SHADER_PRIMITIVE_INDEX`, see `shader.wgsl`.
+const NO_PRIMITIVE: u32 = u32::MAX;
+
+/// Ids of a mesh, the same for all its vertices and instances.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DrawIds {
+    /// [`db::Id`] plus one, 0 is the background.
+    model: u32,
+    mesh: u32,
+}
+
+impl Vertex for DrawIds {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            // Bound at the ids of each draw, every instance reads them.
+            array_stride: 0,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 12,
+                format: wgpu::VertexFormat::Uint32x2,
+            }],
+        }
+    }
+}
+
+/// Where to look, and whether to select what is there.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PickRequest {
+    /// In pixels from the top left corner.
+    pub cursor: na::Point2<f32>,
+    pub select: bool,
+}
+
+/// What covers a pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IdHit {
+    pub model: db::Id,
+    pub instance: usize,
+    pub mesh: usize,
+    /// `None` when the device can't tell the triangles apart.
+    pub triangle: Option<usize>,
+}
+
+/// Read back by [`IdBuffer::poll`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pick {
+    pub request: PickRequest,
+    /// Closest to the cursor in the region around it, `None` when there is
+    /// only background.
+    pub hit: Option<IdHit>,
+}
+
+enum Readback {
+    Idle,
+    /// Copied by commands that may not be submitted yet.
+    Copied(PickRequest),
+    Mapping(
+        PickRequest,
+        mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+    ),
+}
+
+/// Id target, and the readback of the region around the cursor, one request
+/// at a time.
+pub struct IdBuffer {
+    /// Single sampled, whatever the sample count of the scene.
+    view: wgpu::TextureView,
+    /// Of the pass drawing the ids when the scene is multisampled, `None`
+    /// when the render pass draws them.
+    depth: Option<wgpu::TextureView>,
+    samples: u32,
+    width: u32,
+    height: u32,
+    layout: wgpu::BindGroupLayout,
+    /// Draws the ids of triangle meshes in the pass of their own.
+    draw_pipeline: wgpu::RenderPipeline,
+    /// Copies the region to `region_buffer`.
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    origin_buffer: wgpu::Buffer,
+    region_buffer: wgpu::Buffer,
+    /// [`DrawIds`] of every mesh written by [`IdBuffer::write_draws`].
+    draw_buffer: wgpu::Buffer,
+    /// Index in `draw_buffer` of the first mesh of each model.
+    first_draws: Vec<usize>,
+    draws: usize,
+    readback: wgpu::Buffer,
+    state: Readback,
+    /// View projection, region and number of draws of the last copy, to
+    /// skip the next one when it would read the same.
+    last: Option<([[f32; 4]; 4], na::Point2<i32>, usize)>,
+}
+
+impl IdBuffer {
+    /// Draws with the camera of `camera_layout` in the pass of its own.
+    pub fn new(gpu: &Gpu, camera_layout: &wgpu::BindGroupLayout) -> Self {
+        let (width, height) = gpu.get_config_read(|config| (config.width, config.height));
+        let device = &gpu.device;
+
+        let view = Self::create_view(device, width, height);
+        let (layout, pipeline) = Self::create_pipeline(device);
+        let draw_pipeline = Self::create_draw_pipeline(device, camera_layout);
+
+        let origin_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Id Origin Buffer"),
+            size: mem::size_of::<[i32; 2]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let region_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Id Region Buffer"),
+            size: (ROW_SIZE * REGION) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group =
+            Self::create_bind_group(device, &layout, &view, &origin_buffer, &region_buffer);
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Id Readback Buffer"),
+            size: (ROW_SIZE * REGION) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            view,
+            depth: None,
+            samples: 1,
+            width,
+            height,
+            layout,
+            draw_pipeline,
+            pipeline,
+            bind_group,
+            origin_buffer,
+            region_buffer,
+            draw_buffer: Self::draw_buffer(device, 64),
+            first_draws: Vec::new(),
+            draws: 0,
+            readback,
+            state: Readback::Idle,
+            last: None,
+        }
+    }
+
+    fn create_view(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("Id Texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// Depth of the pass drawing the ids, when the scene is multisampled.
+    fn create_depth(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("Id Depth Texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: texture::Texture::DEPTH_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// Appends the fragment entry writing the triangle ids to `source`, when
+    /// the device has `SHADER_PRIMITIVE_INDEX`, and returns the entry to use.
+    /// `source` defines `fragment` for `id_primitive.wgsl`.
+    pub fn fragment_entry(device: &wgpu::Device, source: &mut String) -> &'static str {
+        if device
+            .features()
+            .contains(wgpu::Features::SHADER_PRIMITIVE_INDEX)
+        {
+            source.push_str(include_str!("id_primitive.wgsl"));
+            "fs_primitive"
+        } else {
+            "fs_main"
+        }
+    }
+
+    fn create_draw_pipeline(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::RenderPipeline {
+        let mut source = include_str!("id_draw.wgsl").to_string();
+        let fs_entry = Self::fragment_entry(device, &mut source);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("id_draw.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Id Draw Pipeline Layout"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Id Draw Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[ModelVertex::desc(), InstanceRaw::desc(), DrawIds::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: fs_entry,
+                targets: &[Some(FORMAT.into())],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: texture::Texture::DEPTH_COMPARE,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    /// Copies the region out of the id target.
+    fn create_pipeline(device: &wgpu::Device) -> (wgpu::BindGroupLayout, wgpu::ComputePipeline) {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("id.wgsl"));
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Id Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Uint,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Id Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Id Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main",
+        });
+        (layout, pipeline)
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        view: &wgpu::TextureView,
+        origin_buffer: &wgpu::Buffer,
+        region_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Id Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: origin_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: region_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Room for the ids of `capacity` draws.
+    fn draw_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Id Draw Buffer"),
+            size: (mem::size_of::<DrawIds>() * capacity.max(1)) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Draws the ids in a pass of their own when `samples` is more than 1,
+    /// the sample count of the render pass.
+    pub fn set_msaa_samples(&mut self, gpu: &Gpu, samples: u32) {
+        self.samples = samples;
+        self.resize(gpu, self.width, self.height);
+    }
+
+    pub fn resize(&mut self, gpu: &Gpu, width: u32, height: u32) {
+        let device = &gpu.device;
+        self.width = width;
+        self.height = height;
+        self.view = Self::create_view(device, width, height);
+        self.depth = (self.samples > 1).then(|| Self::create_depth(device, width, height));
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.layout,
+            &self.view,
+            &self.origin_buffer,
+            &self.region_buffer,
+        );
+    }
+
+    /// Second color target of the pipelines of a render pass with `samples`
+    /// samples, written when `ids`. `None` when the pass is multisampled,
+    /// the ids are drawn by a pass of their own then. Integer formats don't
+    /// blend.
+    pub fn color_target(samples: u32, ids: bool) -> Option<wgpu::ColorTargetState> {
+        (samples == 1).then_some(wgpu::ColorTargetState {
+            format: FORMAT,
+            blend: None,
+            write_mask: if ids {
+                wgpu::ColorWrites::ALL
+            } else {
+                wgpu::ColorWrites::empty()
+            },
+        })
+    }
+
+    /// Attachment for [`IdBuffer::color_target`], cleared to the background,
+    /// `None` when the render pass is multisampled.
+    pub fn color_attachment(&self) -> Option<wgpu::RenderPassColorAttachment<'_>> {
+        self.depth.is_none().then(|| Self::attachment(&self.view))
+    }
+
+    fn attachment(view: &wgpu::TextureView) -> wgpu::RenderPassColorAttachment<'_> {
+        wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            },
+        }
+    }
+
+    /// Writes the ids of every mesh of `models` for [`IdBuffer::draw_ids`].
+    pub fn write_draws(&mut self, gpu: &Gpu, models: &[(db::Id, &ModelEntry)]) {
+        let draws = models
+            .iter()
+            .flat_map(|&(id, entry)| {
+                (0..entry.model.meshes.len()).map(move |mesh| DrawIds {
+                    model: id.0 as u32 + 1,
+                    mesh: mesh as u32,
+                })
+            })
+            .collect::<Vec<_>>();
+        self.first_draws = models
+            .iter()
+            .scan(0, |first, (_, entry)| {
+                let model = *first;
+                *first += entry.model.meshes.len();
+                Some(model)
+            })
+            .collect();
+        self.draws = draws.len();
+
+        if mem::size_of_val(draws.as_slice()) as u64 > self.draw_buffer.size() {
+            self.draw_buffer = Self::draw_buffer(&gpu.device, draws.len().next_power_of_two());
+        }
+        if !draws.is_empty() {
+            gpu.queue
+                .write_buffer(&self.draw_buffer, 0, bytemuck::cast_slice(&draws));
+        }
+    }
+
+    /// Vertex buffer of [`DrawIds`] for `mesh` of the `model`th of the models
+    /// last written by [`IdBuffer::write_draws`].
+    pub fn draw_ids(&self, model: usize, mesh: usize) -> wgpu::BufferSlice<'_> {
+        let size = mem::size_of::<DrawIds>() as u64;
+        let offset = (self.first_draws[model] + mesh) as u64 * size;
+        self.draw_buffer.slice(offset..offset + size)
+    }
+
+    /// Copies the ids around `request.cursor` of `models`, seen by a camera
+    /// of `view_proj`, for [`IdBuffer::poll`]. The render pass recorded in
+    /// `encoder` drew them, or with MSAA they are drawn first with
+    /// `camera_bind_group`. Returns false when the request is left for later,
+    /// as a readback is under way, or needless, as the last one read the
+    /// same.
+    pub fn copy_region(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view_proj: &na::Matrix4<f32>,
+        request: PickRequest,
+        models: &[&ModelEntry],
+        camera_bind_group: &wgpu::BindGroup,
+    ) -> bool {
+        if !matches!(self.state, Readback::Idle) {
+            return false;
+        }
+        let origin =
+            request.cursor.map(|v| v.floor() as i32) - na::Vector2::repeat(REGION as i32 / 2);
+        let key = ((*view_proj).into(), origin, self.draws);
+        if !request.select && self.last == Some(key) {
+            return false;
+        }
+
+        if let Some(depth) = &self.depth {
+            self.draw_region(encoder, depth, origin, models, camera_bind_group);
+        }
+        queue.write_buffer(
+            &self.origin_buffer,
+            0,
+            bytemuck::cast_slice(&[origin.x, origin.y]),
+        );
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Id Region Pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.dispatch_workgroups(1, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(
+            &self.region_buffer,
+            0,
+            &self.readback,
+            0,
+            self.readback.size(),
+        );
+        self.state = Readback::Copied(request);
+        self.last = Some(key);
+        true
+    }
+
+    /// Draws the ids of the triangles of `models` in the region at `origin`,
+    /// with the draws of [`IdBuffer::write_draws`].
+    fn draw_region(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        depth: &wgpu::TextureView,
+        origin: na::Point2<i32>,
+        models: &[&ModelEntry],
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Id Pass"),
+            color_attachments: &[Some(Self::attachment(&self.view))],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(texture::Texture::DEPTH_CLEAR),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        // Only the part of the region on the target, the copy reads the
+        // rest as background.
+        let size = na::Point2::new(self.width as i32, self.height as i32);
+        let start = origin.sup(&na::Point2::origin());
+        let end = (origin + na::Vector2::repeat(REGION as i32)).inf(&size);
+        if end.x <= start.x || end.y <= start.y {
+            return;
+        }
+        pass.set_scissor_rect(
+            start.x as u32,
+            start.y as u32,
+            (end.x - start.x) as u32,
+            (end.y - start.y) as u32,
+        );
+
+        pass.set_pipeline(&self.draw_pipeline);
+        pass.set_bind_group(0, camera_bind_group, &[]);
+        for (model, entry) in models.iter().enumerate() {
+            pass.set_vertex_buffer(1, entry.instance_buffer.slice(..));
+            let instances = 0..entry.instances.len() as u32;
+            for (index, mesh) in entry.model.meshes.iter().enumerate() {
+                // Only triangles are picked, see `IdHit::triangle`.
+                if mesh.topology != wgpu::PrimitiveTopology::TriangleList {
+                    continue;
+                }
+                pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                pass.set_vertex_buffer(2, self.draw_ids(model, index));
+                pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                pass.draw_indexed(0..mesh.num_elements, 0, instances.clone());
+            }
+        }
+    }
+
+    /// Starts mapping what the last [`IdBuffer::copy_region`] copied, which must
+    /// have been submitted since, and returns it once mapped.
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<Pick> {
+        if let Readback::Copied(request) = self.state {
+            let (sender, receiver) = mpsc::channel();
+            self.readback
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = sender.send(result);
+                });
+            self.state = Readback::Mapping(request, receiver);
+        }
+        device.poll(wgpu::Maintain::Poll);
+
+        let Readback::Mapping(request, receiver) = &self.state else {
+            return None;
+        };
+        let request = *request;
+        let mapped = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => Err(wgpu::BufferAsyncError),
+        };
+        self.state = Readback::Idle;
+        if let Err(err) = mapped {
+            log::warn!("Reading back the ids under the cursor failed: {err}");
+            return None;
+        }
+
+        let data = self.readback.slice(..).get_mapped_range();
+        let hit = closest_hit(&data);
+        drop(data);
+        self.readback.unmap();
+        Some(Pick { request, hit })
+    }
+}
+
+/// Ids of the texel closest to the center of the region, read back in rows
+/// of `ROW_SIZE` bytes.
+fn closest_hit(data: &[u8]) -> Option<IdHit> {
+    let center = (REGION / 2) as i32;
+    (0..REGION)
+        .flat_map(|y| (0..REGION).map(move |x| (x, y)))
+        .filter_map(|(x, y)| {
+            let offset = (y * ROW_SIZE + x * TEXEL_SIZE) as usize;
+            let texel = &data[offset..offset + TEXEL_SIZE as usize];
+            let [model, mesh, instance, primitive]: [u32; 4] = bytemuck::pod_read_unaligned(texel);
+            let hit = IdHit {
+                model: db::Id(model.checked_sub(1)? as usize),
+                instance: instance as usize,
+                mesh: mesh as usize,
+                triangle: (primitive != NO_PRIMITIVE).then_some(primitive as usize),
+            };
+            let distance = (x as i32 - center).pow(2) + (y as i32 - center).pow(2);
+            Some((distance, hit))
+        })
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, hit)| hit)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn closest_texel_wins() {
+        let mut data = vec![0; (ROW_SIZE * REGION) as usize];
+        let mut write = |x: u32, y: u32, texel: [u32; 4]| {
+            let offset = (y * ROW_SIZE + x * TEXEL_SIZE) as usize;
+            data[offset..offset + 16].copy_from_slice(bytemuck::cast_slice(&texel));
+        };
+        assert_eq!(closest_hit(&vec![0; (ROW_SIZE * REGION) as usize]), None);
+
+        write(0, 0, [3, 0, 0, 7]);
+        write(REGION / 2 + 1, REGION / 2, [6, 2, 1, NO_PRIMITIVE]);
+        let hit = closest_hit(&data).unwrap();
+        assert_eq!(
+            hit,
+            IdHit {
+                model: db::Id(5),
+                instance: 1,
+                mesh: 2,
+                triangle: None,
+            }
+        );
+    }
+}
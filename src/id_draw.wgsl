@@ -0,0 +1,62 @@
+// Ids of the triangle meshes around the cursor, drawn by `id_buffer::IdBuffer`
+// in a pass of their own when the render pass is multisampled.
+
+// Must match `camera::CameraUniform`.
+struct CameraUniform {
+    view_pos: vec4<f32>,
+    view: mat4x4<f32>,
+    view_proj: mat4x4<f32>,
+    inv_proj: mat4x4<f32>,
+    inv_view: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Model, mesh and instance, see `id_buffer::DrawIds`.
+    @location(0) @interpolate(flat) ids: vec3<u32>,
+}
+
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    instance: InstanceInput,
+    @location(12) ids: vec2<u32>,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(position, 1.0);
+    out.ids = vec3(ids, instance_index);
+    return out;
+}
+
+// Must match `id_buffer::NO_PRIMITIVE`.
+const NO_PRIMITIVE: u32 = 0xffffffffu;
+
+struct FragmentOutput {
+    @location(0) ids: vec4<u32>,
+}
+
+fn fragment(in: VertexOutput, primitive: u32) -> FragmentOutput {
+    return FragmentOutput(vec4(in.ids, primitive));
+}
+
+// Without `SHADER_PRIMITIVE_INDEX`, see `id_primitive.wgsl`.
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    return fragment(in, NO_PRIMITIVE);
+}
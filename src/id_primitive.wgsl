@@ -0,0 +1,10 @@
+// Appended to `shader.wgsl` and `id_draw.wgsl` when the device has
+// `SHADER_PRIMITIVE_INDEX`, which the whole module needs.
+
+@fragment
+fn fs_primitive(
+    in: VertexOutput,
+    @builtin(primitive_index) primitive: u32,
+) -> FragmentOutput {
+    return fragment(in, primitive);
+}
@@ -0,0 +1,674 @@
+use crate::io::fs::{AlphaMode, MaterialData, MeshData, TextureData};
+use crate::model;
+
+use anyhow::Result;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    StlBinary,
+    StlAscii,
+    Obj,
+    Ply,
+    Glb,
+}
+
+impl ExportFormat {
+    /// Whether the format can hold point clouds, the others only triangles.
+    fn has_points(self) -> bool {
+        matches!(self, Self::Ply | Self::Glb)
+    }
+
+    /// Picks the format from the file extension, STL files are written as
+    /// ASCII when `ascii` is set and as binary otherwise.
+    pub fn from_path(path: &Path, ascii: bool) -> Result<Self> {
+        let ext = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_lowercase);
+
+        let format = match ext.as_deref() {
+            Some("stl") if ascii => Self::StlAscii,
+            Some("stl") => Self::StlBinary,
+            Some("obj") => Self::Obj,
+            Some("ply") => Self::Ply,
+            Some("glb") => Self::Glb,
+            _ => anyhow::bail!("Unsupported export format"),
+        };
+        anyhow::ensure!(
+            !ascii || format == Self::StlAscii,
+            "Only STL files can be written as ASCII"
+        );
+        Ok(format)
+    }
+}
+
+/// Loaded models flattened into world space, one mesh per instance.
+struct Scene {
+    meshes: Vec<MeshData>,
+    materials: Vec<MaterialData>,
+}
+
+impl Scene {
+    fn new(models: &[(&model::Model, &[model::Instance])]) -> Self {
+        let mut meshes = Vec::new();
+        let mut materials = Vec::new();
+
+        for (model, instances) in models {
+            let material_offset = materials.len();
+            materials.extend(model.materials.iter().map(|m| m.data.clone()));
+
+            for instance in instances.iter() {
+                let isometry = &instance.isometry;
+
+                for mesh in &model.meshes {
+                    let vertices = mesh
+                        .vertices
+                        .iter()
+                        .map(|v| transform(isometry, v))
+                        .collect();
+
+                    meshes.push(MeshData {
+                        name: mesh.name.clone(),
+                        vertices,
+                        indices: mesh.indices.clone(),
+                        material: Some(material_offset + mesh.material),
+                        topology: mesh.topology,
+                        scalars: mesh.scalars.clone(),
+                        has_uv: true,
+                    });
+                }
+            }
+        }
+
+        Self { meshes, materials }
+    }
+
+    /// Drops the point clouds, with a warning for each.
+    fn skip_points(&mut self, format: ExportFormat) {
+        self.meshes.retain(|mesh| {
+            let points = mesh.topology == wgpu::PrimitiveTopology::PointList;
+            if points {
+                log::warn!(
+                    "Skipped point cloud {:?}, {format:?} has no points",
+                    mesh.name
+                );
+            }
+            !points
+        });
+    }
+
+    fn triangles(&self) -> impl Iterator<Item = [[f32; 3]; 3]> + '_ {
+        self.meshes.iter().flat_map(|mesh| {
+            faces(mesh).map(|face| {
+                [
+                    mesh.vertices[face[0] as usize].position,
+                    mesh.vertices[face[1] as usize].position,
+                    mesh.vertices[face[2] as usize].position,
+                ]
+            })
+        })
+    }
+}
+
+/// Writes `models` to `path` in the format given by its extension, see
+/// [`ExportFormat::from_path`].
+pub fn export(
+    path: &Path,
+    ascii: bool,
+    models: &[(&model::Model, &[model::Instance])],
+) -> Result<()> {
+    export_as(path, ExportFormat::from_path(path, ascii)?, models)
+}
+
+pub fn export_as(
+    path: &Path,
+    format: ExportFormat,
+    models: &[(&model::Model, &[model::Instance])],
+) -> Result<()> {
+    let Scene { meshes, materials } = Scene::new(models);
+    export_meshes(path, format, meshes, materials)
+}
+
+/// Writes meshes that are already in world space, `MeshData::material` indexes `materials`.
+pub fn export_meshes(
+    path: &Path,
+    format: ExportFormat,
+    meshes: Vec<MeshData>,
+    materials: Vec<MaterialData>,
+) -> Result<()> {
+    let mut scene = Scene { meshes, materials };
+    if !format.has_points() {
+        scene.skip_points(format);
+    }
+
+    match format {
+        ExportFormat::StlBinary => write_stl_binary(path, &scene),
+        ExportFormat::StlAscii => write_stl_ascii(path, &scene),
+        ExportFormat::Obj => write_obj(path, &scene),
+        ExportFormat::Ply => write_ply(path, &scene),
+        ExportFormat::Glb => write_glb(path, &scene),
+    }
+}
+
+/// `v` moved to world space by `isometry`.
+fn transform(isometry: &na::Isometry3<f32>, v: &model::ModelVertex) -> model::ModelVertex {
+    let [x, y, z, w] = v.tangent;
+    let tangent = isometry.rotation * na::Vector3::new(x, y, z);
+    model::ModelVertex {
+        position: (isometry * na::Point3::from(v.position)).into(),
+        normal: (isometry.rotation * na::Vector3::from(v.normal)).into(),
+        // Rotations keep the handedness, so the bitangent sign too.
+        tangent: [tangent.x, tangent.y, tangent.z, w],
+        ..*v
+    }
+}
+
+/// Triangles of `mesh`, point clouds have none.
+fn faces(mesh: &MeshData) -> std::slice::ChunksExact<'_, u32> {
+    match mesh.topology {
+        wgpu::PrimitiveTopology::TriangleList => mesh.indices.chunks_exact(3),
+        _ => [].chunks_exact(3),
+    }
+}
+
+fn face_normal(triangle: &[[f32; 3]; 3]) -> [f32; 3] {
+    let [a, b, c] = triangle.map(na::Vector3::from);
+    (b - a)
+        .cross(&(c - a))
+        .try_normalize(f32::EPSILON)
+        .unwrap_or_else(na::Vector3::zeros)
+        .into()
+}
+
+fn write_stl_binary(path: &Path, scene: &Scene) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let count = scene.meshes.iter().map(|m| faces(m).len()).sum::<usize>();
+
+    writer.write_all(&[0; 80])?;
+    writer.write_all(&(count as u32).to_le_bytes())?;
+
+    for triangle in scene.triangles() {
+        let normal = face_normal(&triangle);
+        for value in normal.iter().chain(triangle.iter().flatten()) {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        // Attribute byte count
+        writer.write_all(&[0; 2])?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_stl_ascii(path: &Path, scene: &Scene) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writeln!(writer, "solid void")?;
+    for triangle in scene.triangles() {
+        let [nx, ny, nz] = face_normal(&triangle);
+        writeln!(writer, "  facet normal {nx:e} {ny:e} {nz:e}")?;
+        writeln!(writer, "    outer loop")?;
+        for [x, y, z] in triangle {
+            writeln!(writer, "      vertex {x:e} {y:e} {z:e}")?;
+        }
+        writeln!(writer, "    endloop")?;
+        writeln!(writer, "  endfacet")?;
+    }
+    writeln!(writer, "endsolid void")?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes a texture next to the exported file and returns its file name.
+fn write_texture(path: &Path, name: &str, texture: &TextureData) -> Option<String> {
+    let stem = path.file_stem()?.to_string_lossy();
+    let file_name = format!("{stem}_{name}.png");
+
+    let saved = texture
+        .to_image()
+        .and_then(|image| Ok(image.save(path.with_file_name(&file_name))?));
+
+    match saved {
+        Ok(()) => Some(file_name),
+        Err(err) => {
+            log::warn!("Could not export texture {texture}: {err}");
+            None
+        }
+    }
+}
+
+fn write_obj(path: &Path, scene: &Scene) -> Result<()> {
+    let mtl_path = path.with_extension("mtl");
+    let mut mtl = BufWriter::new(File::create(&mtl_path)?);
+
+    let material_names = material_names(&scene.materials);
+    for (i, (material, name)) in scene.materials.iter().zip(&material_names).enumerate() {
+        let [r, g, b, a] = material.base_color;
+        writeln!(mtl, "newmtl {name}")?;
+        writeln!(mtl, "Kd {r} {g} {b}")?;
+        writeln!(mtl, "d {a}")?;
+        if let Some(texture) = &material.diffuse_texture {
+            if let Some(file_name) = write_texture(path, &format!("{i}_diffuse"), texture) {
+                writeln!(mtl, "map_Kd {file_name}")?;
+            }
+        }
+        if let Some(texture) = &material.normal_texture {
+            if let Some(file_name) = write_texture(path, &format!("{i}_normal"), texture) {
+                writeln!(mtl, "map_Bump {file_name}")?;
+            }
+        }
+        writeln!(mtl)?;
+    }
+    mtl.flush()?;
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mtl_name = mtl_path.file_name().unwrap().to_string_lossy();
+    writeln!(writer, "mtllib {mtl_name}")?;
+
+    // OBJ indices are 1 based and global to the file.
+    let mut offset = 1;
+
+    for (i, mesh) in scene.meshes.iter().enumerate() {
+        writeln!(writer, "o {}", obj_name(&mesh.name, "mesh", i))?;
+        for v in &mesh.vertices {
+            let [x, y, z] = v.position;
+            writeln!(writer, "v {x} {y} {z}")?;
+        }
+        for v in &mesh.vertices {
+            // The loaders flip v to match wgpu's texture origin.
+            let [s, t] = v.tex_coord;
+            writeln!(writer, "vt {s} {}", 1.0 - t)?;
+        }
+        for v in &mesh.vertices {
+            let [x, y, z] = v.normal;
+            writeln!(writer, "vn {x} {y} {z}")?;
+        }
+        if let Some(name) = mesh.material.and_then(|i| material_names.get(i)) {
+            writeln!(writer, "usemtl {name}")?;
+        }
+        for face in faces(mesh) {
+            let [a, b, c] = [face[0], face[1], face[2]].map(|i| i + offset);
+            writeln!(writer, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+        offset += mesh.vertices.len() as u32;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// `name` without spaces, `{kind}_{index}` when it is empty.
+fn obj_name(name: &str, kind: &str, index: usize) -> String {
+    let name = name.split_whitespace().collect::<Vec<_>>().join("_");
+    if name.is_empty() {
+        format!("{kind}_{index}")
+    } else {
+        name
+    }
+}
+
+/// Names of `materials` in the MTL file, numbered only when they collide.
+fn material_names(materials: &[MaterialData]) -> Vec<String> {
+    let mut used = HashSet::new();
+    materials
+        .iter()
+        .enumerate()
+        .map(|(i, material)| {
+            let name = obj_name(&material.name, "material", i);
+            let mut unique = name.clone();
+            for n in 1.. {
+                if used.insert(unique.clone()) {
+                    break;
+                }
+                unique = format!("{name}_{n}");
+            }
+            unique
+        })
+        .collect()
+}
+
+fn write_ply(path: &Path, scene: &Scene) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let vertex_count = scene.meshes.iter().map(|m| m.vertices.len()).sum::<usize>();
+    let face_count = scene.meshes.iter().map(|m| faces(m).len()).sum::<usize>();
+
+    write!(
+        writer,
+        "ply\n\
+         format binary_little_endian 1.0\n\
+         comment exported by void\n\
+         element vertex {vertex_count}\n\
+         property float x\n\
+         property float y\n\
+         property float z\n\
+         property float nx\n\
+         property float ny\n\
+         property float nz\n\
+         property float s\n\
+         property float t\n\
+         property uchar red\n\
+         property uchar green\n\
+         property uchar blue\n\
+         property uchar alpha\n\
+         element face {face_count}\n\
+         property list uchar uint vertex_indices\n\
+         end_header\n"
+    )?;
+
+    for v in scene.meshes.iter().flat_map(|m| &m.vertices) {
+        let [s, t] = v.tex_coord;
+        for value in v.position.iter().chain(&v.normal).chain(&[s, 1.0 - t]) {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&v.color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))?;
+    }
+
+    let mut offset = 0;
+    for mesh in &scene.meshes {
+        for face in faces(mesh) {
+            writer.write_all(&[3])?;
+            for index in face {
+                writer.write_all(&(index + offset).to_le_bytes())?;
+            }
+        }
+        offset += mesh.vertices.len() as u32;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Binary chunk of a GLB file, every view is aligned to 4 bytes.
+#[derive(Default)]
+struct GlbBuffer {
+    data: Vec<u8>,
+    views: Vec<serde_json::Value>,
+}
+
+impl GlbBuffer {
+    fn push_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        let offset = self.data.len();
+        self.data.extend_from_slice(bytes);
+        while self.data.len() % 4 != 0 {
+            self.data.push(0);
+        }
+
+        let mut view = serde_json::json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": bytes.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = target.into();
+        }
+        self.views.push(view);
+        self.views.len() - 1
+    }
+}
+
+const POINTS: u32 = 0;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+fn write_glb(path: &Path, scene: &Scene) -> Result<()> {
+    use serde_json::json;
+
+    let mut buffer = GlbBuffer::default();
+    let mut accessors = Vec::new();
+    let mut images = Vec::new();
+    let mut textures = Vec::new();
+
+    let mut push_texture = |buffer: &mut GlbBuffer, texture: &TextureData| {
+        let mut png = Vec::new();
+        let encoded = texture.to_image().and_then(|image| {
+            Ok(image.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)?)
+        });
+        if let Err(err) = encoded {
+            log::warn!("Could not export texture {texture}: {err}");
+            return None;
+        }
+
+        let view = buffer.push_view(&png, None);
+        images.push(json!({ "bufferView": view, "mimeType": "image/png" }));
+        textures.push(json!({ "source": images.len() - 1 }));
+        Some(textures.len() - 1)
+    };
+
+    let materials = scene
+        .materials
+        .iter()
+        .map(|material| {
+            let mut pbr = json!({
+                "baseColorFactor": material.base_color,
+                "metallicFactor": material.metallic,
+                "roughnessFactor": material.roughness,
+            });
+            let mut json = json!({
+                "name": material.name,
+                "emissiveFactor": material.emissive,
+            });
+            match material.alpha_mode {
+                AlphaMode::Opaque => {}
+                AlphaMode::Mask(cutoff) => {
+                    json["alphaMode"] = "MASK".into();
+                    json["alphaCutoff"] = cutoff.into();
+                }
+                AlphaMode::Blend => json["alphaMode"] = "BLEND".into(),
+            }
+
+            let mut texture = |texture: &Option<TextureData>| {
+                let texture = push_texture(&mut buffer, texture.as_ref()?)?;
+                Some(json!({ "index": texture }))
+            };
+            if let Some(info) = texture(&material.diffuse_texture) {
+                pbr["baseColorTexture"] = info;
+            }
+            if let Some(info) = texture(&material.metallic_roughness_texture) {
+                pbr["metallicRoughnessTexture"] = info;
+            }
+            if let Some(info) = texture(&material.normal_texture) {
+                json["normalTexture"] = info;
+            }
+            if let Some(info) = texture(&material.emissive_texture) {
+                json["emissiveTexture"] = info;
+            }
+            if let Some(info) = texture(&material.occlusion_texture) {
+                json["occlusionTexture"] = info;
+            }
+
+            json["pbrMetallicRoughness"] = pbr;
+            json
+        })
+        .collect::<Vec<_>>();
+
+    let mut meshes = Vec::new();
+    let mut nodes = Vec::new();
+
+    for (i, mesh) in scene.meshes.iter().enumerate() {
+        if mesh.vertices.is_empty() || mesh.indices.is_empty() {
+            continue;
+        }
+
+        let positions = mesh.vertices.iter().map(|v| v.position).collect::<Vec<_>>();
+        let normals = mesh.vertices.iter().map(|v| v.normal).collect::<Vec<_>>();
+        let tex_coords = mesh
+            .vertices
+            .iter()
+            .map(|v| v.tex_coord)
+            .collect::<Vec<_>>();
+
+        // Positions need bounds to be a valid glTF accessor.
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for position in &positions {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+
+        let count = mesh.vertices.len();
+        let view = buffer.push_view(bytemuck::cast_slice(&positions), Some(ARRAY_BUFFER));
+        accessors.push(json!({
+            "bufferView": view, "componentType": FLOAT, "count": count, "type": "VEC3",
+            "min": min, "max": max,
+        }));
+        let view = buffer.push_view(bytemuck::cast_slice(&normals), Some(ARRAY_BUFFER));
+        accessors.push(json!({
+            "bufferView": view, "componentType": FLOAT, "count": count, "type": "VEC3",
+        }));
+        let view = buffer.push_view(bytemuck::cast_slice(&tex_coords), Some(ARRAY_BUFFER));
+        accessors.push(json!({
+            "bufferView": view, "componentType": FLOAT, "count": count, "type": "VEC2",
+        }));
+        let view = buffer.push_view(
+            bytemuck::cast_slice(&mesh.indices),
+            Some(ELEMENT_ARRAY_BUFFER),
+        );
+        accessors.push(json!({
+            "bufferView": view, "componentType": UNSIGNED_INT,
+            "count": mesh.indices.len(), "type": "SCALAR",
+        }));
+
+        let first = accessors.len() - 4;
+        let mut primitive = json!({
+            "attributes": {
+                "POSITION": first,
+                "NORMAL": first + 1,
+                "TEXCOORD_0": first + 2,
+            },
+            "indices": first + 3,
+        });
+        if let Some(material) = mesh.material {
+            primitive["material"] = material.into();
+        }
+        if mesh.topology == wgpu::PrimitiveTopology::PointList {
+            primitive["mode"] = POINTS.into();
+        }
+
+        meshes.push(json!({ "name": obj_name(&mesh.name, "mesh", i), "primitives": [primitive] }));
+        nodes.push(json!({ "mesh": meshes.len() - 1 }));
+    }
+
+    let mut root = json!({
+        "asset": { "version": "2.0", "generator": "void" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "materials": materials,
+        "textures": textures,
+        "images": images,
+        "accessors": accessors,
+        "bufferViews": buffer.views,
+        "buffers": [{ "byteLength": buffer.data.len() }],
+    });
+
+    // glTF does not allow empty arrays.
+    if let Some(root) = root.as_object_mut() {
+        root.retain(|_, value| value.as_array().map_or(true, |a| !a.is_empty()));
+        if buffer.data.is_empty() {
+            root.remove("buffers");
+        }
+    }
+
+    let mut json = serde_json::to_vec(&root)?;
+    // Chunks are padded to 4 bytes, JSON with spaces and BIN with zeros.
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+
+    let length = 12 + 8 + json.len() + 8 + buffer.data.len();
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writer.write_all(b"glTF")?;
+    writer.write_all(&2u32.to_le_bytes())?;
+    writer.write_all(&(length as u32).to_le_bytes())?;
+
+    writer.write_all(&(json.len() as u32).to_le_bytes())?;
+    writer.write_all(b"JSON")?;
+    writer.write_all(&json)?;
+
+    writer.write_all(&(buffer.data.len() as u32).to_le_bytes())?;
+    writer.write_all(b"BIN\0")?;
+    writer.write_all(&buffer.data)?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::fs::test_util::{mesh, quad, vertex};
+
+    #[test]
+    fn formats_follow_the_extension() {
+        let format = |path: &str, ascii| ExportFormat::from_path(Path::new(path), ascii).ok();
+        assert_eq!(format("a.STL", false), Some(ExportFormat::StlBinary));
+        assert_eq!(format("a.stl", true), Some(ExportFormat::StlAscii));
+        assert_eq!(format("a.glb", false), Some(ExportFormat::Glb));
+        assert_eq!(format("a.glb", true), None);
+        assert_eq!(format("a.fbx", false), None);
+    }
+
+    #[test]
+    fn material_names_are_kept_unless_they_collide() {
+        let material = |name: &str| MaterialData {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let materials = ["Steel", "", "Steel", "Brushed steel", "Steel_1"].map(material);
+        assert_eq!(
+            material_names(&materials),
+            [
+                "Steel",
+                "material_1",
+                "Steel_1",
+                "Brushed_steel",
+                "Steel_1_1"
+            ]
+        );
+    }
+
+    #[test]
+    fn tangents_rotate_with_normals() {
+        let isometry = na::Isometry3::rotation(na::Vector3::z() * std::f32::consts::FRAC_PI_2);
+        let v = model::ModelVertex {
+            normal: [1.0, 0.0, 0.0],
+            tangent: [0.0, 1.0, 0.0, -1.0],
+            ..vertex([1.0, 0.0, 0.0])
+        };
+        let moved = transform(&isometry, &v);
+
+        let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-6);
+        assert!(close(&moved.position, &[0.0, 1.0, 0.0]));
+        assert!(close(&moved.normal, &[0.0, 1.0, 0.0]));
+        assert!(close(&moved.tangent, &[-1.0, 0.0, 0.0, -1.0]));
+    }
+
+    #[test]
+    fn point_clouds_are_skipped_without_points() -> Result<()> {
+        let points = MeshData {
+            topology: wgpu::PrimitiveTopology::PointList,
+            ..mesh(vec![vertex([0.0; 3]), vertex([1.0; 3])], vec![0, 1])
+        };
+        let dir = std::env::temp_dir().join("void_test_export_points");
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("mesh.obj");
+        export_meshes(&path, ExportFormat::Obj, vec![quad(), points], Vec::new())?;
+
+        let obj = std::fs::read_to_string(&path)?;
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 4);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("o ")).count(), 1);
+        Ok(())
+    }
+}
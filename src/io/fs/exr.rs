@@ -0,0 +1,124 @@
+//! Minimal OpenEXR writer for the raw HDR frame: one part, scanlines,
+//! uncompressed 32 bit float channels.
+
+use anyhow::Result;
+use std::{fs::File, io::Write, path::Path};
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const VERSION: u32 = 2;
+const PIXEL_TYPE_FLOAT: i32 = 2;
+/// Channels in the alphabetical order EXR requires, with their index in RGBA.
+const CHANNELS: [(&str, usize); 4] = [("A", 3), ("B", 2), ("G", 1), ("R", 0)];
+
+/// Writes linear RGBA `pixels`, row by row from the top, to `path`.
+pub fn write_exr(path: &Path, width: u32, height: u32, pixels: &[[f32; 4]]) -> Result<()> {
+    let bytes = encode_exr(width, height, pixels)?;
+    File::create(path)?.write_all(&bytes)?;
+    Ok(())
+}
+
+pub fn encode_exr(width: u32, height: u32, pixels: &[[f32; 4]]) -> Result<Vec<u8>> {
+    anyhow::ensure!(
+        pixels.len() == width as usize * height as usize,
+        "{} pixels don't make a {width}x{height} image",
+        pixels.len()
+    );
+    anyhow::ensure!(width > 0 && height > 0, "Empty image");
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+
+    let mut channels = Vec::new();
+    for (name, _) in CHANNELS {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+        // Not perceptually linear, reserved bytes, x and y sampling.
+        channels.extend_from_slice(&[0; 4]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+
+    let window = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|v: &i32| v.to_le_bytes())
+        .collect::<Vec<_>>();
+
+    attribute(&mut out, "channels", "chlist", &channels);
+    attribute(&mut out, "compression", "compression", &[0]);
+    attribute(&mut out, "dataWindow", "box2i", &window);
+    attribute(&mut out, "displayWindow", "box2i", &window);
+    attribute(&mut out, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut out, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute(&mut out, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut out, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    out.push(0);
+
+    // One chunk per scanline: its y, its size and every channel in turn.
+    let line_size = width as usize * CHANNELS.len() * std::mem::size_of::<f32>();
+    let chunk_size = 8 + line_size;
+    let table_end = out.len() + height as usize * 8;
+    for y in 0..height as usize {
+        let offset = (table_end + y * chunk_size) as u64;
+        out.extend_from_slice(&offset.to_le_bytes());
+    }
+
+    for (y, row) in pixels.chunks_exact(width as usize).enumerate() {
+        out.extend_from_slice(&(y as i32).to_le_bytes());
+        out.extend_from_slice(&(line_size as i32).to_le_bytes());
+        for (_, channel) in CHANNELS {
+            for pixel in row {
+                out.extend_from_slice(&pixel[channel].to_le_bytes());
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+fn attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.extend_from_slice(kind.as_bytes());
+    out.push(0);
+    out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scanlines_follow_the_offset_table() {
+        let pixels = [
+            [1.0, 2.0, 3.0, 4.0],
+            [5.0, 6.0, 7.0, 8.0],
+            [9.0, 10.0, 11.0, 12.0],
+            [13.0, 14.0, 15.0, 16.0],
+            [17.0, 18.0, 19.0, 20.0],
+            [21.0, 22.0, 23.0, 24.0],
+        ];
+        let bytes = encode_exr(3, 2, &pixels).unwrap();
+        assert_eq!(bytes[..4], MAGIC);
+
+        let line_size = 3 * 4 * 4;
+        let table = bytes.len() - 2 * (8 + line_size) - 2 * 8;
+        let read_u64 = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let read_f32 = |at: usize| f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+
+        let second_line = read_u64(table + 8) as usize;
+        assert_eq!(read_u64(table) as usize + 8 + line_size, second_line);
+        assert_eq!(bytes[second_line..second_line + 4], 1i32.to_le_bytes());
+        // Alpha of the first pixel, then red of the last pixel of the line.
+        assert_eq!(read_f32(second_line + 8), 16.0);
+        assert_eq!(read_f32(second_line + 8 + line_size - 4), 21.0);
+    }
+
+    #[test]
+    fn pixel_count_must_match() {
+        assert!(encode_exr(2, 2, &[[0.0; 4]; 3]).is_err());
+    }
+}
@@ -0,0 +1,550 @@
+use crate::io::fs::{
+    box_uv, AlphaMode, IMeshFile, ImportWarning, MaterialData, MeshData, NodeData, SamplerData,
+    TextureData,
+};
+use crate::model;
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// glTF 2.0 file, either `.gltf` with external or embedded buffers or binary `.glb`.
+///
+/// Node transforms are split into a rigid isometry, which becomes the node's
+/// instance, and a scale which is baked into a copy of the mesh.
+pub struct GltfFile {
+    meshes: Vec<MeshData>,
+    materials: Vec<MaterialData>,
+    nodes: Vec<NodeData>,
+    warnings: Vec<ImportWarning>,
+}
+
+struct Importer<'a> {
+    buffers: &'a [::gltf::buffer::Data],
+    meshes: Vec<MeshData>,
+    nodes: Vec<NodeData>,
+    warnings: Vec<ImportWarning>,
+    /// Meshes already imported for a glTF mesh index and quantized scale.
+    placed: HashMap<(usize, [i64; 3]), Vec<usize>>,
+}
+
+impl GltfFile {
+    pub fn new(path: &PathBuf) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        // Validated, out of range references would panic while reading.
+        let ::gltf::Gltf { document, blob } = ::gltf::Gltf::from_slice(&data)?;
+        let base = path.parent().unwrap_or_else(|| Path::new("./"));
+        let buffers = ::gltf::import_buffers(&document, Some(base), blob)?;
+
+        let mut warnings = Vec::new();
+
+        let required = document.extensions_required().collect::<Vec<_>>();
+        for name in document.extensions_used() {
+            warnings.push(ImportWarning::UnsupportedExtension {
+                name: name.to_string(),
+                required: required.contains(&name),
+            });
+        }
+
+        let images = document
+            .images()
+            .map(|image| {
+                let name = image
+                    .name()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("image {}", image.index()));
+                let decoded =
+                    ::gltf::image::Data::from_source(image.source(), Some(base), &buffers)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|data| to_rgba(&data));
+                match decoded {
+                    Ok(image) => Some((name, image)),
+                    Err(err) => {
+                        warnings.push(ImportWarning::MissingTexture {
+                            name,
+                            reason: err.to_string(),
+                        });
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let texture = |texture: ::gltf::Texture| {
+            let (name, image) = images[texture.source().index()].clone()?;
+            Some(TextureData::Image {
+                name,
+                image,
+                sampler: sampler(&texture.sampler()),
+            })
+        };
+
+        let materials = document
+            .materials()
+            .map(|material| {
+                let pbr = material.pbr_metallic_roughness();
+                MaterialData {
+                    name: material.name().unwrap_or_default().to_string(),
+                    base_color: pbr.base_color_factor(),
+                    metallic: pbr.metallic_factor(),
+                    roughness: pbr.roughness_factor(),
+                    emissive: material.emissive_factor(),
+                    alpha_mode: match material.alpha_mode() {
+                        ::gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                        ::gltf::material::AlphaMode::Mask => {
+                            AlphaMode::Mask(material.alpha_cutoff().unwrap_or(0.5))
+                        }
+                        ::gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+                    },
+                    diffuse_texture: pbr.base_color_texture().and_then(|t| texture(t.texture())),
+                    normal_texture: material.normal_texture().and_then(|t| texture(t.texture())),
+                    metallic_roughness_texture: pbr
+                        .metallic_roughness_texture()
+                        .and_then(|t| texture(t.texture())),
+                    emissive_texture: material
+                        .emissive_texture()
+                        .and_then(|t| texture(t.texture())),
+                    occlusion_texture: material
+                        .occlusion_texture()
+                        .and_then(|t| texture(t.texture())),
+                }
+            })
+            .collect();
+
+        let mut importer = Importer {
+            buffers: &buffers,
+            meshes: Vec::new(),
+            nodes: Vec::new(),
+            warnings,
+            placed: HashMap::new(),
+        };
+
+        match document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+        {
+            Some(scene) => {
+                for node in scene.nodes() {
+                    importer.visit(node, na::Matrix4::identity())?;
+                }
+            }
+            // Without a scene every mesh is shown once at the origin.
+            None => {
+                for mesh in document.meshes() {
+                    importer.import_mesh(&mesh, na::Vector3::repeat(1.0))?;
+                }
+            }
+        }
+
+        let Importer {
+            meshes,
+            nodes,
+            warnings,
+            ..
+        } = importer;
+
+        Ok(Self {
+            meshes,
+            materials,
+            nodes,
+            warnings,
+        })
+    }
+}
+
+impl<'a> Importer<'a> {
+    fn visit(&mut self, node: ::gltf::Node, parent: na::Matrix4<f32>) -> Result<()> {
+        let world = parent * na::Matrix4::from(node.transform().matrix());
+
+        if let Some(mesh) = node.mesh() {
+            let (isometry, scale) = decompose(&world);
+            // Quantize so that float noise in the hierarchy does not duplicate meshes.
+            let scale = scale.map(|s| (s * 1e4).round() / 1e4);
+            let key = (mesh.index(), scale.map(|s| (s * 1e4) as i64).into());
+
+            let meshes = match self.placed.get(&key) {
+                Some(meshes) => meshes.clone(),
+                None => {
+                    let meshes = self.import_mesh(&mesh, scale)?;
+                    self.placed.insert(key, meshes.clone());
+                    meshes
+                }
+            };
+
+            self.nodes.push(NodeData {
+                name: node.name().unwrap_or_default().to_string(),
+                meshes,
+                isometry,
+            });
+        }
+
+        for child in node.children() {
+            self.visit(child, world)?;
+        }
+        Ok(())
+    }
+
+    /// Imports every triangle and point primitive of `mesh` with `scale` baked in.
+    fn import_mesh(&mut self, mesh: &::gltf::Mesh, scale: na::Vector3<f32>) -> Result<Vec<usize>> {
+        let name = mesh.name().unwrap_or_default().to_string();
+        // Mirroring transforms turn the triangles inside out.
+        let flip_winding = scale.x * scale.y * scale.z < 0.0;
+        let mut imported = Vec::new();
+
+        for primitive in mesh.primitives() {
+            let topology = match primitive.mode() {
+                ::gltf::mesh::Mode::Triangles => wgpu::PrimitiveTopology::TriangleList,
+                ::gltf::mesh::Mode::Points => wgpu::PrimitiveTopology::PointList,
+                mode => {
+                    self.warnings.push(ImportWarning::UnsupportedPrimitive {
+                        mesh: name.clone(),
+                        mode: format!("{:?}", mode),
+                    });
+                    continue;
+                }
+            };
+
+            let buffers = self.buffers;
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|d| &d.0[..]));
+
+            let Some(positions) = reader.read_positions() else {
+                continue;
+            };
+            let positions = positions
+                .map(|p| na::Vector3::from(p).component_mul(&scale))
+                .collect::<Vec<_>>();
+            let normals = reader
+                .read_normals()
+                .map(|normals| normals.collect::<Vec<_>>());
+            let tex_coords = reader
+                .read_tex_coords(0)
+                .map(|tex_coords| tex_coords.into_f32().collect::<Vec<_>>());
+            let colors = reader
+                .read_colors(0)
+                .map(|colors| colors.into_rgba_f32().collect::<Vec<_>>());
+            let counts = [
+                ("NORMAL", normals.as_ref().map(Vec::len)),
+                ("TEXCOORD_0", tex_coords.as_ref().map(Vec::len)),
+                ("COLOR_0", colors.as_ref().map(Vec::len)),
+            ];
+            for (attribute, count) in counts {
+                let Some(count) = count else {
+                    continue;
+                };
+                anyhow::ensure!(
+                    count == positions.len(),
+                    "glTF mesh {name:?} has {count} {attribute} values for {} positions",
+                    positions.len()
+                );
+            }
+
+            let vertices = positions
+                .iter()
+                .enumerate()
+                .map(|(i, position)| {
+                    let position: [f32; 3] = (*position).into();
+                    let normal = match &normals {
+                        // Normals transform with the inverse scale.
+                        Some(normals) => na::Vector3::from(normals[i])
+                            .component_div(&scale)
+                            .try_normalize(f32::EPSILON)
+                            .unwrap_or_else(na::Vector3::zeros)
+                            .into(),
+                        None => [0.0; 3],
+                    };
+                    let tex_coord = match &tex_coords {
+                        Some(tex_coords) => tex_coords[i],
+                        None => box_uv(&position),
+                    };
+                    model::ModelVertex {
+                        position,
+                        tex_coord,
+                        normal,
+                        color: colors.as_ref().map_or([1.0; 4], |colors| colors[i]),
+                        tangent: [0.0; 4],
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let mut indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+            if let Some(&index) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+                anyhow::bail!(
+                    "glTF mesh {name:?} index {index} out of range of {} vertices",
+                    vertices.len()
+                );
+            }
+            if flip_winding && topology == wgpu::PrimitiveTopology::TriangleList {
+                indices.chunks_exact_mut(3).for_each(|face| face.swap(1, 2));
+            }
+
+            imported.push(self.meshes.len());
+            self.meshes.push(MeshData {
+                name: name.clone(),
+                vertices,
+                indices,
+                material: primitive.material().index(),
+                topology,
+                scalars: Default::default(),
+                has_uv: tex_coords.is_some(),
+            });
+        }
+
+        Ok(imported)
+    }
+}
+
+/// Splits an affine transform into an isometry and a per-axis scale.
+///
+/// Shear introduced by non-uniform scale on a rotated parent is dropped.
+fn decompose(matrix: &na::Matrix4<f32>) -> (na::Isometry3<f32>, na::Vector3<f32>) {
+    let translation = na::Translation3::new(matrix[(0, 3)], matrix[(1, 3)], matrix[(2, 3)]);
+    let linear = matrix.fixed_view::<3, 3>(0, 0).into_owned();
+
+    let mut scale = na::Vector3::new(
+        linear.column(0).norm(),
+        linear.column(1).norm(),
+        linear.column(2).norm(),
+    );
+    if linear.determinant() < 0.0 {
+        scale.x = -scale.x;
+    }
+
+    let rotation = if scale.iter().any(|s| s.abs() <= f32::EPSILON) {
+        na::UnitQuaternion::identity()
+    } else {
+        let rotation = na::Matrix3::from_columns(&[
+            linear.column(0) / scale.x,
+            linear.column(1) / scale.y,
+            linear.column(2) / scale.z,
+        ]);
+        na::UnitQuaternion::from_matrix(&rotation)
+    };
+
+    (na::Isometry3::from_parts(translation, rotation), scale)
+}
+
+/// Unset filters keep the defaults of [`SamplerData`].
+fn sampler(sampler: &::gltf::texture::Sampler) -> SamplerData {
+    use ::gltf::texture::{MagFilter, MinFilter, WrappingMode};
+    use wgpu::{AddressMode, FilterMode};
+
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => AddressMode::Repeat,
+    };
+    let default = SamplerData::default();
+    let mag_filter = match sampler.mag_filter() {
+        None => default.mag_filter,
+        Some(MagFilter::Nearest) => FilterMode::Nearest,
+        Some(MagFilter::Linear) => FilterMode::Linear,
+    };
+    let (min_filter, mipmap_filter) = match sampler.min_filter() {
+        None => (default.min_filter, default.mipmap_filter),
+        Some(MinFilter::Nearest | MinFilter::NearestMipmapNearest) => {
+            (FilterMode::Nearest, FilterMode::Nearest)
+        }
+        Some(MinFilter::Linear | MinFilter::LinearMipmapNearest) => {
+            (FilterMode::Linear, FilterMode::Nearest)
+        }
+        Some(MinFilter::NearestMipmapLinear) => (FilterMode::Nearest, FilterMode::Linear),
+        Some(MinFilter::LinearMipmapLinear) => (FilterMode::Linear, FilterMode::Linear),
+    };
+    SamplerData {
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+        mag_filter,
+        min_filter,
+        mipmap_filter,
+    }
+}
+
+fn to_rgba(data: &::gltf::image::Data) -> Result<image::RgbaImage> {
+    use ::gltf::image::Format::*;
+
+    let channels = match data.format {
+        R8 | R16 => 1,
+        R8G8 | R16G16 => 2,
+        R8G8B8 | R16G16B16 | R32G32B32FLOAT => 3,
+        R8G8B8A8 | R16G16B16A16 | R32G32B32A32FLOAT => 4,
+    };
+
+    // Bring every format down to 8 bit channels. Wider channels are not in
+    // the byte order of the file: `gltf` decodes the image with the `image`
+    // crate and casts its `u16` and `f32` buffers to bytes, in memory order.
+    let values: Vec<u8> = match data.format {
+        R8 | R8G8 | R8G8B8 | R8G8B8A8 => data.pixels.clone(),
+        R16 | R16G16 | R16G16B16 | R16G16B16A16 => data
+            .pixels
+            .chunks_exact(2)
+            .map(|c| (bytemuck::pod_read_unaligned::<u16>(c) >> 8) as u8)
+            .collect(),
+        R32G32B32FLOAT | R32G32B32A32FLOAT => data
+            .pixels
+            .chunks_exact(4)
+            .map(|c| (bytemuck::pod_read_unaligned::<f32>(c).clamp(0.0, 1.0) * 255.0) as u8)
+            .collect(),
+    };
+
+    let pixels = values
+        .chunks_exact(channels)
+        .flat_map(|c| match c {
+            [l] => [*l, *l, *l, 255],
+            [l, a] => [*l, *l, *l, *a],
+            [r, g, b] => [*r, *g, *b, 255],
+            [r, g, b, a] => [*r, *g, *b, *a],
+            _ => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+
+    image::RgbaImage::from_raw(data.width, data.height, pixels)
+        .ok_or_else(|| anyhow::anyhow!("Image data does not match its size"))
+}
+
+impl IMeshFile for GltfFile {
+    fn get_vertices(&self) -> Result<Vec<model::ModelVertex>> {
+        Ok(self
+            .meshes
+            .iter()
+            .flat_map(|m| m.vertices.iter().copied())
+            .collect())
+    }
+
+    fn get_indices(&self) -> Result<Vec<u32>> {
+        let mut indices = Vec::new();
+        let mut offset = 0;
+
+        for m in &self.meshes {
+            indices.extend(m.indices.iter().map(|i| i + offset));
+            offset += m.vertices.len() as u32;
+        }
+
+        Ok(indices)
+    }
+
+    fn get_uv(&self, vertex: &[f32; 3]) -> [f32; 2] {
+        box_uv(vertex)
+    }
+
+    fn get_meshes(&self) -> Result<Vec<MeshData>> {
+        Ok(self.meshes.clone())
+    }
+
+    fn get_materials(&self) -> Result<Vec<MaterialData>> {
+        Ok(self.materials.clone())
+    }
+
+    fn get_nodes(&self) -> Result<Vec<NodeData>> {
+        Ok(self.nodes.clone())
+    }
+
+    fn get_warnings(&self) -> Vec<ImportWarning> {
+        self.warnings.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn samplers_map_to_wgpu() -> Result<()> {
+        let json = br#"{
+            "asset": { "version": "2.0" },
+            "samplers": [
+                {},
+                { "wrapS": 33071, "wrapT": 33648, "magFilter": 9728, "minFilter": 9987 }
+            ]
+        }"#;
+        let ::gltf::Gltf { document, .. } = ::gltf::Gltf::from_slice(json)?;
+        let samplers = document.samplers().map(|s| sampler(&s)).collect::<Vec<_>>();
+
+        assert_eq!(samplers[0], SamplerData::default());
+        assert_eq!(samplers[0].address_mode_u, wgpu::AddressMode::Repeat);
+        assert_eq!(
+            samplers[1],
+            SamplerData {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::MirrorRepeat,
+                mag_filter: wgpu::FilterMode::Nearest,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn wide_channels_keep_their_values() -> Result<()> {
+        let dir = std::env::temp_dir().join("void_test_gltf_16bit");
+        std::fs::create_dir_all(&dir)?;
+        let rgb16 =
+            image::ImageBuffer::<image::Rgb<u16>, _>::from_raw(1, 1, vec![0xffff, 0x8000, 0x00ff])
+                .unwrap();
+        image::DynamicImage::ImageRgb16(rgb16).save(dir.join("rgb16.png"))?;
+
+        let json = br#"{ "asset": { "version": "2.0" }, "images": [{ "uri": "rgb16.png" }] }"#;
+        let ::gltf::Gltf { document, .. } = ::gltf::Gltf::from_slice(json)?;
+        let image = document.images().next().unwrap();
+        let data = ::gltf::image::Data::from_source(image.source(), Some(&dir), &[])?;
+        assert_eq!(data.format, ::gltf::image::Format::R16G16B16);
+        assert_eq!(to_rgba(&data)?.into_raw(), vec![255, 128, 0, 255]);
+        Ok(())
+    }
+
+    /// A triangle with `normals` normals and the indices at `index_offset`,
+    /// 72 for `[0, 1, 2]` and 78 for `[0, 1, 3]`.
+    fn triangle(name: &str, normals: usize, index_offset: usize) -> Result<GltfFile> {
+        let json = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "buffers": [{{
+                    "byteLength": 84,
+                    "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAABAAIAAAABAAMA"
+                }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteLength": 72 }},
+                    {{ "buffer": 0, "byteOffset": 72, "byteLength": 12 }}
+                ],
+                "accessors": [
+                    {{
+                        "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                        "min": [0, 0, 0], "max": [1, 1, 0]
+                    }},
+                    {{ "bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": {normals}, "type": "VEC3" }},
+                    {{ "bufferView": 1, "byteOffset": {}, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+                ],
+                "meshes": [{{
+                    "primitives": [{{ "attributes": {{ "POSITION": 0, "NORMAL": 1 }}, "indices": 2 }}]
+                }}]
+            }}"#,
+            index_offset - 72
+        );
+        let dir = std::env::temp_dir().join("void_test_gltf_checks");
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(name);
+        std::fs::write(&path, json)?;
+        GltfFile::new(&path)
+    }
+
+    #[test]
+    fn counts_and_indices_are_checked() {
+        let file = triangle("valid.gltf", 3, 72).unwrap();
+        assert_eq!(file.meshes[0].indices, [0, 1, 2]);
+
+        let err = triangle("normals.gltf", 2, 72).err().unwrap();
+        assert!(
+            err.to_string().contains("2 NORMAL values for 3 positions"),
+            "{err}"
+        );
+        let err = triangle("indices.gltf", 3, 78).err().unwrap();
+        assert!(
+            err.to_string()
+                .contains("index 3 out of range of 3 vertices"),
+            "{err}"
+        );
+    }
+}
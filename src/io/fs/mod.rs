@@ -1,20 +1,247 @@
 use crate::model;
 use anyhow::Result;
-use std::{ffi::OsStr, path::PathBuf};
+use std::{collections::BTreeMap, ffi::OsStr, fmt::Display, path::PathBuf};
 
+pub mod export;
+pub mod exr;
+mod gltf;
+pub mod normals;
 mod obj;
+mod ply;
+pub mod stats;
 mod stl;
+pub mod tangents;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod uv;
+pub mod weld;
 
+use self::gltf::*;
 use obj::*;
+use ply::*;
 use stl::*;
 
+/// A single drawable piece of a mesh file, indexed into its own vertices.
+#[derive(Clone)]
+pub struct MeshData {
+    pub name: String,
+    pub vertices: Vec<model::ModelVertex>,
+    pub indices: Vec<u32>,
+    /// Index into [`IMeshFile::get_materials`], if the mesh has a material.
+    pub material: Option<usize>,
+    /// `PointList` for point clouds, `TriangleList` otherwise.
+    pub topology: wgpu::PrimitiveTopology,
+    /// Extra per-vertex values such as scanner confidence or intensity.
+    pub scalars: BTreeMap<String, Vec<f32>>,
+    /// False when the file has no texture coordinates and `tex_coord` is only
+    /// a placeholder, see [`uv::generate_uvs`].
+    pub has_uv: bool,
+}
+
+/// Where the pixels of a material texture come from.
+#[derive(Clone)]
+pub enum TextureData {
+    /// Image file on disk, already resolved relative to the mesh file.
+    File { path: PathBuf, sampler: SamplerData },
+    /// Image embedded in the mesh file.
+    Image {
+        name: String,
+        image: image::RgbaImage,
+        sampler: SamplerData,
+    },
+}
+
+impl TextureData {
+    pub fn to_image(&self) -> Result<image::DynamicImage> {
+        match self {
+            Self::File { path, .. } => Ok(image::open(path)?),
+            Self::Image { image, .. } => Ok(image::DynamicImage::ImageRgba8(image.clone())),
+        }
+    }
+
+    pub fn sampler(&self) -> SamplerData {
+        match self {
+            Self::File { sampler, .. } | Self::Image { sampler, .. } => *sampler,
+        }
+    }
+}
+
+impl Display for TextureData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File { path, .. } => write!(f, "{}", path.display()),
+            Self::Image { name, .. } => write!(f, "{name}"),
+        }
+    }
+}
+
+/// How a material texture is filtered and wrapped, repeating by default as
+/// in glTF and MTL.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerData {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+}
+
+impl Default for SamplerData {
+    fn default() -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+        }
+    }
+}
+
+impl SamplerData {
+    pub fn descriptor(&self) -> wgpu::SamplerDescriptor<'static> {
+        wgpu::SamplerDescriptor {
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            ..Default::default()
+        }
+    }
+}
+
+/// Material description following the glTF metallic-roughness model.
+#[derive(Clone)]
+pub struct MaterialData {
+    pub name: String,
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3],
+    pub alpha_mode: AlphaMode,
+    pub diffuse_texture: Option<TextureData>,
+    pub normal_texture: Option<TextureData>,
+    pub metallic_roughness_texture: Option<TextureData>,
+    pub emissive_texture: Option<TextureData>,
+    pub occlusion_texture: Option<TextureData>,
+}
+
+impl Default for MaterialData {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            base_color: [1.0; 4],
+            metallic: 0.0,
+            roughness: 1.0,
+            emissive: [0.0; 3],
+            alpha_mode: AlphaMode::Opaque,
+            diffuse_texture: None,
+            normal_texture: None,
+            metallic_roughness_texture: None,
+            emissive_texture: None,
+            occlusion_texture: None,
+        }
+    }
+}
+
+/// How the alpha of the base color is used, as in glTF.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    #[default]
+    Opaque,
+    /// Fragments with an alpha below the cutoff are discarded.
+    Mask(f32),
+    /// Blended with what is behind.
+    Blend,
+}
+
+/// Placement of a group of meshes in the file's scene.
+#[derive(Clone)]
+pub struct NodeData {
+    pub name: String,
+    /// Indices into [`IMeshFile::get_meshes`].
+    pub meshes: Vec<usize>,
+    pub isometry: na::Isometry3<f32>,
+}
+
+/// Something in the file that could not be imported as authored.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportWarning {
+    UnsupportedExtension { name: String, required: bool },
+    UnsupportedPrimitive { mesh: String, mode: String },
+    MissingTexture { name: String, reason: String },
+}
+
+impl Display for ImportWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnsupportedExtension { name, required } => {
+                let kind = if *required { "required" } else { "used" };
+                write!(f, "Unsupported {kind} extension {name}")
+            }
+            Self::UnsupportedPrimitive { mesh, mode } => {
+                write!(f, "Skipped {mode} primitive in mesh {mesh}")
+            }
+            Self::MissingTexture { name, reason } => {
+                write!(f, "Could not load texture {name}: {reason}")
+            }
+        }
+    }
+}
+
 pub trait IMeshFile {
     fn get_vertices(&self) -> Result<Vec<model::ModelVertex>>;
     fn get_indices(&self) -> Result<Vec<u32>>;
     fn get_uv(&self, vertex: &[f32; 3]) -> [f32; 2];
+
+    /// Formats that only store one mesh get it from `get_vertices` and `get_indices`.
+    fn get_meshes(&self) -> Result<Vec<MeshData>> {
+        Ok(vec![MeshData {
+            name: String::new(),
+            vertices: self.get_vertices()?,
+            indices: self.get_indices()?,
+            material: None,
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            scalars: BTreeMap::new(),
+            has_uv: false,
+        }])
+    }
+
+    fn get_materials(&self) -> Result<Vec<MaterialData>> {
+        Ok(Vec::new())
+    }
+
+    /// Empty when the meshes are not placed by a scene graph.
+    fn get_nodes(&self) -> Result<Vec<NodeData>> {
+        Ok(Vec::new())
+    }
+
+    fn get_warnings(&self) -> Vec<ImportWarning> {
+        Vec::new()
+    }
 }
 
-/// Supported formats obj, stl.
+/// Projects `vertex` on the plane of its dominant axis, assuming the model lies in [-1, 1].
+pub fn box_uv(vertex: &[f32; 3]) -> [f32; 2] {
+    let abs_x = vertex[0].abs();
+    let abs_y = vertex[1].abs();
+    let abs_z = vertex[2].abs();
+
+    if abs_x >= abs_y && abs_x >= abs_z {
+        // Project on the yz plane
+        [(vertex[1] + 1.0) * 0.5, (vertex[2] + 1.0) * 0.5]
+    } else if abs_y >= abs_x && abs_y >= abs_z {
+        // Project on the xz plane
+        [(vertex[0] + 1.0) * 0.5, (vertex[2] + 1.0) * 0.5]
+    } else {
+        // Project on the xy plane
+        [(vertex[0] + 1.0) * 0.5, (vertex[1] + 1.0) * 0.5]
+    }
+}
+
+/// Supported formats obj, stl, ply, gltf, glb.
 pub struct MeshFile {
     inner: Box<dyn IMeshFile>,
 }
@@ -33,10 +260,15 @@ impl MeshFile {
     pub fn new(path: PathBuf) -> Result<Self> {
         valid_file(&path)?;
 
-        let ext = OsStr::to_str(path.extension().unwrap()).unwrap();
+        let ext = OsStr::to_str(path.extension().unwrap())
+            .unwrap()
+            .to_lowercase();
 
-        let inner = match ext {
+        let inner: Box<dyn IMeshFile> = match ext.as_str() {
             "stl" => Box::new(StlFile::new(&path)?),
+            "obj" => Box::new(ObjFile::new(&path)?),
+            "ply" => Box::new(PlyFile::new(&path)?),
+            "gltf" | "glb" => Box::new(GltfFile::new(&path)?),
             _ => {
                 anyhow::bail!("Unsupported file format")
             }
@@ -56,6 +288,18 @@ impl IMeshFile for MeshFile {
     fn get_indices(&self) -> Result<Vec<u32>> {
         self.inner.get_indices()
     }
+    fn get_meshes(&self) -> Result<Vec<MeshData>> {
+        self.inner.get_meshes()
+    }
+    fn get_materials(&self) -> Result<Vec<MaterialData>> {
+        self.inner.get_materials()
+    }
+    fn get_nodes(&self) -> Result<Vec<NodeData>> {
+        self.inner.get_nodes()
+    }
+    fn get_warnings(&self) -> Vec<ImportWarning> {
+        self.inner.get_warnings()
+    }
 }
 
 #[cfg(test)]
@@ -72,6 +316,115 @@ mod test {
         let test_mesh_file = MeshFile::new(stl_path)?;
         let meshes = test_mesh_file.get_vertices()?;
         assert!(meshes.len() != 0);
+        // Corners at the same position share a vertex.
+        assert!(meshes.len() < test_mesh_file.get_indices()?.len());
+
+        // Welded vertices are shared between faces.
+        let stl_path = PathBuf::from_str(&MODEL_PATH).unwrap().join("test.stl");
+        let (welded, _) = crate::resource::load_world_meshes(stl_path, Default::default())?;
+        assert!(welded[0].vertices.len() < welded[0].indices.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_obj_file() -> Result<()> {
+        let obj_path = PathBuf::from_str("./res").unwrap().join("cube.obj");
+        let obj_file = MeshFile::new(obj_path)?;
+
+        let meshes = obj_file.get_meshes()?;
+        assert!(!meshes.is_empty());
+        assert!(meshes.iter().all(|m| m.material == Some(0)));
+
+        let materials = obj_file.get_materials()?;
+        assert_eq!(materials.len(), 1);
+        let Some(TextureData::File { path: diffuse, .. }) = &materials[0].diffuse_texture else {
+            panic!("cube.mtl has a diffuse map");
+        };
+        assert!(diffuse.ends_with("cube-diffuse.jpg"));
+        assert!(diffuse.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_gltf_file() -> Result<()> {
+        let gltf_path = PathBuf::from_str(&MODEL_PATH)
+            .unwrap()
+            .join("triangle.gltf");
+        let gltf_file = MeshFile::new(gltf_path)?;
+
+        // The same mesh placed with two different scales is imported twice.
+        let meshes = gltf_file.get_meshes()?;
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].vertices[1].position, [2.0, 0.0, 0.0]);
+        assert_eq!(meshes[1].vertices[1].position, [1.0, 0.0, 0.0]);
+
+        let nodes = gltf_file.get_nodes()?;
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].meshes, vec![0]);
+        assert_eq!(nodes[0].isometry.translation.x, 1.0);
+        assert_eq!(nodes[1].meshes, vec![1]);
+
+        let materials = gltf_file.get_materials()?;
+        assert_eq!(materials[0].base_color, [1.0, 0.0, 0.0, 1.0]);
+
+        assert_eq!(
+            gltf_file.get_warnings(),
+            vec![ImportWarning::UnsupportedExtension {
+                name: "KHR_materials_variants".to_string(),
+                required: false,
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ply_points() -> Result<()> {
+        let path = PathBuf::from_str(&MODEL_PATH).unwrap().join("points.ply");
+        let ply_file = MeshFile::new(path)?;
+        let meshes = ply_file.get_meshes()?;
+
+        assert_eq!(meshes.len(), 1);
+        let mesh = &meshes[0];
+        assert_eq!(mesh.topology, wgpu::PrimitiveTopology::PointList);
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        assert_eq!(mesh.vertices[1].position, [1.0, 0.0, 0.0]);
+        assert_eq!(mesh.vertices[1].color, [0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(mesh.scalars["confidence"], vec![0.5, 0.75, 1.0]);
+        assert_eq!(mesh.scalars["intensity"], vec![10.0, 20.0, 30.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_export() -> Result<()> {
+        use export::{export_meshes, ExportFormat};
+
+        let gltf_path = PathBuf::from_str(&MODEL_PATH)
+            .unwrap()
+            .join("triangle.gltf");
+        let gltf_file = MeshFile::new(gltf_path)?;
+        let meshes = gltf_file.get_meshes()?;
+        let materials = gltf_file.get_materials()?;
+        let triangles = meshes.iter().map(|m| m.indices.len() / 3).sum::<usize>();
+
+        let dir = std::env::temp_dir().join("void_test_export");
+        std::fs::create_dir_all(&dir)?;
+
+        for (file_name, format) in [
+            ("binary.stl", ExportFormat::StlBinary),
+            ("ascii.stl", ExportFormat::StlAscii),
+            ("mesh.obj", ExportFormat::Obj),
+            ("mesh.ply", ExportFormat::Ply),
+            ("mesh.glb", ExportFormat::Glb),
+        ] {
+            let path = dir.join(file_name);
+            export_meshes(&path, format, meshes.clone(), materials.clone())?;
+
+            let exported = MeshFile::new(path)?;
+            assert_eq!(exported.get_indices()?.len() / 3, triangles, "{file_name}");
+        }
+
+        let obj = MeshFile::new(dir.join("mesh.obj"))?;
+        assert_eq!(obj.get_materials()?[0].name, "Red");
         Ok(())
     }
 }
@@ -0,0 +1,255 @@
+use crate::io::fs::MeshData;
+
+use std::fmt::Display;
+
+/// How vertex normals are produced when a mesh is loaded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NormalMode {
+    /// Use the normals stored in the file, flat faces for files without any.
+    Keep,
+    /// Keep the file's normals when they are smooth across the faces, otherwise
+    /// average the face normals, within the crease angle when welding.
+    #[default]
+    Auto,
+    /// One normal per face, every face gets its own vertices.
+    Face,
+    /// Average of the adjacent face normals weighted by face area.
+    AreaWeighted,
+    /// Average of the adjacent face normals weighted by the angle at the vertex.
+    AngleWeighted,
+}
+
+impl NormalMode {
+    pub const ALL: [NormalMode; 5] = [
+        Self::Keep,
+        Self::Auto,
+        Self::Face,
+        Self::AreaWeighted,
+        Self::AngleWeighted,
+    ];
+}
+
+impl Display for NormalMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Keep => "From file",
+            Self::Auto => "Auto",
+            Self::Face => "Flat",
+            Self::AreaWeighted => "Area weighted",
+            Self::AngleWeighted => "Angle weighted",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Faces whose vertex normals point away from them, above this fraction the
+/// file's normals are considered wrong.
+const MAX_FLIPPED_FACES: f32 = 0.1;
+
+/// Faces whose corners all carry the face normal, above this fraction the
+/// file's normals are facet normals stored per corner rather than smooth.
+const MAX_FLAT_FACES: f32 = 0.9;
+/// Smallest cosine between the normal of a flat face and those of its corners.
+const FLAT_COS: f32 = 0.9999;
+
+/// Whether `mode` keeps the normals stored in `mesh`.
+pub fn keeps_normals(mesh: &MeshData, mode: NormalMode) -> bool {
+    match mode {
+        NormalMode::Keep => true,
+        NormalMode::Auto => has_smooth_normals(mesh),
+        _ => false,
+    }
+}
+
+/// Returns `mesh` with normals generated according to `mode`.
+///
+/// Point clouds are returned unchanged.
+pub fn generate_normals(mesh: &MeshData, mode: NormalMode) -> MeshData {
+    if mesh.topology != wgpu::PrimitiveTopology::TriangleList {
+        return mesh.clone();
+    }
+
+    match mode {
+        NormalMode::Keep if has_normals(mesh) => mesh.clone(),
+        NormalMode::Keep => face_normals(mesh),
+        NormalMode::Auto if has_smooth_normals(mesh) => mesh.clone(),
+        NormalMode::Auto => smooth_normals(mesh, true),
+        NormalMode::Face => face_normals(mesh),
+        NormalMode::AreaWeighted => smooth_normals(mesh, false),
+        NormalMode::AngleWeighted => smooth_normals(mesh, true),
+    }
+}
+
+fn triangle(mesh: &MeshData, face: &[u32]) -> [na::Vector3<f32>; 3] {
+    [face[0], face[1], face[2]].map(|i| na::Vector3::from(mesh.vertices[i as usize].position))
+}
+
+/// Whether the file stored any normals, those of STL files are per face and
+/// not read.
+fn has_normals(mesh: &MeshData) -> bool {
+    mesh.vertices.iter().any(|v| v.normal != [0.0; 3])
+}
+
+/// Checks that every normal has unit length and that they mostly agree with the winding.
+fn has_valid_normals(mesh: &MeshData) -> bool {
+    let degenerate = mesh.vertices.iter().any(|v| {
+        let length = na::Vector3::from(v.normal).norm();
+        !length.is_finite() || (length - 1.0).abs() > 0.1
+    });
+    if degenerate {
+        return false;
+    }
+
+    let mut faces = 0;
+    let mut flipped = 0;
+    for face in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = triangle(mesh, face);
+        let normal = (b - a).cross(&(c - a));
+        if normal.norm() <= f32::EPSILON {
+            continue;
+        }
+
+        let vertex_normals = face
+            .iter()
+            .map(|&i| na::Vector3::from(mesh.vertices[i as usize].normal))
+            .sum::<na::Vector3<f32>>();
+
+        faces += 1;
+        if normal.dot(&vertex_normals) < 0.0 {
+            flipped += 1;
+        }
+    }
+
+    flipped as f32 <= faces as f32 * MAX_FLIPPED_FACES
+}
+
+/// Checks that the normals are valid and, unlike face normals, vary across
+/// most faces.
+fn has_smooth_normals(mesh: &MeshData) -> bool {
+    if !has_valid_normals(mesh) {
+        return false;
+    }
+
+    let mut faces = 0;
+    let mut flat = 0;
+    for face in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = triangle(mesh, face);
+        let Some(normal) = (b - a).cross(&(c - a)).try_normalize(f32::EPSILON) else {
+            continue;
+        };
+
+        faces += 1;
+        let corners_flat = face.iter().all(|&i| {
+            let corner = na::Vector3::from(mesh.vertices[i as usize].normal).normalize();
+            normal.dot(&corner) >= FLAT_COS
+        });
+        if corners_flat {
+            flat += 1;
+        }
+    }
+
+    flat as f32 <= faces as f32 * MAX_FLAT_FACES
+}
+
+/// Splits every face into its own vertices carrying the face normal.
+fn face_normals(mesh: &MeshData) -> MeshData {
+    let mut vertices = Vec::with_capacity(mesh.indices.len());
+
+    for face in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = triangle(mesh, face);
+        let normal = (b - a)
+            .cross(&(c - a))
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(na::Vector3::zeros);
+
+        for &i in face {
+            let mut vertex = mesh.vertices[i as usize];
+            vertex.normal = normal.into();
+            vertices.push(vertex);
+        }
+    }
+
+    MeshData {
+        indices: (0..vertices.len() as u32).collect(),
+        vertices,
+        // Per vertex values no longer line up with the split vertices.
+        scalars: Default::default(),
+        ..mesh.clone()
+    }
+}
+
+/// Averages the normals of the faces around every vertex.
+fn smooth_normals(mesh: &MeshData, angle_weighted: bool) -> MeshData {
+    let mut normals = vec![na::Vector3::<f32>::zeros(); mesh.vertices.len()];
+
+    for face in mesh.indices.chunks_exact(3) {
+        let corners = triangle(mesh, face);
+        // The length is twice the face area.
+        let normal = (corners[1] - corners[0]).cross(&(corners[2] - corners[0]));
+
+        for (corner, &i) in face.iter().enumerate() {
+            let weight = if angle_weighted {
+                let p = corners[corner];
+                let e1 = corners[(corner + 1) % 3] - p;
+                let e2 = corners[(corner + 2) % 3] - p;
+                e1.angle(&e2) / normal.norm().max(f32::EPSILON)
+            } else {
+                1.0
+            };
+            normals[i as usize] += normal * weight;
+        }
+    }
+
+    let mut mesh = mesh.clone();
+    for (vertex, normal) in mesh.vertices.iter_mut().zip(normals) {
+        vertex.normal = normal
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(na::Vector3::zeros)
+            .into();
+    }
+    mesh
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::fs::test_util;
+
+    fn quad(normal: [f32; 3]) -> MeshData {
+        let mut quad = test_util::quad();
+        for vertex in &mut quad.vertices {
+            vertex.normal = normal;
+        }
+        quad
+    }
+
+    #[test]
+    fn test_generate_normals() {
+        // Smooth normals are kept.
+        let mut smooth = quad([0.0, 0.0, 1.0]);
+        smooth.vertices[2].normal = na::Vector3::new(0.3, 0.3, 1.0).normalize().into();
+        let kept = generate_normals(&smooth, NormalMode::Auto);
+        assert_eq!(kept.vertices[2].normal, smooth.vertices[2].normal);
+        assert!(keeps_normals(&smooth, NormalMode::Auto));
+
+        // Facet normals stored per corner are averaged.
+        let faceted = test_util::split_corners(&quad([0.0, 0.0, 1.0]));
+        assert!(!keeps_normals(&faceted, NormalMode::Auto));
+        assert!(keeps_normals(&faceted, NormalMode::Keep));
+
+        // Missing and flipped normals are regenerated.
+        for normal in [[0.0; 3], [0.0, 0.0, -1.0]] {
+            let fixed = generate_normals(&quad(normal), NormalMode::Auto);
+            assert!(fixed.vertices.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+        }
+
+        let flat = generate_normals(&quad([0.0; 3]), NormalMode::Face);
+        assert_eq!(flat.vertices.len(), 6);
+        assert!(flat.vertices.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+
+        // Without normals in the file, kept ones are those of the faces.
+        let missing = generate_normals(&quad([0.0; 3]), NormalMode::Keep);
+        assert_eq!(missing.vertices.len(), 6);
+        assert!(missing.vertices.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+    }
+}
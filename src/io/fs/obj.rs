@@ -1,158 +1,218 @@
-use crate::texture;
-use crate::{gpu::Gpu, model};
+use crate::io::fs::{
+    box_uv, AlphaMode, IMeshFile, MaterialData, MeshData, SamplerData, TextureData,
+};
+use crate::model;
 use anyhow::Result;
-use cfg_if::cfg_if;
-use std::io::{BufReader, Cursor};
-use std::path::PathBuf;
-use tobj;
-use wgpu::util::DeviceExt;
-
-pub async fn load_texture(
-    file_name: &str,
-    device: &wgpu::Device,
-    queue: &wgpu::Queue,
-) -> anyhow::Result<texture::Texture> {
-    let bytes = load_binary(file_name).await?;
-    texture::Texture::from_bytes(device, queue, &bytes, file_name)
+use std::path::{Path, PathBuf};
+
+pub struct ObjFile {
+    models: Vec<tobj::Model>,
+    materials: Vec<tobj::Material>,
+    directory: PathBuf,
 }
 
-pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
-    cfg_if! {
-        if #[cfg(target_arch = "wasm32")] {
-            let url = format_url(file_name);
-            let txt = reqwest::get(url)
-                .await?
-                .text()
-                .await?;
-        } else {
-            let path = std::path::Path::new(env!("OUT_DIR"))
-                .join("res")
-                .join(file_name);
-            let txt = std::fs::read_to_string(path)?;
-        }
+impl ObjFile {
+    pub fn new(path: &PathBuf) -> Result<Self> {
+        // `load_obj` already resolves `mtllib` relative to the obj file.
+        let (models, materials) = tobj::load_obj(
+            path,
+            &tobj::LoadOptions {
+                single_index: true,
+                triangulate: true,
+                ..Default::default()
+            },
+        )?;
+
+        // A missing or broken MTL file should not stop the geometry from loading.
+        let materials = materials.unwrap_or_else(|err| {
+            log::warn!("Could not load materials for {:?}: {err}", path);
+            Vec::new()
+        });
+
+        let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        Ok(Self {
+            models,
+            materials,
+            directory,
+        })
+    }
+
+    /// Resolves a texture referenced in the MTL file relative to the obj file.
+    fn resolve_texture(&self, texture: &str) -> Option<TextureData> {
+        let (texture, sampler) = texture_path(texture)?;
+        // MTL files exported on Windows frequently use backslashes.
+        let path = self.directory.join(texture.replace('\\', "/"));
+        Some(TextureData::File { path, sampler })
     }
 
-    Ok(txt)
+    fn mesh_vertices(&self, mesh: &tobj::Mesh) -> Vec<model::ModelVertex> {
+        let has_uv = !mesh.texcoords.is_empty();
+        let has_normals = !mesh.normals.is_empty();
+
+        (0..mesh.positions.len() / 3)
+            .map(|i| {
+                let position = [
+                    mesh.positions[i * 3],
+                    mesh.positions[i * 3 + 1],
+                    mesh.positions[i * 3 + 2],
+                ];
+                let tex_coord = if has_uv {
+                    [mesh.texcoords[i * 2], 1.0 - mesh.texcoords[i * 2 + 1]]
+                } else {
+                    self.get_uv(&position)
+                };
+                let normal = if has_normals {
+                    [
+                        mesh.normals[i * 3],
+                        mesh.normals[i * 3 + 1],
+                        mesh.normals[i * 3 + 2],
+                    ]
+                } else {
+                    [0.0; 3]
+                };
+                model::ModelVertex {
+                    position,
+                    tex_coord,
+                    normal,
+                    color: [1.0; 4],
+                    tangent: [0.0; 4],
+                }
+            })
+            .collect()
+    }
 }
 
-pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
-    cfg_if! {
-        if #[cfg(target_arch = "wasm32")] {
-            let url = format_url(file_name);
-            let data = reqwest::get(url)
-                .await?
-                .bytes()
-                .await?
-                .to_vec();
-        } else {
-            let path = std::path::Path::new(env!("OUT_DIR"))
-                .join("res")
-                .join(file_name);
-            let data = std::fs::read(path)?;
+impl IMeshFile for ObjFile {
+    fn get_vertices(&self) -> Result<Vec<model::ModelVertex>> {
+        Ok(self
+            .models
+            .iter()
+            .flat_map(|m| self.mesh_vertices(&m.mesh))
+            .collect())
+    }
+
+    fn get_indices(&self) -> Result<Vec<u32>> {
+        let mut indices = Vec::new();
+        let mut offset = 0;
+
+        for m in &self.models {
+            indices.extend(m.mesh.indices.iter().map(|i| i + offset));
+            offset += (m.mesh.positions.len() / 3) as u32;
         }
+
+        Ok(indices)
     }
 
-    Ok(data)
-}
+    fn get_uv(&self, vertex: &[f32; 3]) -> [f32; 2] {
+        box_uv(vertex)
+    }
+
+    fn get_meshes(&self) -> Result<Vec<MeshData>> {
+        Ok(self
+            .models
+            .iter()
+            .map(|m| MeshData {
+                name: m.name.clone(),
+                vertices: self.mesh_vertices(&m.mesh),
+                indices: m.mesh.indices.clone(),
+                material: m.mesh.material_id,
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                scalars: Default::default(),
+                has_uv: !m.mesh.texcoords.is_empty(),
+            })
+            .collect())
+    }
 
-pub struct ObjFile {}
+    fn get_materials(&self) -> Result<Vec<MaterialData>> {
+        Ok(self
+            .materials
+            .iter()
+            .map(|m| MaterialData {
+                name: m.name.clone(),
+                base_color: [m.diffuse[0], m.diffuse[1], m.diffuse[2], m.dissolve],
+                alpha_mode: if m.dissolve < 1.0 {
+                    AlphaMode::Blend
+                } else {
+                    AlphaMode::Opaque
+                },
+                diffuse_texture: self.resolve_texture(&m.diffuse_texture),
+                normal_texture: self.resolve_texture(&m.normal_texture),
+                ..Default::default()
+            })
+            .collect())
+    }
+}
 
-impl ObjFile {
-    pub fn new(_path: PathBuf) -> Result<Self> {
-        todo!("Implement Obj file")
+/// File name of a texture statement, after options such as `-bm 1.0`. The
+/// rest of the line is the name, spaces included. Of the options only
+/// `-clamp on` changes the sampler.
+fn texture_path(statement: &str) -> Option<(&str, SamplerData)> {
+    let mut sampler = SamplerData::default();
+    let mut rest = statement.trim();
+    while rest.starts_with('-') {
+        let (option, after) = split_token(rest);
+        rest = after;
+        let values = match option {
+            "-o" | "-s" | "-t" => 3,
+            "-mm" => 2,
+            _ => 1,
+        };
+        for i in 0..values {
+            let (value, after) = split_token(rest);
+            // Offsets and scales take one to three numbers.
+            if i > 0 && value.parse::<f32>().is_err() {
+                break;
+            }
+            if option == "-clamp" && value == "on" {
+                sampler.address_mode_u = wgpu::AddressMode::ClampToEdge;
+                sampler.address_mode_v = wgpu::AddressMode::ClampToEdge;
+            }
+            rest = after;
+        }
     }
+    (!rest.is_empty()).then_some((rest, sampler))
 }
 
-async fn get_models(
-    gpu: &Gpu,
-    file_name: &str,
-    layout: &wgpu::BindGroupLayout,
-) -> Result<model::Model> {
-    let (device, queue) = (&gpu.device, &gpu.queue);
-    let obj_text = load_string(file_name).await?;
-    let obj_cursor = Cursor::new(obj_text);
-    let mut obj_reader = BufReader::new(obj_cursor);
-
-    let (models, obj_materials) = tobj::load_obj_buf_async(
-        &mut obj_reader,
-        &tobj::LoadOptions {
-            single_index: true,
-            triangulate: true,
-            ..Default::default()
-        },
-        |p| async move {
-            let mat_tex = load_string(&p).await.unwrap();
-            tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_tex)))
-        },
-    )
-    .await?;
-
-    let mut materials = Vec::new();
-
-    for m in obj_materials? {
-        let diffuse_texture = load_texture(&m.diffuse_texture, device, queue).await?;
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-        });
+/// First whitespace separated token of `s` and what follows it.
+fn split_token(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    match s.find(char::is_whitespace) {
+        Some(end) => (&s[..end], s[end..].trim_start()),
+        None => (s, ""),
+    }
+}
 
-        materials.push(model::Material {
-            bind_group,
-            diffuse_texture,
-            name: m.name,
-        });
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn texture_paths_keep_spaces() {
+        let texture_path = |statement| texture_path(statement).map(|(path, _)| path);
+        assert_eq!(texture_path("my texture.png"), Some("my texture.png"));
+        assert_eq!(texture_path("-bm 0.5 bump map.png"), Some("bump map.png"));
+        assert_eq!(
+            texture_path("-o 0.5 0.5 -s 2 -clamp on  tiles 2.png "),
+            Some("tiles 2.png")
+        );
+        assert_eq!(texture_path("-mm 0 1 a.png"), Some("a.png"));
+        assert_eq!(texture_path(""), None);
+        assert_eq!(texture_path("-blendu off"), None);
     }
 
-    let meshes = models
-        .into_iter()
-        .map(|m| {
-            let vertices = (0..m.mesh.positions.len() / 3)
-                .map(|i| model::ModelVertex {
-                    position: [
-                        m.mesh.positions[i * 3],
-                        m.mesh.positions[i * 3 + 1],
-                        m.mesh.positions[i * 3 + 2],
-                    ],
-                    tex_coord: [m.mesh.texcoords[i * 2], 1.0 - m.mesh.texcoords[i * 2 + 1]],
-                    normal: [
-                        m.mesh.normals[i * 3],
-                        m.mesh.normals[i * 3 + 1],
-                        m.mesh.normals[i * 3 + 2],
-                    ],
-                })
-                .collect::<Vec<_>>();
-
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Vertex Buffer", file_name)),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Index Buffer", file_name)),
-                contents: bytemuck::cast_slice(&m.mesh.indices),
-                usage: wgpu::BufferUsages::INDEX,
-            });
-
-            model::Mesh {
-                name: file_name.to_string(),
-                vertex_buffer,
-                index_buffer,
-                material: m.mesh.material_id.unwrap_or(0),
-                num_elements: m.mesh.indices.len() as u32,
+    #[test]
+    fn clamp_maps_to_the_sampler() {
+        let sampler = |statement| texture_path(statement).unwrap().1;
+        assert_eq!(sampler("a.png"), SamplerData::default());
+        assert_eq!(sampler("-clamp off a.png"), SamplerData::default());
+        assert_eq!(
+            sampler("-s 2 -clamp on a.png"),
+            SamplerData {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                ..Default::default()
             }
-        })
-        .collect::<Vec<_>>();
-
-    Ok(model::Model { meshes, materials })
+        );
+    }
 }
@@ -0,0 +1,507 @@
+use crate::io::fs::{box_uv, IMeshFile, MeshData};
+use crate::model;
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader};
+use std::{fs::File, path::PathBuf};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum ScalarType {
+    Char,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Float,
+    Double,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self> {
+        use ScalarType::*;
+        Ok(match name {
+            "char" | "int8" => Char,
+            "uchar" | "uint8" => UChar,
+            "short" | "int16" => Short,
+            "ushort" | "uint16" => UShort,
+            "int" | "int32" => Int,
+            "uint" | "uint32" => UInt,
+            "float" | "float32" => Float,
+            "double" | "float64" => Double,
+            _ => anyhow::bail!("Unknown PLY type {name}"),
+        })
+    }
+
+    fn size(self) -> usize {
+        use ScalarType::*;
+        match self {
+            Char | UChar => 1,
+            Short | UShort => 2,
+            Int | UInt | Float => 4,
+            Double => 8,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, ScalarType),
+    List(String, ScalarType, ScalarType),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads property values from the body of the file.
+enum Body<R> {
+    /// One line at a time, into a buffer reused for the whole file.
+    Ascii {
+        reader: R,
+        line: String,
+        position: usize,
+    },
+    Binary {
+        data: Vec<u8>,
+        offset: usize,
+        big_endian: bool,
+    },
+}
+
+impl<R: BufRead> Body<R> {
+    fn read(&mut self, ty: ScalarType) -> Result<f64> {
+        match self {
+            Self::Ascii {
+                reader,
+                line,
+                position,
+            } => loop {
+                let rest = &line[*position..];
+                let token = rest.trim_start();
+                if !token.is_empty() {
+                    let end = token.find(char::is_whitespace).unwrap_or(token.len());
+                    *position += rest.len() - token.len() + end;
+                    let token = &token[..end];
+                    return token
+                        .parse()
+                        .with_context(|| format!("Invalid PLY value {token}"));
+                }
+                line.clear();
+                *position = 0;
+                anyhow::ensure!(reader.read_line(line)? > 0, "Unexpected end of PLY data");
+            },
+            Self::Binary {
+                data,
+                offset,
+                big_endian,
+            } => {
+                let size = ty.size();
+                let mut bytes = [0; 8];
+                bytes[..size].copy_from_slice(
+                    data.get(*offset..*offset + size)
+                        .context("Unexpected end of PLY data")?,
+                );
+                *offset += size;
+                if *big_endian {
+                    bytes[..size].reverse();
+                }
+
+                use ScalarType::*;
+                let [b0, b1, b2, b3, ..] = bytes;
+                Ok(match ty {
+                    Char => b0 as i8 as f64,
+                    UChar => b0 as f64,
+                    Short => i16::from_le_bytes([b0, b1]) as f64,
+                    UShort => u16::from_le_bytes([b0, b1]) as f64,
+                    Int => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    UInt => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Float => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Double => f64::from_le_bytes(bytes),
+                })
+            }
+        }
+    }
+}
+
+/// Stanford PLY file in ASCII or binary encoding.
+///
+/// Files without faces are loaded as point clouds.
+pub struct PlyFile {
+    vertices: Vec<model::ModelVertex>,
+    indices: Vec<u32>,
+    scalars: BTreeMap<String, Vec<f32>>,
+    points: bool,
+    has_uv: bool,
+}
+
+impl PlyFile {
+    pub fn new(path: &PathBuf) -> Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    fn from_reader(mut reader: impl BufRead) -> Result<Self> {
+        let (format, elements) = read_header(&mut reader)?;
+
+        let mut body = match format {
+            Format::Ascii => Body::Ascii {
+                reader,
+                line: String::new(),
+                position: 0,
+            },
+            _ => {
+                let mut data = Vec::new();
+                reader.read_to_end(&mut data)?;
+                Body::Binary {
+                    data,
+                    offset: 0,
+                    big_endian: format == Format::BinaryBigEndian,
+                }
+            }
+        };
+
+        let vertex_count = elements
+            .iter()
+            .filter(|element| element.name == "vertex")
+            .map(|element| element.count)
+            .sum::<usize>();
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut scalars: BTreeMap<String, Vec<f32>> = BTreeMap::new();
+        let mut has_faces = false;
+
+        // Values of the properties of the current element, by index.
+        let mut values = Vec::new();
+        let mut lists: Vec<Vec<f64>> = Vec::new();
+
+        for element in elements.iter().filter(|element| element.count > 0) {
+            let layout = match element.name.as_str() {
+                "vertex" => Some(VertexLayout::new(&element.properties)?),
+                _ => None,
+            };
+            // Filled in place, without looking up the scalars by name.
+            let mut columns = layout
+                .iter()
+                .flat_map(|layout| &layout.scalars)
+                .map(|(i, name)| (*i, name, scalars.remove(name).unwrap_or_default()))
+                .collect::<Vec<_>>();
+
+            let face_list = match element.name.as_str() {
+                "face" => Some(
+                    element
+                        .properties
+                        .iter()
+                        .position(|property| {
+                            matches!(property, Property::List(name, ..)
+                                if name == "vertex_indices" || name == "vertex_index")
+                        })
+                        .context("PLY face without vertex indices")?,
+                ),
+                _ => None,
+            };
+            has_faces |= face_list.is_some();
+
+            values.resize(element.properties.len(), 0.0);
+            lists.resize_with(element.properties.len(), Vec::new);
+
+            for _ in 0..element.count {
+                for (i, property) in element.properties.iter().enumerate() {
+                    match property {
+                        Property::Scalar(_, ty) => values[i] = body.read(*ty)?,
+                        Property::List(_, count_ty, item_ty) => {
+                            let count = body.read(*count_ty)? as usize;
+                            lists[i].clear();
+                            for _ in 0..count {
+                                lists[i].push(body.read(*item_ty)?);
+                            }
+                        }
+                    }
+                }
+
+                if let Some(layout) = &layout {
+                    vertices.push(layout.vertex(&values));
+                    for (i, _, column) in &mut columns {
+                        column.push(values[*i] as f32);
+                    }
+                }
+                if let Some(list) = face_list {
+                    let face = &lists[list];
+                    for &index in face {
+                        anyhow::ensure!(
+                            index >= 0.0 && (index as usize) < vertex_count,
+                            "PLY face index {index} out of range of {vertex_count} vertices"
+                        );
+                    }
+                    // Polygons are triangulated as fans.
+                    for i in 1..face.len().saturating_sub(1) {
+                        indices.extend([face[0], face[i], face[i + 1]].map(|i| i as u32));
+                    }
+                }
+            }
+
+            for (_, name, column) in columns {
+                scalars.insert(name.clone(), column);
+            }
+        }
+
+        let has_uv = elements
+            .iter()
+            .filter(|element| element.name == "vertex")
+            .flat_map(|element| &element.properties)
+            .any(|property| {
+                matches!(property, Property::Scalar(name, _)
+                    if ["u", "s", "texture_u"].contains(&name.as_str()))
+            });
+
+        let points = !has_faces;
+        if points {
+            indices = (0..vertices.len() as u32).collect();
+        }
+
+        Ok(Self {
+            vertices,
+            indices,
+            scalars,
+            points,
+            has_uv,
+        })
+    }
+}
+
+fn read_header(reader: &mut impl BufRead) -> Result<(Format, Vec<Element>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim_end() != "ply" {
+        anyhow::bail!("Not a PLY file");
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            anyhow::bail!("PLY header is not terminated");
+        }
+
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        match tokens.as_slice() {
+            ["format", "ascii", ..] => format = Some(Format::Ascii),
+            ["format", "binary_little_endian", ..] => format = Some(Format::BinaryLittleEndian),
+            ["format", "binary_big_endian", ..] => format = Some(Format::BinaryBigEndian),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse()?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_ty, item_ty, name] => elements
+                .last_mut()
+                .context("PLY property outside of an element")?
+                .properties
+                .push(Property::List(
+                    name.to_string(),
+                    ScalarType::parse(count_ty)?,
+                    ScalarType::parse(item_ty)?,
+                )),
+            ["property", ty, name] => elements
+                .last_mut()
+                .context("PLY property outside of an element")?
+                .properties
+                .push(Property::Scalar(name.to_string(), ScalarType::parse(ty)?)),
+            ["end_header"] => break,
+            _ => {}
+        }
+    }
+
+    Ok((format.context("PLY file without format")?, elements))
+}
+
+const VERTEX_PROPERTIES: &[&str] = &[
+    "x",
+    "y",
+    "z",
+    "nx",
+    "ny",
+    "nz",
+    "red",
+    "green",
+    "blue",
+    "alpha",
+    "u",
+    "v",
+    "s",
+    "t",
+    "texture_u",
+    "texture_v",
+];
+
+fn is_vertex_property(name: &str) -> bool {
+    VERTEX_PROPERTIES.contains(&name)
+}
+
+/// Where the attributes of a vertex are among the properties of its element.
+struct VertexLayout {
+    position: [usize; 3],
+    normal: [Option<usize>; 3],
+    tex_coord: [Option<usize>; 2],
+    /// With whether the value is an integer in [0, 255] rather than a float
+    /// in [0, 1].
+    color: [Option<(usize, bool)>; 4],
+    /// Other scalar properties, kept as [`MeshData::scalars`].
+    scalars: Vec<(usize, String)>,
+}
+
+impl VertexLayout {
+    fn new(properties: &[Property]) -> Result<Self> {
+        // The first of `names` the element has.
+        let find = |names: &[&str]| {
+            names.iter().find_map(|name| {
+                properties
+                    .iter()
+                    .position(|p| matches!(p, Property::Scalar(n, _) if n == name))
+            })
+        };
+        let required =
+            |name: &str| find(&[name]).with_context(|| format!("PLY vertex without {name}"));
+
+        let color = |name: &str| {
+            let i = find(&[name])?;
+            let integer = matches!(&properties[i], Property::Scalar(_, ty)
+                if !matches!(ty, ScalarType::Float | ScalarType::Double));
+            Some((i, integer))
+        };
+
+        let scalars = properties
+            .iter()
+            .enumerate()
+            .filter_map(|(i, property)| match property {
+                Property::Scalar(name, _) if !is_vertex_property(name) => Some((i, name.clone())),
+                _ => None,
+            })
+            .collect();
+
+        Ok(Self {
+            position: [required("x")?, required("y")?, required("z")?],
+            normal: [find(&["nx"]), find(&["ny"]), find(&["nz"])],
+            tex_coord: [
+                find(&["u", "s", "texture_u"]),
+                find(&["v", "t", "texture_v"]),
+            ],
+            color: [color("red"), color("green"), color("blue"), color("alpha")],
+            scalars,
+        })
+    }
+
+    /// `values` are those of every property of the element, by index.
+    fn vertex(&self, values: &[f64]) -> model::ModelVertex {
+        let get = |i: Option<usize>| i.map(|i| values[i] as f32);
+        let position = self.position.map(|i| values[i] as f32);
+
+        let normal = match self.normal.map(get) {
+            [Some(x), Some(y), Some(z)] => [x, y, z],
+            _ => [0.0; 3],
+        };
+
+        let tex_coord = match self.tex_coord.map(get) {
+            [Some(u), Some(v)] => [u, 1.0 - v],
+            _ => box_uv(&position),
+        };
+
+        let color = self.color.map(|channel| match channel {
+            Some((i, true)) => values[i] as f32 / 255.0,
+            Some((i, false)) => values[i] as f32,
+            None => 1.0,
+        });
+
+        model::ModelVertex {
+            position,
+            tex_coord,
+            normal,
+            color,
+            tangent: [0.0; 4],
+        }
+    }
+}
+
+impl IMeshFile for PlyFile {
+    fn get_vertices(&self) -> Result<Vec<model::ModelVertex>> {
+        Ok(self.vertices.clone())
+    }
+
+    fn get_indices(&self) -> Result<Vec<u32>> {
+        Ok(self.indices.clone())
+    }
+
+    fn get_uv(&self, vertex: &[f32; 3]) -> [f32; 2] {
+        box_uv(vertex)
+    }
+
+    fn get_meshes(&self) -> Result<Vec<MeshData>> {
+        let topology = if self.points {
+            wgpu::PrimitiveTopology::PointList
+        } else {
+            wgpu::PrimitiveTopology::TriangleList
+        };
+
+        Ok(vec![MeshData {
+            name: String::new(),
+            vertices: self.get_vertices()?,
+            indices: self.get_indices()?,
+            material: None,
+            topology,
+            scalars: self.scalars.clone(),
+            has_uv: self.has_uv,
+        }])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ascii(body: &str) -> Result<PlyFile> {
+        let header = "ply\n\
+            format ascii 1.0\n\
+            element vertex 4\n\
+            property float x\n\
+            property float y\n\
+            property float z\n\
+            property uchar red\n\
+            property float quality\n\
+            element face 1\n\
+            property list uchar int vertex_indices\n\
+            end_header\n";
+        PlyFile::from_reader(format!("{header}{body}").as_bytes())
+    }
+
+    const VERTICES: &str = "0 0 0 255 0.5\n1 0 0 0 1\n1 1 0 0 1\n0 1 0 0 1\n";
+
+    #[test]
+    fn ascii_quads_are_triangulated() -> Result<()> {
+        let ply = ascii(&format!("{VERTICES}4 0 1 2 3\n"))?;
+        assert_eq!(ply.indices, [0, 1, 2, 0, 2, 3]);
+        assert_eq!(ply.vertices[2].position, [1.0, 1.0, 0.0]);
+        assert_eq!(ply.vertices[0].color, [1.0; 4]);
+        assert_eq!(ply.vertices[1].color, [0.0, 1.0, 1.0, 1.0]);
+        assert_eq!(ply.scalars["quality"], [0.5, 1.0, 1.0, 1.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors() {
+        for face in ["3 0 1 4", "3 0 -1 2"] {
+            let err = ascii(&format!("{VERTICES}{face}\n")).err().unwrap();
+            assert!(err.to_string().contains("out of range"), "{face}: {err}");
+        }
+        assert!(ascii("0 0 0 255 0.5\n").is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use crate::io::fs::{weld, MeshData};
+
+use std::{collections::HashMap, fmt::Display};
+
+/// Axis aligned box around a set of points.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: na::Point3<f32>,
+    pub max: na::Point3<f32>,
+}
+
+impl Bounds {
+    /// `None` when there are no points.
+    pub fn from_points(points: impl IntoIterator<Item = na::Point3<f32>>) -> Option<Self> {
+        points.into_iter().fold(None, |bounds, point| {
+            Some(match bounds {
+                Some(Self { min, max }) => Self {
+                    min: min.inf(&point),
+                    max: max.sup(&point),
+                },
+                None => Self {
+                    min: point,
+                    max: point,
+                },
+            })
+        })
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    pub fn center(&self) -> na::Point3<f32> {
+        na::center(&self.min, &self.max)
+    }
+
+    pub fn size(&self) -> na::Vector3<f32> {
+        self.max - self.min
+    }
+
+    pub fn corners(&self) -> [na::Point3<f32>; 8] {
+        let (min, max) = (self.min, self.max);
+        std::array::from_fn(|i| {
+            na::Point3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        })
+    }
+
+    /// Box around this one moved by `isometry`.
+    pub fn transform(&self, isometry: &na::Isometry3<f32>) -> Self {
+        Self::from_points(self.corners().map(|corner| isometry * corner))
+            .expect("a box has corners")
+    }
+
+    /// Radius of the sphere around the box, centered on [`Bounds::center`].
+    pub fn radius(&self) -> f32 {
+        self.size().norm() * 0.5
+    }
+}
+
+/// Sphere around a set of points.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: na::Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Centered on `bounds`, the box around `points`, reaching the farthest
+    /// one. Often tighter than [`Bounds::radius`].
+    pub fn around(bounds: &Bounds, points: impl IntoIterator<Item = na::Point3<f32>>) -> Self {
+        let center = bounds.center();
+        let radius = points
+            .into_iter()
+            .map(|point| na::distance(&center, &point))
+            .fold(0.0, f32::max);
+        Self { center, radius }
+    }
+
+    /// Smallest sphere around both.
+    pub fn union(&self, other: &Self) -> Self {
+        let offset = other.center - self.center;
+        let distance = offset.norm();
+        if distance + other.radius <= self.radius {
+            return *self;
+        }
+        if distance + self.radius <= other.radius {
+            return *other;
+        }
+        let radius = (distance + self.radius + other.radius) / 2.0;
+        Self {
+            center: self.center + offset * ((radius - self.radius) / distance),
+            radius,
+        }
+    }
+
+    pub fn transform(&self, isometry: &na::Isometry3<f32>) -> Self {
+        Self {
+            center: isometry * self.center,
+            radius: self.radius,
+        }
+    }
+}
+
+/// Summary of meshes printed by `void info`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshStats {
+    pub meshes: usize,
+    pub vertices: usize,
+    pub triangles: usize,
+    /// Vertices of point clouds.
+    pub points: usize,
+    pub bounds: Option<Bounds>,
+    /// Edges used by a single triangle.
+    pub boundary_edges: usize,
+    /// Edges shared by more than two triangles.
+    pub non_manifold_edges: usize,
+}
+
+impl MeshStats {
+    /// Collects the stats of `meshes`, already in world space. Edges are
+    /// matched by position so that split normals or seams don't open them.
+    pub fn new(meshes: &[MeshData]) -> Self {
+        let vertices = meshes
+            .iter()
+            .flat_map(|mesh| mesh.vertices.iter().copied())
+            .collect::<Vec<_>>();
+        let (positions, _) = weld::weld_positions(&vertices, 1e-5);
+
+        let mut stats = Self {
+            meshes: meshes.len(),
+            vertices: vertices.len(),
+            bounds: Bounds::from_points(vertices.iter().map(|v| v.position.into())),
+            ..Default::default()
+        };
+
+        let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut offset = 0;
+        for mesh in meshes {
+            match mesh.topology {
+                wgpu::PrimitiveTopology::TriangleList => {
+                    for face in mesh.indices.chunks_exact(3) {
+                        let [a, b, c] =
+                            [face[0], face[1], face[2]].map(|i| positions[offset + i as usize]);
+                        // Collapsed triangles don't close anything.
+                        if a == b || b == c || c == a {
+                            continue;
+                        }
+                        stats.triangles += 1;
+                        for (from, to) in [(a, b), (b, c), (c, a)] {
+                            *edges.entry((from.min(to), from.max(to))).or_default() += 1;
+                        }
+                    }
+                }
+                _ => stats.points += mesh.vertices.len(),
+            }
+            offset += mesh.vertices.len();
+        }
+
+        stats.boundary_edges = edges.values().filter(|&&count| count == 1).count();
+        stats.non_manifold_edges = edges.values().filter(|&&count| count > 2).count();
+        stats
+    }
+
+    /// Every edge is shared by exactly two triangles.
+    pub fn is_watertight(&self) -> bool {
+        self.triangles > 0 && self.boundary_edges == 0 && self.non_manifold_edges == 0
+    }
+}
+
+impl Display for MeshStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "meshes: {}", self.meshes)?;
+        writeln!(f, "vertices: {}", self.vertices)?;
+        writeln!(f, "triangles: {}", self.triangles)?;
+        if self.points > 0 {
+            writeln!(f, "points: {}", self.points)?;
+        }
+        match &self.bounds {
+            Some(bounds) => {
+                let [min, max, size] = [bounds.min.coords, bounds.max.coords, bounds.size()];
+                writeln!(
+                    f,
+                    "bounds: ({:.4}, {:.4}, {:.4}) to ({:.4}, {:.4}, {:.4}), size {:.4} x {:.4} x {:.4}",
+                    min.x, min.y, min.z, max.x, max.y, max.z, size.x, size.y, size.z
+                )?;
+            }
+            None => writeln!(f, "bounds: empty")?,
+        }
+        if self.is_watertight() {
+            write!(f, "watertight: yes")
+        } else if self.triangles == 0 {
+            write!(f, "watertight: no (no triangles)")
+        } else {
+            write!(
+                f,
+                "watertight: no ({} boundary edges, {} non-manifold edges)",
+                self.boundary_edges, self.non_manifold_edges
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::fs::test_util::{cube, split_corners};
+
+    #[test]
+    fn closed_cube_is_watertight() {
+        let stats = MeshStats::new(&[split_corners(&cube(6))]);
+        assert_eq!(stats.triangles, 12);
+        assert_eq!(stats.vertices, 36);
+        assert!(stats.is_watertight());
+
+        let bounds = stats.bounds.unwrap();
+        assert_eq!(bounds.min, na::Point3::origin());
+        assert_eq!(bounds.max, na::Point3::new(1.0, 1.0, 1.0));
+        assert_eq!(bounds.center(), na::Point3::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn open_cube_has_boundary_edges() {
+        let stats = MeshStats::new(&[split_corners(&cube(5))]);
+        assert_eq!(stats.triangles, 10);
+        assert_eq!(stats.boundary_edges, 4);
+        assert_eq!(stats.non_manifold_edges, 0);
+        assert!(!stats.is_watertight());
+    }
+
+    #[test]
+    fn cube_split_in_meshes_is_watertight() {
+        let mut top = split_corners(&cube(6));
+        let bottom = MeshData {
+            vertices: top.vertices.split_off(18),
+            indices: (0..18).collect(),
+            ..top.clone()
+        };
+        top.indices.truncate(18);
+        assert!(MeshStats::new(&[top, bottom]).is_watertight());
+    }
+
+    #[test]
+    fn spheres_contain_their_points() {
+        let points = [
+            na::Point3::new(0.0, 0.5, 0.0),
+            na::Point3::new(2.0, 0.5, 0.0),
+            na::Point3::new(1.0, 0.0, 0.0),
+            na::Point3::new(1.0, 1.0, 0.0),
+        ];
+        let bounds = Bounds::from_points(points).unwrap();
+        let sphere = BoundingSphere::around(&bounds, points);
+        assert_eq!(sphere.center, na::Point3::new(1.0, 0.5, 0.0));
+        assert_eq!(sphere.radius, 1.0);
+        assert!(sphere.radius < bounds.radius());
+
+        let other = BoundingSphere {
+            center: na::Point3::new(5.0, 0.5, 0.0),
+            radius: 1.0,
+        };
+        let union = sphere.union(&other);
+        for sphere in [sphere, other] {
+            let reach = na::distance(&union.center, &sphere.center) + sphere.radius;
+            assert!(reach <= union.radius + 1e-5);
+        }
+        assert_eq!(sphere.union(&union), union);
+    }
+}
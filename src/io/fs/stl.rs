@@ -1,74 +1,123 @@
-use crate::io::fs::IMeshFile;
+use crate::io::fs::{box_uv, weld, IMeshFile};
 use crate::model;
 
 use anyhow::Result;
 use std::{fs::OpenOptions, path::PathBuf};
-use stl_io::IndexedMesh;
 
+/// STL file as an indexed mesh, corners closer than the default weld epsilon
+/// share a vertex. The facet normals can't be carried by shared vertices, the
+/// loader generates normals, see [`crate::resource::LoadOptions`].
 pub struct StlFile {
-    mesh: IndexedMesh,
+    vertices: Vec<model::ModelVertex>,
+    indices: Vec<u32>,
 }
 
 impl StlFile {
     pub fn new(path: &PathBuf) -> Result<Self> {
-        let mut file = OpenOptions::new().read(true).open(&path)?;
+        let mut file = OpenOptions::new().read(true).open(path)?;
         let mesh = stl_io::read_stl(&mut file)?;
-        Ok(Self { mesh })
+
+        let corners = mesh
+            .faces
+            .iter()
+            .flat_map(|face| face.vertices)
+            .map(|i| {
+                let position = mesh.vertices[i].into();
+                model::ModelVertex {
+                    position,
+                    tex_coord: box_uv(&position),
+                    normal: [0.0; 3],
+                    color: [1.0; 4],
+                    tangent: [0.0; 4],
+                }
+            })
+            .collect::<Vec<_>>();
+        let epsilon = weld::WeldOptions::default().epsilon;
+        let (positions, count) = weld::weld_positions(&corners, epsilon);
+
+        // The first corner at every position becomes its vertex.
+        let mut vertices = Vec::with_capacity(count);
+        for (corner, &position) in corners.iter().zip(&positions) {
+            if position == vertices.len() {
+                vertices.push(*corner);
+            }
+        }
+        let indices = positions.into_iter().map(|i| i as u32).collect();
+        Ok(Self { vertices, indices })
     }
 }
 
 impl IMeshFile for StlFile {
     fn get_indices(&self) -> Result<Vec<u32>> {
-        let mesh = &self.mesh;
-        let mut indices = Vec::with_capacity(mesh.vertices.len());
-        let total_faces = mesh.faces.len();
-
-        for i in 0..total_faces {
-            indices.push((i * 3) as u32);
-            indices.push((i * 3 + 1) as u32);
-            indices.push((i * 3 + 2) as u32);
-        }
-        Ok(indices)
+        Ok(self.indices.clone())
     }
 
     fn get_vertices(&self) -> Result<Vec<model::ModelVertex>> {
-        let mesh = &self.mesh;
+        Ok(self.vertices.clone())
+    }
 
-        let mut model_vertices = Vec::with_capacity(mesh.vertices.len());
-        let mesh_vertices = &mesh.vertices;
+    fn get_uv(&self, vertex: &[f32; 3]) -> [f32; 2] {
+        box_uv(vertex)
+    }
+}
 
-        for face in mesh.faces.iter() {
-            let indices = face.vertices;
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::fs::normals::NormalMode;
+    use crate::resource::{load_world_meshes, LoadOptions};
 
-            for i in 0..3 {
-                let idx = indices[i];
-                let vertex = model::ModelVertex {
-                    position: mesh_vertices[idx].into(),
-                    normal: face.normal.into(),
-                    tex_coord: self.get_uv(&mesh_vertices[idx].into()),
-                };
+    /// Square in the xy plane whose facet normals lean towards y.
+    const TILTED: &str = "solid tilted
+facet normal 0 0.6 0.8
+outer loop
+vertex 0 0 0
+vertex 1 0 0
+vertex 1 1 0
+endloop
+endfacet
+facet normal 0 0.6 0.8
+outer loop
+vertex 0 0 0
+vertex 1 1 0
+vertex 0 1 0
+endloop
+endfacet
+endsolid tilted
+";
 
-                model_vertices.push(vertex);
-            }
-        }
+    #[test]
+    fn corners_are_shared_and_welded_by_crease_angle() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join("void_test_stl_normals");
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("tilted.stl");
+        std::fs::write(&path, TILTED)?;
 
-        Ok(model_vertices)
-    }
+        let file = StlFile::new(&path)?;
+        assert_eq!(file.get_vertices()?.len(), 4);
+        assert_eq!(file.get_indices()?, [0, 1, 2, 0, 2, 3]);
 
-    fn get_uv(&self, vertex: &[f32; 3]) -> [f32; 2] {
-        let abs_x = vertex[0].abs();
-        let abs_y = vertex[1].abs();
-        let abs_z = vertex[2].abs();
+        // Without a weld the square stays indexed.
+        let options = LoadOptions {
+            weld: None,
+            ..Default::default()
+        };
+        let (meshes, _) = load_world_meshes(path.clone(), options)?;
+        assert_eq!(meshes[0].vertices.len(), 4);
 
-        if abs_x >= abs_y && abs_x >= abs_z {
-            // Project on the yz plane
-            [(vertex[1] + 1.0) * 0.5, (vertex[2] + 1.0) * 0.5]
-        } else if abs_y >= abs_x && abs_y >= abs_z {
-            // Project on the xz plane
-            [(vertex[0] + 1.0) * 0.5, (vertex[2] + 1.0) * 0.5]
-        } else {
-            // Project on the xy plane
-            [(vertex[0] + 1.0) * 0.5, (vertex[1] + 1.0) * 0.5]
+        // The facet normals are not read, those of the faces are used.
+        for (normal_mode, vertices) in [(NormalMode::Auto, 4), (NormalMode::Keep, 6)] {
+            let options = LoadOptions {
+                normal_mode,
+                ..Default::default()
+            };
+            let (meshes, _) = load_world_meshes(path.clone(), options)?;
+            assert_eq!(meshes[0].vertices.len(), vertices);
+            assert!(meshes[0]
+                .vertices
+                .iter()
+                .all(|v| v.normal == [0.0, 0.0, 1.0]));
         }
+        Ok(())
     }
 }
@@ -0,0 +1,117 @@
+use crate::io::fs::MeshData;
+
+use std::collections::HashMap;
+
+/// Per corner view of an indexed mesh for the MikkTSpace generator.
+struct Geometry<'a> {
+    mesh: &'a MeshData,
+    tangents: Vec<[f32; 4]>,
+}
+
+impl<'a> Geometry<'a> {
+    fn vertex(&self, face: usize, vert: usize) -> &crate::model::ModelVertex {
+        &self.mesh.vertices[self.mesh.indices[face * 3 + vert] as usize]
+    }
+}
+
+impl<'a> bevy_mikktspace::Geometry for Geometry<'a> {
+    fn num_faces(&self) -> usize {
+        self.mesh.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).position
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.vertex(face, vert).tex_coord
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[face * 3 + vert] = tangent;
+    }
+}
+
+/// Returns `mesh` with MikkTSpace tangents, matching the tangent space normal
+/// maps are baked in by most tools.
+///
+/// Vertices shared by corners that end up with different tangents, such as
+/// on UV mirror seams, are split.
+pub fn generate_tangents(mesh: &MeshData) -> MeshData {
+    if mesh.topology != wgpu::PrimitiveTopology::TriangleList || mesh.indices.is_empty() {
+        return mesh.clone();
+    }
+
+    let mut geometry = Geometry {
+        mesh,
+        tangents: vec![[0.0; 4]; mesh.indices.len() / 3 * 3],
+    };
+    if !bevy_mikktspace::generate_tangents(&mut geometry) {
+        log::warn!("Could not generate tangents for {}", mesh.name);
+        return mesh.clone();
+    }
+    let tangents = geometry.tangents;
+
+    let mut result = mesh.clone();
+    let mut assigned = vec![false; mesh.vertices.len()];
+    let mut splits: HashMap<(u32, [u32; 4]), u32> = HashMap::new();
+
+    for (index, tangent) in result.indices.iter_mut().zip(tangents) {
+        let vertex = *index as usize;
+
+        if !assigned[vertex] {
+            assigned[vertex] = true;
+            result.vertices[vertex].tangent = tangent;
+        } else if result.vertices[vertex].tangent != tangent {
+            let key = (*index, tangent.map(f32::to_bits));
+            *index = *splits.entry(key).or_insert_with(|| {
+                let mut split = mesh.vertices[vertex];
+                split.tangent = tangent;
+                result.vertices.push(split);
+                for values in result.scalars.values_mut() {
+                    if let Some(&value) = values.get(vertex) {
+                        values.push(value);
+                    }
+                }
+                result.vertices.len() as u32 - 1
+            });
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::fs::test_util::quad;
+
+    #[test]
+    fn test_generate_tangents() {
+        // A quad facing +z with u along +x and v along -y, as loaded from
+        // files with the texture origin at the top left.
+        let mut mesh = quad();
+        for vertex in &mut mesh.vertices {
+            let [x, y, _] = vertex.position;
+            vertex.tex_coord = [x, 1.0 - y];
+            vertex.normal = [0.0, 0.0, 1.0];
+        }
+        mesh.has_uv = true;
+
+        let mesh = generate_tangents(&mesh);
+        assert_eq!(mesh.vertices.len(), 4);
+        for vertex in &mesh.vertices {
+            let [x, y, z, w] = vertex.tangent;
+            assert!((x - 1.0).abs() < 1e-5 && y.abs() < 1e-5 && z.abs() < 1e-5);
+            assert_eq!(w.abs(), 1.0);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Meshes shared by the tests of the mesh processing modules.
+
+use crate::io::fs::MeshData;
+use crate::model;
+
+/// Vertex at `position` with every other attribute zero, colored white.
+pub fn vertex(position: [f32; 3]) -> model::ModelVertex {
+    model::ModelVertex {
+        position,
+        tex_coord: [0.0; 2],
+        normal: [0.0; 3],
+        color: [1.0; 4],
+        tangent: [0.0; 4],
+    }
+}
+
+/// Unnamed triangle list without material or texture coordinates.
+pub fn mesh(vertices: Vec<model::ModelVertex>, indices: Vec<u32>) -> MeshData {
+    MeshData {
+        name: String::new(),
+        vertices,
+        indices,
+        material: None,
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        scalars: Default::default(),
+        has_uv: false,
+    }
+}
+
+/// Unit quad in the xy plane facing +z.
+pub fn quad() -> MeshData {
+    let positions = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ];
+    mesh(
+        positions.into_iter().map(vertex).collect(),
+        vec![0, 1, 2, 0, 2, 3],
+    )
+}
+
+/// The first `sides` sides of the unit cube, two counter clockwise triangles
+/// each, sharing the 8 corners.
+pub fn cube(sides: usize) -> MeshData {
+    let corners = (0..8)
+        .map(|i: u32| vertex([0, 1, 2].map(|axis| (i >> axis & 1) as f32)))
+        .collect();
+    #[rustfmt::skip]
+    let quads: [[u32; 4]; 6] = [
+        [0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4],
+        [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5],
+    ];
+    let indices = quads[..sides]
+        .iter()
+        .flat_map(|[a, b, c, d]| [*a, *b, *c, *a, *c, *d])
+        .collect();
+    mesh(corners, indices)
+}
+
+/// `mesh` with a separate vertex per face corner, as STL files have.
+pub fn split_corners(mesh: &MeshData) -> MeshData {
+    MeshData {
+        vertices: mesh
+            .indices
+            .iter()
+            .map(|&i| mesh.vertices[i as usize])
+            .collect(),
+        indices: (0..mesh.indices.len() as u32).collect(),
+        ..mesh.clone()
+    }
+}
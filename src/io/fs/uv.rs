@@ -0,0 +1,432 @@
+use crate::io::fs::MeshData;
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fmt::Display;
+
+/// How texture coordinates are generated for meshes that have none.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UvMode {
+    /// Projection onto the plane of the two longest bounding box axes.
+    Planar,
+    /// Angle around and height along the longest bounding box axis.
+    Cylindrical,
+    /// Longitude and latitude around the bounding box center.
+    Spherical,
+    /// Triangle level triplanar mapping: every face is projected along the
+    /// axis closest to its normal. Blending the three projections per pixel
+    /// cannot be stored in vertex texture coordinates.
+    #[default]
+    Box,
+    /// Faces are grouped into flat charts which are packed into the unit square.
+    Charts,
+}
+
+impl UvMode {
+    pub const ALL: [UvMode; 5] = [
+        Self::Planar,
+        Self::Cylindrical,
+        Self::Spherical,
+        Self::Box,
+        Self::Charts,
+    ];
+}
+
+impl Display for UvMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Planar => "Planar",
+            Self::Cylindrical => "Cylindrical",
+            Self::Spherical => "Spherical",
+            Self::Box => "Box (triplanar)",
+            Self::Charts => "Charts",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Returns `mesh` with texture coordinates generated according to `mode`.
+///
+/// Projections are normalized to the mesh bounding box, so the result does
+/// not depend on the size or position of the model. Vertices are split where
+/// faces sharing them need different coordinates, e.g. on wrap around seams.
+pub fn generate_uvs(mesh: &MeshData, mode: UvMode) -> MeshData {
+    if mesh.topology != wgpu::PrimitiveTopology::TriangleList || mesh.indices.is_empty() {
+        return mesh.clone();
+    }
+
+    let bounds = Bounds::new(mesh);
+    let uvs = match mode {
+        UvMode::Planar => planar(mesh, &bounds),
+        UvMode::Cylindrical => wrap_seams(cylindrical(mesh, &bounds)),
+        UvMode::Spherical => wrap_seams(spherical(mesh, &bounds)),
+        UvMode::Box => box_projection(mesh, &bounds),
+        UvMode::Charts => charts(mesh),
+    };
+
+    with_corner_uvs(mesh, &uvs)
+}
+
+struct Bounds {
+    min: na::Vector3<f32>,
+    extent: na::Vector3<f32>,
+}
+
+impl Bounds {
+    fn new(mesh: &MeshData) -> Self {
+        let mut min = na::Vector3::repeat(f32::MAX);
+        let mut max = na::Vector3::repeat(f32::MIN);
+        for vertex in &mesh.vertices {
+            let position = na::Vector3::from(vertex.position);
+            min = min.inf(&position);
+            max = max.sup(&position);
+        }
+        Self {
+            min,
+            extent: max - min,
+        }
+    }
+
+    fn center(&self) -> na::Vector3<f32> {
+        self.min + self.extent / 2.0
+    }
+
+    /// Position in the bounding box, scaled uniformly so the longest side is 1.
+    fn normalize(&self, position: &na::Vector3<f32>) -> na::Vector3<f32> {
+        (position - self.min) / self.extent.max().max(f32::EPSILON)
+    }
+
+    /// Axes sorted from the longest to the shortest extent.
+    fn axes(&self) -> [usize; 3] {
+        let mut axes = [0, 1, 2];
+        axes.sort_by(|&a, &b| self.extent[b].total_cmp(&self.extent[a]));
+        axes
+    }
+}
+
+fn position(mesh: &MeshData, corner: usize) -> na::Vector3<f32> {
+    na::Vector3::from(mesh.vertices[mesh.indices[corner] as usize].position)
+}
+
+/// Not normalized, the length is twice the face area.
+fn face_normal(mesh: &MeshData, face: usize) -> na::Vector3<f32> {
+    let [a, b, c] = [0, 1, 2].map(|i| position(mesh, face * 3 + i));
+    (b - a).cross(&(c - a))
+}
+
+fn planar(mesh: &MeshData, bounds: &Bounds) -> Vec<[f32; 2]> {
+    let [u, v, _] = bounds.axes();
+    (0..mesh.indices.len())
+        .map(|corner| {
+            let p = bounds.normalize(&position(mesh, corner));
+            [p[u], 1.0 - p[v]]
+        })
+        .collect()
+}
+
+fn cylindrical(mesh: &MeshData, bounds: &Bounds) -> Vec<[f32; 2]> {
+    let [axis, ..] = bounds.axes();
+    let (x, y) = ((axis + 1) % 3, (axis + 2) % 3);
+    let center = bounds.center();
+    let height = bounds.extent[axis].max(f32::EPSILON);
+
+    (0..mesh.indices.len())
+        .map(|corner| {
+            let p = position(mesh, corner);
+            let d = p - center;
+            let angle = d[y].atan2(d[x]);
+            [
+                angle / (2.0 * PI) + 0.5,
+                1.0 - (p[axis] - bounds.min[axis]) / height,
+            ]
+        })
+        .collect()
+}
+
+fn spherical(mesh: &MeshData, bounds: &Bounds) -> Vec<[f32; 2]> {
+    let center = bounds.center();
+
+    (0..mesh.indices.len())
+        .map(|corner| {
+            let d = position(mesh, corner) - center;
+            let latitude = match d.try_normalize(f32::EPSILON) {
+                Some(d) => d.y.clamp(-1.0, 1.0).acos() / PI,
+                None => 0.5,
+            };
+            [d.z.atan2(d.x) / (2.0 * PI) + 0.5, latitude]
+        })
+        .collect()
+}
+
+/// Moves the corners of faces crossing the `u` seam next to the rest of the
+/// face, up to 1.5. File textures and the default texture repeat, see
+/// [`crate::io::fs::SamplerData`].
+fn wrap_seams(mut uvs: Vec<[f32; 2]>) -> Vec<[f32; 2]> {
+    for face in uvs.chunks_exact_mut(3) {
+        let min = face.iter().map(|uv| uv[0]).fold(f32::MAX, f32::min);
+        let max = face.iter().map(|uv| uv[0]).fold(f32::MIN, f32::max);
+        if max - min > 0.5 {
+            for uv in face.iter_mut().filter(|uv| uv[0] < 0.5) {
+                uv[0] += 1.0;
+            }
+        }
+    }
+    uvs
+}
+
+fn box_projection(mesh: &MeshData, bounds: &Bounds) -> Vec<[f32; 2]> {
+    let mut uvs = Vec::with_capacity(mesh.indices.len());
+
+    for face in 0..mesh.indices.len() / 3 {
+        let normal = face_normal(mesh, face);
+        let axis = normal.iamax();
+        let (u, v) = match axis {
+            0 => (2, 1),
+            1 => (0, 2),
+            _ => (0, 1),
+        };
+        // Keep the texture from being mirrored on the back faces.
+        let flip = normal[axis] < 0.0;
+
+        for corner in face * 3..face * 3 + 3 {
+            let p = bounds.normalize(&position(mesh, corner));
+            let s = if flip { 1.0 - p[u] } else { p[u] };
+            uvs.push([s, 1.0 - p[v]]);
+        }
+    }
+
+    uvs
+}
+
+/// Faces with a normal further than this from their chart's direction start a new chart.
+const CHART_ANGLE: f32 = PI / 4.0;
+
+/// Splits the mesh into connected charts of faces facing roughly the same way,
+/// projects every chart onto its own plane and packs them into the unit square.
+fn charts(mesh: &MeshData) -> Vec<[f32; 2]> {
+    let face_count = mesh.indices.len() / 3;
+    let normals = (0..face_count)
+        .map(|face| face_normal(mesh, face))
+        .collect::<Vec<_>>();
+
+    // Faces sharing an edge are neighbours.
+    let mut edges: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+    for face in 0..face_count {
+        for i in 0..3 {
+            let a = mesh.indices[face * 3 + i];
+            let b = mesh.indices[face * 3 + (i + 1) % 3];
+            edges.entry((a.min(b), a.max(b))).or_default().push(face);
+        }
+    }
+    let mut neighbours = vec![Vec::new(); face_count];
+    for faces in edges.values() {
+        for &a in faces {
+            for &b in faces {
+                if a != b {
+                    neighbours[a].push(b);
+                }
+            }
+        }
+    }
+
+    // Grow charts from seed faces while the normals stay close to the seed.
+    let min_cos = CHART_ANGLE.cos();
+    let mut chart_of = vec![usize::MAX; face_count];
+    let mut charts: Vec<Vec<usize>> = Vec::new();
+    for seed in 0..face_count {
+        if chart_of[seed] != usize::MAX {
+            continue;
+        }
+        let direction = normals[seed]
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(na::Vector3::z);
+        let chart = charts.len();
+        let mut faces = vec![seed];
+        let mut stack = vec![seed];
+        chart_of[seed] = chart;
+
+        while let Some(face) = stack.pop() {
+            for &next in &neighbours[face] {
+                let facing = normals[next]
+                    .try_normalize(f32::EPSILON)
+                    .map_or(true, |n| n.dot(&direction) >= min_cos);
+                if chart_of[next] == usize::MAX && facing {
+                    chart_of[next] = chart;
+                    faces.push(next);
+                    stack.push(next);
+                }
+            }
+        }
+        charts.push(faces);
+    }
+
+    // Project every chart on the plane of its average normal, in world units
+    // so that all charts keep the same texel density.
+    let mut uvs = vec![[0.0; 2]; mesh.indices.len()];
+    let mut islands = Vec::with_capacity(charts.len());
+    for (chart, faces) in charts.iter().enumerate() {
+        let normal = faces
+            .iter()
+            .map(|&face| normals[face])
+            .sum::<na::Vector3<f32>>()
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(na::Vector3::z);
+        let reference = if normal.x.abs() < 0.9 {
+            na::Vector3::x()
+        } else {
+            na::Vector3::y()
+        };
+        let tangent = (reference - normal * normal.dot(&reference)).normalize();
+        let bitangent = normal.cross(&tangent);
+
+        let mut min = [f32::MAX; 2];
+        let mut max = [f32::MIN; 2];
+        for &face in faces {
+            for corner in face * 3..face * 3 + 3 {
+                let p = position(mesh, corner);
+                let uv = [p.dot(&tangent), p.dot(&bitangent)];
+                for axis in 0..2 {
+                    min[axis] = min[axis].min(uv[axis]);
+                    max[axis] = max[axis].max(uv[axis]);
+                }
+                uvs[corner] = uv;
+            }
+        }
+        islands.push(Island {
+            chart,
+            min,
+            size: [max[0] - min[0], max[1] - min[1]],
+            offset: [0.0; 2],
+        });
+    }
+
+    let scale = pack(&mut islands);
+    for island in &islands {
+        for &face in &charts[island.chart] {
+            for uv in &mut uvs[face * 3..face * 3 + 3] {
+                *uv =
+                    [0, 1].map(|axis| (uv[axis] - island.min[axis] + island.offset[axis]) * scale);
+            }
+        }
+    }
+
+    uvs
+}
+
+struct Island {
+    chart: usize,
+    min: [f32; 2],
+    size: [f32; 2],
+    /// Position in the atlas, in chart units.
+    offset: [f32; 2],
+}
+
+/// Places the islands on shelves sorted by height.
+///
+/// Returns the scale that fits the packed atlas into the unit square.
+fn pack(islands: &mut [Island]) -> f32 {
+    let area = islands
+        .iter()
+        .map(|island| island.size[0] * island.size[1])
+        .sum::<f32>();
+    let padding = area.sqrt() * 0.01;
+    let padded_area = islands
+        .iter()
+        .map(|island| (island.size[0] + padding) * (island.size[1] + padding))
+        .sum::<f32>();
+    let widest = islands
+        .iter()
+        .map(|island| island.size[0] + padding)
+        .fold(0.0, f32::max);
+    let width = padded_area.sqrt().max(widest) + padding;
+
+    islands.sort_by(|a, b| b.size[1].total_cmp(&a.size[1]));
+
+    let (mut x, mut y, mut shelf) = (padding, padding, 0.0f32);
+    for island in islands.iter_mut() {
+        if x + island.size[0] + padding > width && x > padding {
+            x = padding;
+            y += shelf;
+            shelf = 0.0;
+        }
+        island.offset = [x, y];
+        x += island.size[0] + padding;
+        shelf = shelf.max(island.size[1] + padding);
+    }
+
+    1.0 / width.max(y + shelf).max(f32::EPSILON)
+}
+
+/// Builds a mesh with one vertex per distinct vertex and texture coordinate pair.
+fn with_corner_uvs(mesh: &MeshData, uvs: &[[f32; 2]]) -> MeshData {
+    let mut vertices = Vec::new();
+    let mut sources = Vec::new();
+    let mut ids: HashMap<(u32, [u32; 2]), u32> = HashMap::new();
+
+    let indices = mesh
+        .indices
+        .iter()
+        .zip(uvs)
+        .map(|(&index, uv)| {
+            *ids.entry((index, uv.map(f32::to_bits))).or_insert_with(|| {
+                let mut vertex = mesh.vertices[index as usize];
+                vertex.tex_coord = *uv;
+                vertices.push(vertex);
+                sources.push(index as usize);
+                vertices.len() as u32 - 1
+            })
+        })
+        .collect();
+
+    let scalars = mesh
+        .scalars
+        .iter()
+        .filter(|(_, values)| values.len() == mesh.vertices.len())
+        .map(|(name, values)| (name.clone(), sources.iter().map(|&i| values[i]).collect()))
+        .collect();
+
+    MeshData {
+        vertices,
+        indices,
+        scalars,
+        has_uv: true,
+        ..mesh.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::fs::test_util::cube;
+
+    #[test]
+    fn test_generate_uvs() {
+        let mesh = cube(6);
+
+        for mode in UvMode::ALL {
+            let unwrapped = generate_uvs(&mesh, mode);
+            assert!(unwrapped.has_uv);
+            assert_eq!(unwrapped.indices.len(), mesh.indices.len(), "{mode}");
+            // Seams wrapped past 1 still tile correctly.
+            let max = if matches!(mode, UvMode::Cylindrical | UvMode::Spherical) {
+                1.5
+            } else {
+                1.0
+            };
+            for vertex in &unwrapped.vertices {
+                let [u, v] = vertex.tex_coord;
+                assert!(
+                    (0.0..=max).contains(&u) && (0.0..=1.0).contains(&v),
+                    "{mode}"
+                );
+            }
+        }
+
+        // Every side of the cube becomes its own chart.
+        let charts = generate_uvs(&mesh, UvMode::Charts);
+        assert_eq!(charts.vertices.len(), 24);
+        // Corners shared by sides projected along different axes are split.
+        let box_mapped = generate_uvs(&mesh, UvMode::Box);
+        assert!(box_mapped.vertices.len() > mesh.vertices.len());
+    }
+}
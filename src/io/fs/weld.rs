@@ -0,0 +1,222 @@
+use crate::io::fs::MeshData;
+use crate::model;
+
+use std::collections::HashMap;
+
+/// Controls which vertices [`weld`] merges.
+#[derive(Clone, Copy, Debug)]
+pub struct WeldOptions {
+    /// Positions closer than this are treated as the same point.
+    pub epsilon: f32,
+    /// Faces meeting at a larger angle, in radians, keep separate vertices
+    /// so that hard edges stay sharp.
+    pub crease_angle: f32,
+}
+
+impl Default for WeldOptions {
+    fn default() -> Self {
+        Self {
+            epsilon: 1e-5,
+            crease_angle: 30f32.to_radians(),
+        }
+    }
+}
+
+/// Largest difference between the components of file normals that are merged.
+const NORMAL_EPSILON: f32 = 1e-4;
+
+/// Merges the vertices of a triangle mesh that share a position.
+///
+/// Corners around a position are grouped by face normal, every group becomes
+/// one vertex with the area weighted normal of its faces. With
+/// `keep_normals` they are grouped by their own normal instead, which is kept.
+/// Corners with different texture coordinates or colors are never merged.
+pub fn weld(mesh: &MeshData, options: &WeldOptions, keep_normals: bool) -> MeshData {
+    if mesh.topology != wgpu::PrimitiveTopology::TriangleList {
+        return mesh.clone();
+    }
+
+    let (positions, position_count) = weld_positions(&mesh.vertices, options.epsilon);
+
+    let face_normals = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|face| {
+            let [a, b, c] = [face[0], face[1], face[2]]
+                .map(|i| na::Vector3::from(mesh.vertices[i as usize].position));
+            // Not normalized, the length is twice the face area.
+            (b - a).cross(&(c - a))
+        })
+        .collect::<Vec<_>>();
+
+    let corner_count = face_normals.len() * 3;
+    let mut corners_at = vec![Vec::new(); position_count];
+    for corner in 0..corner_count {
+        let vertex = mesh.indices[corner] as usize;
+        corners_at[positions[vertex]].push(corner);
+    }
+
+    let min_cos = options.crease_angle.cos();
+    let compatible = |a: usize, b: usize| {
+        let (va, vb) = (
+            &mesh.vertices[mesh.indices[a] as usize],
+            &mesh.vertices[mesh.indices[b] as usize],
+        );
+        let attributes = va.color == vb.color
+            && (0..2).all(|i| (va.tex_coord[i] - vb.tex_coord[i]).abs() <= options.epsilon);
+
+        if keep_normals {
+            let normals = (0..3).all(|i| (va.normal[i] - vb.normal[i]).abs() <= NORMAL_EPSILON);
+            return attributes && normals;
+        }
+
+        let (na, nb) = (&face_normals[a / 3], &face_normals[b / 3]);
+        // Degenerate faces have no direction and join any group.
+        let smooth = match (
+            na.try_normalize(f32::EPSILON),
+            nb.try_normalize(f32::EPSILON),
+        ) {
+            (Some(na), Some(nb)) => na.dot(&nb) >= min_cos,
+            _ => true,
+        };
+
+        attributes && smooth
+    };
+
+    let mut vertices: Vec<model::ModelVertex> = Vec::new();
+    // Source vertex of every welded vertex, for the scalars.
+    let mut sources: Vec<usize> = Vec::new();
+    let mut normals: Vec<na::Vector3<f32>> = Vec::new();
+    let mut indices = vec![0; corner_count];
+
+    for corners in &corners_at {
+        // First corner of every group at this position, and its vertex.
+        let mut groups: Vec<(usize, u32)> = Vec::new();
+
+        for &corner in corners {
+            let vertex = match groups.iter().find(|(first, _)| compatible(*first, corner)) {
+                Some((_, vertex)) => *vertex,
+                None => {
+                    let vertex = vertices.len() as u32;
+                    let source = mesh.indices[corner] as usize;
+                    vertices.push(mesh.vertices[source]);
+                    sources.push(source);
+                    normals.push(na::Vector3::zeros());
+                    groups.push((corner, vertex));
+                    vertex
+                }
+            };
+
+            normals[vertex as usize] += face_normals[corner / 3];
+            indices[corner] = vertex;
+        }
+    }
+
+    if !keep_normals {
+        for (vertex, normal) in vertices.iter_mut().zip(normals) {
+            if let Some(normal) = normal.try_normalize(f32::EPSILON) {
+                vertex.normal = normal.into();
+            }
+        }
+    }
+
+    MeshData {
+        name: mesh.name.clone(),
+        vertices,
+        indices,
+        material: mesh.material,
+        topology: mesh.topology,
+        scalars: mesh
+            .scalars
+            .iter()
+            .map(|(name, values)| {
+                let values = sources.iter().map(|&source| values[source]).collect();
+                (name.clone(), values)
+            })
+            .collect(),
+        has_uv: mesh.has_uv,
+    }
+}
+
+/// Maps every vertex to the index of the first vertex within `epsilon` of it.
+///
+/// Returns the mapping and the number of distinct positions.
+pub(crate) fn weld_positions(vertices: &[model::ModelVertex], epsilon: f32) -> (Vec<usize>, usize) {
+    let epsilon = epsilon.max(f32::MIN_POSITIVE);
+    let cell = |p: &[f32; 3]| p.map(|x| (x / epsilon).floor() as i64);
+
+    let mut grid: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    let mut unique: Vec<[f32; 3]> = Vec::new();
+    let mut mapping = Vec::with_capacity(vertices.len());
+
+    for vertex in vertices {
+        let position = vertex.position;
+        let [x, y, z] = cell(&position);
+
+        // A match can sit in any neighbouring cell.
+        let mut found = None;
+        'search: for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let Some(candidates) = grid.get(&[x + dx, y + dy, z + dz]) else {
+                        continue;
+                    };
+                    for &candidate in candidates {
+                        let distance = na::distance(
+                            &na::Point3::from(unique[candidate]),
+                            &na::Point3::from(position),
+                        );
+                        if distance <= epsilon {
+                            found = Some(candidate);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+
+        let id = found.unwrap_or_else(|| {
+            unique.push(position);
+            grid.entry([x, y, z]).or_default().push(unique.len() - 1);
+            unique.len() - 1
+        });
+        mapping.push(id);
+    }
+
+    (mapping, unique.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::fs::test_util::{mesh, vertex};
+
+    /// Two triangles folded along the x axis, as unshared vertices.
+    fn folded(angle: f32) -> MeshData {
+        let (s, c) = angle.sin_cos();
+        let vertices = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [0.0, -c, s],
+        ];
+        mesh(vertices.into_iter().map(vertex).collect(), (0..6).collect())
+    }
+
+    #[test]
+    fn test_weld() {
+        let options = WeldOptions::default();
+
+        // Nearly flat, the shared edge is welded and smoothed.
+        let smooth = weld(&folded(10f32.to_radians()), &options, false);
+        assert_eq!(smooth.vertices.len(), 4);
+        assert_eq!(smooth.indices.len(), 6);
+
+        // Right angle, the edge stays hard.
+        let hard = weld(&folded(90f32.to_radians()), &options, false);
+        assert_eq!(hard.vertices.len(), 6);
+        assert_eq!(hard.vertices[0].normal, [0.0, 0.0, 1.0]);
+    }
+}
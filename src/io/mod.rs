@@ -1,28 +1,27 @@
 use egui::epaint::Shadow;
 use egui::{Context, Visuals};
-use egui_wgpu::renderer::ScreenDescriptor;
 use egui_wgpu::Renderer;
+use egui_wgpu::ScreenDescriptor;
 
-use crate::gpu::Gpu;
-use crate::model;
+use crate::gpu::{self, Gpu};
 use crate::resource;
 use crate::texture;
 use crate::ModelEntry;
 use crate::Resources;
 
-use wgpu::util::DeviceExt;
-
 use egui_winit::State;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use wgpu::TextureFormat;
-use winit::event::{KeyEvent, WindowEvent};
+use winit::event::{ElementState, WindowEvent};
 use winit::window::Window;
 
 pub mod fs;
 
 pub trait Controller {
-    fn process_events(&self, ctx: &KeyEvent);
+    /// Called with the window events not used by the UI.
+    fn process_events(&self, event: &WindowEvent);
 }
 
 pub trait Ui {
@@ -60,21 +59,30 @@ impl<T: Controller> IoEngine<T> {
 
     pub fn handle_event(&mut self, event: &WindowEvent) {
         use WindowEvent::*;
-        match event {
-            DroppedFile(path) => match futures::executor::block_on(self.handle_file_drop(path)) {
+        if let DroppedFile(path) = event {
+            match futures::executor::block_on(self.handle_file_drop(path)) {
                 Ok(()) => log::info!("Added Model"),
                 Err(msg) => log::error!("{msg}"),
-            },
-            KeyboardInput { event, .. } => {
-                self.camera_controller.process_events(&event);
             }
-            _ => {}
-        };
+        }
 
-        self.gui.handle_input(&self.window, event);
+        let consumed = self.gui.handle_input(&self.window, event);
+        // Releases always go through, or drags started in the scene could
+        // not end over the UI.
+        let release = matches!(
+            event,
+            MouseInput {
+                state: ElementState::Released,
+                ..
+            }
+        );
+        if !consumed || release {
+            self.camera_controller.process_events(event);
+        }
     }
 
-    async fn handle_file_drop(&mut self, path: &PathBuf) -> anyhow::Result<()> {
+    /// Adds the models of a mesh file, or of every mesh file in a folder.
+    pub async fn handle_file_drop(&mut self, path: &PathBuf) -> anyhow::Result<()> {
         if path.is_dir() {
             let dir = std::fs::read_dir(path)?;
 
@@ -94,25 +102,13 @@ impl<T: Controller> IoEngine<T> {
     }
 
     pub async fn add_model(&mut self, path: &PathBuf) -> anyhow::Result<()> {
-        let model = resource::load_model(path.to_path_buf(), &self.gpu).await?;
+        let options = *self.resources.load_options.read().unwrap();
+        let scene = resource::load_scene(path.to_path_buf(), &self.gpu, options).await?;
         let mut model_db = self.resources.model_db.write().unwrap();
-        let device = &self.gpu.device;
-        let instances = vec![model::Instance::default()];
-
-        let instance_data = instances
-            .iter()
-            .map(model::Instance::to_raw)
-            .collect::<Vec<_>>();
-        let model_entry = ModelEntry {
-            instances,
-            instance_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Model instance"),
-                contents: bytemuck::cast_slice(&instance_data),
-                usage: wgpu::BufferUsages::VERTEX,
-            }),
-            model,
-        };
-        model_db.insert(model_entry);
+
+        for (model, instances) in scene {
+            model_db.insert(ModelEntry::new(&self.gpu.device, model, instances));
+        }
         Ok(())
     }
 }
@@ -121,7 +117,9 @@ pub struct GuiRenderer {
     context: Context,
     gpu: Arc<Gpu>,
     state: State,
-    renderer: Renderer,
+    /// One renderer per sample count of the surface, so that MSAA can be
+    /// switched without uploading the textures again.
+    renderers: BTreeMap<u32, Renderer>,
     window: Arc<Window>,
     ui: Box<dyn Ui>,
 }
@@ -130,7 +128,6 @@ impl GuiRenderer {
     pub fn new(
         gpu: Arc<Gpu>,
         output_depth_format: Option<TextureFormat>,
-        msaa_samples: u32,
         window: Arc<Window>,
         ui: impl Ui + 'static,
     ) -> Self {
@@ -153,25 +150,33 @@ impl GuiRenderer {
         let egui_state = egui_winit::State::new(egui_context.clone(), id, &window, None, None);
 
         // egui_state.set_pixels_per_point(window.scale_factor() as f32);
-        let egui_renderer = egui_wgpu::Renderer::new(
-            &device,
-            output_color_format,
-            output_depth_format,
-            msaa_samples,
-        );
+        let renderers = [1, 2, 4, 8]
+            .into_iter()
+            .filter(|&samples| gpu.clamp_msaa_samples(samples, &[output_color_format]) == samples)
+            .map(|samples| {
+                let renderer = egui_wgpu::Renderer::new(
+                    &device,
+                    output_color_format,
+                    output_depth_format,
+                    samples,
+                );
+                (samples, renderer)
+            })
+            .collect();
 
         GuiRenderer {
             context: egui_context,
             state: egui_state,
-            renderer: egui_renderer,
+            renderers,
             ui: Box::new(ui),
             gpu,
             window,
         }
     }
 
-    pub fn handle_input(&mut self, window: &Window, event: &WindowEvent) {
-        let _ = self.state.on_window_event(window, event);
+    /// Whether the UI used `event`, and the scene should ignore it.
+    pub fn handle_input(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        self.state.on_window_event(window, event).consumed
     }
 
     pub fn render_ui(&mut self) {
@@ -190,15 +195,21 @@ impl GuiRenderer {
         let tris = self
             .context
             .tessellate(full_output.shapes, full_output.pixels_per_point);
-        for (id, image_delta) in &full_output.textures_delta.set {
-            self.renderer
-                .update_texture(&self.gpu.device, &self.gpu.queue, *id, &image_delta);
+        for renderer in self.renderers.values_mut() {
+            for (id, image_delta) in &full_output.textures_delta.set {
+                renderer.update_texture(&self.gpu.device, &self.gpu.queue, *id, &image_delta);
+            }
         }
         let screen_descriptor = ScreenDescriptor {
             size_in_pixels: [config.width, config.height],
             pixels_per_point: window.scale_factor() as f32,
         };
-        self.renderer.update_buffers(
+        let msaa_view = self.gpu.get_msaa_view();
+        let renderer = self
+            .renderers
+            .get_mut(&self.gpu.msaa_samples())
+            .expect("the surface supports the sample count");
+        renderer.update_buffers(
             &self.gpu.device,
             &self.gpu.queue,
             &mut encoder,
@@ -206,23 +217,22 @@ impl GuiRenderer {
             &screen_descriptor,
         );
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &window_surface_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
+            color_attachments: &[Some(gpu::surface_attachment(
+                &window_surface_view,
+                msaa_view.as_ref(),
+                wgpu::LoadOp::Load,
+            ))],
             depth_stencil_attachment: None,
             label: Some("egui main render pass"),
             timestamp_writes: None,
             occlusion_query_set: None,
         });
-        self.renderer.render(&mut rpass, &tris, &screen_descriptor);
+        renderer.render(&mut rpass, &tris, &screen_descriptor);
         drop(rpass);
-        for x in &full_output.textures_delta.free {
-            self.renderer.free_texture(x)
+        for renderer in self.renderers.values_mut() {
+            for x in &full_output.textures_delta.free {
+                renderer.free_texture(x)
+            }
         }
         self.gpu.submit_cmd(encoder.finish());
     }
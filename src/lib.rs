@@ -1,25 +1,37 @@
 extern crate nalgebra as na;
 
 pub mod app;
+mod bloom;
+mod bvh;
 mod camera;
+pub mod cli;
 mod db;
+mod debug;
+mod exposure;
 pub mod gpu;
 mod gui;
 mod hdr;
+pub mod headless;
+mod ibl;
+mod id_buffer;
 mod io;
 mod light;
 mod model;
+mod pick;
 mod resource;
+mod shadow;
 mod texture;
 
 use crate::db::Id;
 use crate::model::{InstanceRaw, ModelVertex, Vertex};
 
-use camera::{CameraController, CameraUniform, Projection, StaticCamera};
+use camera::{CameraController, CameraUniform, Framing, ICamera, Projection, StaticCamera};
 use db::DB;
+pub use debug::{DebugSettings, DebugView};
 use gpu::Gpu;
-use io::Controller;
-use light::LightUniform;
+pub use hdr::{Msaa, PostSettings, ToneMapping};
+use io::fs::stats::{BoundingSphere, Bounds};
+use io::{fs::AlphaMode, Controller};
 use model::DrawLight;
 use model::DrawModel;
 use std::sync::{Arc, RwLock};
@@ -34,10 +46,25 @@ fn create_render_pipeline(
     depth_format: Option<wgpu::TextureFormat>,
     vertex_layouts: &[wgpu::VertexBufferLayout],
     topology: wgpu::PrimitiveTopology, // NEW!
+    blend: Option<wgpu::BlendState>,
+    sample_count: u32,
     shader: wgpu::ShaderModuleDescriptor,
+    fs_entry: &str,
+    // Second target of the render pass, see `id_buffer::IdBuffer::color_target`,
+    // `None` for a single target.
+    ids: Option<wgpu::ColorTargetState>,
 ) -> wgpu::RenderPipeline {
     let device = &gpu.device;
     let shader = device.create_shader_module(shader);
+    let has_ids = ids.is_some();
+    let targets = [
+        Some(wgpu::ColorTargetState {
+            format: color_format,
+            blend,
+            write_mask: wgpu::ColorWrites::ALL,
+        }),
+        ids,
+    ];
 
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some(&format!("{:?}", shader)),
@@ -49,12 +76,8 @@ fn create_render_pipeline(
         },
         fragment: Some(wgpu::FragmentState {
             module: &shader,
-            entry_point: "fs_main",
-            targets: &[Some(wgpu::ColorTargetState {
-                format: color_format,
-                blend: None,
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
+            entry_point: fs_entry,
+            targets: if has_ids { &targets[..] } else { &targets[..1] },
         }),
         primitive: wgpu::PrimitiveState {
             topology, // NEW!
@@ -70,13 +93,14 @@ fn create_render_pipeline(
         },
         depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
             format,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::LessEqual, // UDPATED!
+            // Blended surfaces must not hide what is drawn behind them later.
+            depth_write_enabled: blend.is_none(),
+            depth_compare: texture::Texture::DEPTH_COMPARE,
             stencil: wgpu::StencilState::default(),
             bias: wgpu::DepthBiasState::default(),
         }),
         multisample: wgpu::MultisampleState {
-            count: 1,
+            count: sample_count,
             mask: !0,
             alpha_to_coverage_enabled: false,
         },
@@ -86,13 +110,37 @@ fn create_render_pipeline(
     })
 }
 
+/// Binds the lights and their shadow maps.
+fn create_light_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    lights: &light::LightBuffer,
+    shadow: &shadow::ShadowMaps,
+) -> wgpu::BindGroup {
+    let [cube, sampler, uniform, cascades] = shadow.bind_group_entries(1);
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Light Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: lights.buffer.as_entire_binding(),
+            },
+            cube,
+            sampler,
+            uniform,
+            cascades,
+        ],
+    })
+}
+
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
 impl Controller for Arc<RwLock<CameraController>> {
-    fn process_events(&self, ctx: &KeyEvent) {
+    fn process_events(&self, event: &WindowEvent) {
         let mut camera_write = self.write().unwrap();
-        camera_write.process_key(ctx);
+        camera_write.process_event(event);
     }
 }
 
@@ -109,6 +157,58 @@ struct ModelEntry {
     model: model::Model,
     instances: Vec<model::Instance>,
     instance_buffer: wgpu::Buffer,
+    /// Of `model` in model space, `None` when it has no vertices.
+    bounds: Option<Bounds>,
+    bounding_sphere: Option<BoundingSphere>,
+}
+
+impl ModelEntry {
+    /// `model` drawn once per instance.
+    fn new(device: &wgpu::Device, model: model::Model, instances: Vec<model::Instance>) -> Self {
+        let instance_data = instances
+            .iter()
+            .map(model::Instance::to_raw)
+            .collect::<Vec<_>>();
+        let bounds = model
+            .meshes
+            .iter()
+            .filter_map(|mesh| mesh.bounds)
+            .reduce(|a, b| a.union(&b));
+        let bounding_sphere = model
+            .meshes
+            .iter()
+            .filter_map(|mesh| mesh.bounding_sphere)
+            .reduce(|a, b| a.union(&b));
+        Self {
+            instances,
+            instance_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Model instance"),
+                contents: bytemuck::cast_slice(&instance_data),
+                usage: wgpu::BufferUsages::VERTEX,
+            }),
+            model,
+            bounds,
+            bounding_sphere,
+        }
+    }
+
+    /// Around every instance, in world space.
+    fn world_bounds(&self) -> Option<Bounds> {
+        let bounds = self.bounds?;
+        self.instances
+            .iter()
+            .map(|instance| bounds.transform(&instance.isometry))
+            .reduce(|a, b| a.union(&b))
+    }
+
+    /// Around every instance, in world space.
+    fn world_bounding_sphere(&self) -> Option<BoundingSphere> {
+        let sphere = self.bounding_sphere?;
+        self.instances
+            .iter()
+            .map(|instance| sphere.transform(&instance.isometry))
+            .reduce(|a, b| a.union(&b))
+    }
 }
 
 struct BindGroupEntry {
@@ -120,6 +220,14 @@ struct Resources {
     pub pipeline_db: RwLock<PipelineDB>,
     pub bind_group_db: RwLock<BindGroupDB>,
     pub model_db: RwLock<ModelDB>,
+    /// Applied to meshes as they are loaded.
+    pub load_options: RwLock<resource::LoadOptions>,
+    pub render_settings: RwLock<RenderSettings>,
+    pub lights: RwLock<Vec<light::Light>>,
+    /// Model the selection actions apply to.
+    pub selection: RwLock<Option<db::Id>>,
+    /// Model under the cursor.
+    pub hovered: RwLock<Option<db::Id>>,
 }
 
 impl Resources {
@@ -128,66 +236,193 @@ impl Resources {
             pipeline_db: RwLock::default(),
             bind_group_db: RwLock::default(),
             model_db: RwLock::default(),
+            load_options: RwLock::default(),
+            render_settings: RwLock::default(),
+            lights: RwLock::new(vec![
+                light::Light::new(light::LightKind::Point),
+                light::Light::new(light::LightKind::Directional),
+            ]),
+            selection: RwLock::default(),
+            hovered: RwLock::default(),
         }
     }
 }
 
+/// Options of the renderer edited from the UI, applied every frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderSettings {
+    pub environment: ibl::EnvironmentSettings,
+    pub shadow: shadow::ShadowSettings,
+    pub msaa: hdr::Msaa,
+    pub post: hdr::PostSettings,
+    pub debug: debug::DebugSettings,
+}
+
 struct Renderer {
     gpu: Arc<Gpu>,
-    window: Arc<Window>,
+    /// `None` when rendering offscreen, see [`Renderer::render_to_image`].
+    window: Option<Arc<Window>>,
     camera_controller: Arc<RwLock<CameraController>>,
+    /// When the camera was last moved by `camera_controller`.
+    last_update: std::time::Instant,
     size: winit::dpi::PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
+    pipeline_layouts: ScenePipelineLayouts,
+    pipelines: ScenePipelines,
+    /// Sample count of `pipelines` and their targets.
+    msaa_samples: u32,
+    debug: debug::DebugRenderer,
     camera: Arc<RwLock<StaticCamera>>,
     camera_uniform: CameraUniform,
     camera_buffer: wgpu::Buffer,
+    /// Models highlighted by [`debug::DebugRenderer::draw_highlight`], copied
+    /// from [`Resources::selection`] and [`Resources::hovered`].
+    selection: Option<Id>,
+    hovered: Option<Id>,
+    /// Reads back what is under `cursor`, in pixels, or under the click
+    /// `pending_select` first.
+    id_buffer: id_buffer::IdBuffer,
+    cursor: Option<na::Point2<f32>>,
+    pending_select: Option<na::Point2<f32>>,
     camera_bind_group: Id,
     depth_texture: Option<texture::Texture>,
-    light_uniform: LightUniform,
-    light_buffer: wgpu::Buffer,
+    light_buffer: light::LightBuffer,
+    light_count: u32,
+    light_bind_group_layout: wgpu::BindGroupLayout,
     light_bind_group: wgpu::BindGroup,
-    light_render_pipeline: wgpu::RenderPipeline,
+    /// Drawn once per light by `ScenePipelines::light`.
+    light_gizmo: model::Model,
+    shadow: shadow::ShadowMaps,
     hdr: hdr::HdrPipeline,
     bind_group_db: BindGroupDB,
     envoronment_bind_group: wgpu::BindGroup,
-    sky_pipeline: wgpu::RenderPipeline,
+    /// Image based lighting baked from the sky.
+    environment: ibl::Environment,
+    ibl_bind_group: wgpu::BindGroup,
+}
+
+/// Layouts of [`ScenePipelines`], kept to rebuild them.
+struct ScenePipelineLayouts {
+    model: wgpu::PipelineLayout,
+    light: wgpu::PipelineLayout,
+    sky: wgpu::PipelineLayout,
+}
+
+/// Pipelines drawing the scene into the HDR target, rebuilt when the MSAA
+/// sample count changes.
+struct ScenePipelines {
+    render: wgpu::RenderPipeline,
+    /// Same as `render` for meshes without faces.
+    point: wgpu::RenderPipeline,
+    /// Same as `render` for materials with `AlphaMode::Blend`.
+    blend: wgpu::RenderPipeline,
+    light: wgpu::RenderPipeline,
+    sky: wgpu::RenderPipeline,
+    debug: debug::DebugPipelines,
+}
+
+impl ScenePipelines {
+    fn new(
+        gpu: &Gpu,
+        layouts: &ScenePipelineLayouts,
+        debug: &debug::DebugRenderer,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let depth_format = Some(texture::Texture::DEPTH_FORMAT);
+        let mut source = include_str!("shader.wgsl").to_string();
+        let fs_entry = id_buffer::IdBuffer::fragment_entry(&gpu.device, &mut source);
+        let model_pipeline = |topology, blend| {
+            let shader = wgpu::ShaderModuleDescriptor {
+                label: Some("Normal Shader"),
+                source: wgpu::ShaderSource::Wgsl(source.as_str().into()),
+            };
+            create_render_pipeline(
+                gpu,
+                &layouts.model,
+                format,
+                depth_format,
+                &[
+                    model::ModelVertex::desc(),
+                    InstanceRaw::desc(),
+                    id_buffer::DrawIds::desc(),
+                ],
+                topology,
+                blend,
+                sample_count,
+                shader,
+                fs_entry,
+                // Only triangles are picked, see `id_buffer::IdHit::triangle`.
+                id_buffer::IdBuffer::color_target(
+                    sample_count,
+                    topology == wgpu::PrimitiveTopology::TriangleList,
+                ),
+            )
+        };
+        let render = model_pipeline(wgpu::PrimitiveTopology::TriangleList, None);
+        let point = model_pipeline(wgpu::PrimitiveTopology::PointList, None);
+        let blend = model_pipeline(
+            wgpu::PrimitiveTopology::TriangleList,
+            Some(wgpu::BlendState::ALPHA_BLENDING),
+        );
+
+        let light = create_render_pipeline(
+            gpu,
+            &layouts.light,
+            format,
+            depth_format,
+            &[ModelVertex::desc()],
+            wgpu::PrimitiveTopology::TriangleList,
+            None,
+            sample_count,
+            wgpu::ShaderModuleDescriptor {
+                label: Some("Light Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("light.wgsl").into()),
+            },
+            "fs_main",
+            id_buffer::IdBuffer::color_target(sample_count, false),
+        );
+
+        let sky = create_render_pipeline(
+            gpu,
+            &layouts.sky,
+            format,
+            depth_format,
+            &[],
+            wgpu::PrimitiveTopology::TriangleList,
+            None,
+            sample_count,
+            wgpu::include_wgsl!("sky.wgsl"),
+            "fs_main",
+            id_buffer::IdBuffer::color_target(sample_count, false),
+        );
+
+        Self {
+            render,
+            point,
+            blend,
+            light,
+            sky,
+            debug: debug.create_pipelines(gpu, format, sample_count),
+        }
+    }
 }
 
 impl Renderer {
     async fn new(
-        window: Arc<Window>,
+        window: Option<Arc<Window>>,
         gpu: Arc<Gpu>,
         camera_controller: Arc<RwLock<CameraController>>,
         static_camera: Arc<RwLock<StaticCamera>>,
+        sky: resource::Sky<'_>,
     ) -> Self {
         let device = &gpu.device;
 
-        let size = window.inner_size();
+        let size = gpu
+            .get_config_read(|config| winit::dpi::PhysicalSize::new(config.width, config.height));
 
+        // Must match the bind groups created by `Texture::load`.
         let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("Texture Bind Group Layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        // This should match the filterable field of the
-                        // corresponding Texture entry above.
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-            });
+            device.create_bind_group_layout(&Texture::BIND_GROUP_LAYOUT_DESCRIPTOR);
 
         let mut camera_uniform = CameraUniform::new();
         let camera = static_camera.read().unwrap();
@@ -225,68 +460,64 @@ impl Renderer {
             label: Some("camera_bind_group"),
         });
 
-        let light_uniform = light::LightUniform {
-            position: [2.0, 2.0, 2.0],
-            _padding: 0,
-            color: [1.0, 1.0, 1.0],
-            _paddding: 0,
-        };
-
         let hdr = hdr::HdrPipeline::new(&gpu);
 
-        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Light V8"),
-            contents: bytemuck::cast_slice(&[light_uniform]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+        // Sized for the lights on the first update.
+        let light_buffer = light::LightBuffer::new(device, 0);
+
+        // The shadows of the lights are bound next to them.
+        let shadow = shadow::ShadowMaps::new(&gpu);
+        let [cube_entry, sampler_entry, uniform_entry, cascades_entry] =
+            shadow::ShadowMaps::layout_entries(1);
 
         let light_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("Light Bind Group Layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    count: None,
-                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        count: None,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
                     },
-                }],
+                    cube_entry,
+                    sampler_entry,
+                    uniform_entry,
+                    cascades_entry,
+                ],
             });
 
-        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Light Bind Group"),
-            layout: &light_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: light_buffer.as_entire_binding(),
-            }],
-        });
+        let light_bind_group =
+            create_light_bind_group(device, &light_bind_group_layout, &light_buffer, &shadow);
+        let light_gizmo = resource::light_gizmo(&gpu).unwrap();
 
         let depth_texture = None;
 
-        // lib.rs
-        let light_render_pipeline = {
-            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        let light_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Light Pipeline Layout"),
                 bind_group_layouts: &[&camera_bind_group_layout, &light_bind_group_layout],
                 push_constant_ranges: &[],
             });
-            let shader = wgpu::ShaderModuleDescriptor {
-                label: Some("Light Shader"),
-                source: wgpu::ShaderSource::Wgsl(include_str!("light.wgsl").into()),
-            };
-            create_render_pipeline(
-                &gpu,
-                &layout,
-                hdr.format(),
-                Some(texture::Texture::DEPTH_FORMAT),
-                &[ModelVertex::desc()],
-                wgpu::PrimitiveTopology::TriangleList,
-                shader,
-            )
-        };
+
+        let hdr_loader = resource::HdrLoader::new(&device);
+        let sky_texture = hdr_loader
+            .from_sky(&gpu, sky, 1080, Some("Sky Texture"))
+            .await
+            .unwrap();
+
+        let environment = ibl::IblBaker::new(&device).bake(
+            &gpu,
+            &sky_texture,
+            ibl::EnvironmentSettings::default(),
+        );
+        let ibl_layout =
+            device.create_bind_group_layout(&ibl::Environment::BIND_GROUP_LAYOUT_DESCRIPTOR);
+        let ibl_bind_group = environment.bind_group(&device, &ibl_layout);
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -295,18 +526,11 @@ impl Renderer {
                     &texture_bind_group_layout,
                     &camera_bind_group_layout,
                     &light_bind_group_layout,
+                    &ibl_layout,
                 ],
                 push_constant_ranges: &[],
             });
 
-        let hdr = hdr::HdrPipeline::new(&gpu);
-
-        let hdr_loader = resource::HdrLoader::new(&device);
-        let sky_bytes = resource::load_binary("pure-sky.hdr").await.unwrap();
-        let sky_texture = hdr_loader
-            .from_equirectangular_bytes(&gpu, &sky_bytes, 1080, Some("Sky Texture"))
-            .unwrap();
-
         let environment_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("environment_layout"),
@@ -327,6 +551,16 @@ impl Renderer {
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
             });
 
@@ -342,43 +576,28 @@ impl Renderer {
                     binding: 1,
                     resource: wgpu::BindingResource::Sampler(sky_texture.sampler()),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: environment.uniform_buffer().as_entire_binding(),
+                },
             ],
         });
 
-        // NEW!
-        let sky_pipeline = {
-            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Sky Pipeline Layout"),
-                bind_group_layouts: &[&camera_bind_group_layout, &environment_layout],
-                push_constant_ranges: &[],
-            });
-            let shader = wgpu::include_wgsl!("sky.wgsl");
-            create_render_pipeline(
-                &gpu,
-                &layout,
-                hdr.format(),
-                Some(texture::Texture::DEPTH_FORMAT),
-                &[],
-                wgpu::PrimitiveTopology::TriangleList,
-                shader,
-            )
-        };
+        let sky_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sky Pipeline Layout"),
+            bind_group_layouts: &[&camera_bind_group_layout, &environment_layout],
+            push_constant_ranges: &[],
+        });
 
-        let render_pipeline = {
-            let shader = wgpu::ShaderModuleDescriptor {
-                label: Some("Normal Shader"),
-                source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-            };
-            create_render_pipeline(
-                &gpu,
-                &render_pipeline_layout,
-                hdr.format(),
-                Some(texture::Texture::DEPTH_FORMAT),
-                &[model::ModelVertex::desc(), InstanceRaw::desc()],
-                wgpu::PrimitiveTopology::TriangleList,
-                shader,
-            )
+        // Single sampled until the first update applies the MSAA setting.
+        let pipeline_layouts = ScenePipelineLayouts {
+            model: render_pipeline_layout,
+            light: light_pipeline_layout,
+            sky: sky_pipeline_layout,
         };
+        let debug = debug::DebugRenderer::new(&gpu, &camera_bind_group_layout);
+        let pipelines = ScenePipelines::new(&gpu, &pipeline_layouts, &debug, hdr.format(), 1);
+        let id_buffer = id_buffer::IdBuffer::new(&gpu, &camera_bind_group_layout);
 
         let mut bind_group_db = BindGroupDB::default();
 
@@ -389,86 +608,257 @@ impl Renderer {
 
         Self {
             envoronment_bind_group: environment_bind_group,
+            environment,
+            ibl_bind_group,
             gpu,
             depth_texture,
             hdr,
             size,
-            render_pipeline,
+            pipeline_layouts,
+            pipelines,
+            msaa_samples: 1,
+            debug,
             window,
             camera: static_camera,
             camera_uniform,
+            selection: None,
+            hovered: None,
+            id_buffer,
+            cursor: None,
+            pending_select: None,
             camera_bind_group,
             camera_buffer,
             light_buffer,
-            light_uniform,
+            light_count: 0,
+            light_bind_group_layout,
             light_bind_group,
-            light_render_pipeline,
+            light_gizmo,
+            shadow,
             camera_controller,
+            last_update: std::time::Instant::now(),
             bind_group_db,
-            sky_pipeline,
         }
     }
 
-    pub fn window(&self) -> &Window {
-        &self.window
+    pub fn window(&self) -> Option<&Window> {
+        self.window.as_deref()
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
 
-            let device = &self.gpu.device;
-
             let mut config_write = self.gpu.get_config_mut();
-            let surface = &self.gpu.surface;
-
             config_write.width = new_size.width;
             config_write.height = new_size.height;
+            drop(config_write);
 
-            surface.configure(device, &config_write);
+            self.gpu.configure();
             self.depth_texture = Some(texture::Texture::create_depth_texture(
-                &device,
-                &config_write,
+                &self.gpu.device,
+                &self.gpu.get_config(),
+                self.msaa_samples,
                 "depth_texture",
             ));
             self.hdr
                 .resize(&self.gpu, self.size.width, self.size.height);
+            self.id_buffer
+                .resize(&self.gpu, self.size.width, self.size.height);
+            self.gpu.set_msaa_samples(self.msaa_samples);
         }
     }
 
     #[allow(unused_variables)]
     fn input(&mut self, event: &WindowEvent) -> bool {
-        self.window().request_redraw();
+        if let Some(window) = self.window() {
+            window.request_redraw();
+        }
         false
     }
 
-    fn update(&mut self) {
-        let mut camera = self.camera.write().unwrap();
-        self.camera_controller
-            .write()
-            .unwrap()
-            .update_camera(&mut *camera);
+    fn update(&mut self, resources: &Resources) {
+        let settings = *resources.render_settings.read().unwrap();
+        self.set_msaa_samples(settings.msaa.samples());
+        let post = if settings.debug.view.replaces_shading() {
+            // Debug colors are shown as they are.
+            hdr::PostSettings {
+                tone_mapping: hdr::ToneMapping::None,
+                exposure: 0.0,
+                auto_exposure: false,
+                bloom: false,
+                ..settings.post
+            }
+        } else {
+            settings.post
+        };
+        self.hdr.update(&self.gpu.queue, &post);
+        self.environment
+            .update(&self.gpu.queue, settings.environment);
 
         let (width, height) = self
             .gpu
             .get_config_read(|config| (config.width as f32, config.height as f32));
+        let viewport = na::Vector2::new(width, height);
+
+        let now = std::time::Instant::now();
+        let dt = now.duration_since(self.last_update).as_secs_f32();
+        self.last_update = now;
+
+        let model_db = resources.model_db.read().unwrap();
+        let lights = resources.lights.read().unwrap();
+        // Light gizmos are drawn too, and must not be clipped.
+        let light_positions = lights
+            .iter()
+            .filter(|light| light.kind != light::LightKind::Directional)
+            .map(|light| na::Point3::from(light.position));
+        let scene = model_db
+            .get_all()
+            .filter_map(ModelEntry::world_bounds)
+            .chain(Bounds::from_points(light_positions))
+            .reduce(|a, b| a.union(&b));
+        drop(lights);
 
-        let projection = Projection::with_aspect(width, height);
+        let mut camera = self.camera.write().unwrap();
+        let mut controller = self.camera_controller.write().unwrap();
+        let projection = controller.projection(width, height, &camera, scene.as_ref());
+        controller.update_camera(&mut camera, &projection, viewport, dt);
+        // Follows the camera, as the orthographic size and the clip planes.
+        let projection = controller.projection(width, height, &camera, scene.as_ref());
+        if let Some(framing) = controller.take_frame_request() {
+            let selection = *resources.selection.read().unwrap();
+            let selected = match framing {
+                Framing::Selection => selection.and_then(|id| model_db.data.get(&id)),
+                Framing::All => None,
+            };
+            // Everything when nothing is selected.
+            let sphere = match selected {
+                Some(entry) => entry.world_bounding_sphere(),
+                None => model_db
+                    .get_all()
+                    .filter_map(ModelEntry::world_bounding_sphere)
+                    .reduce(|a, b| a.union(&b)),
+            };
+            if let Some(sphere) = sphere {
+                controller.frame(&camera, &sphere, &projection);
+            }
+        }
+        if let Some(cursor) = controller.take_select_request() {
+            // Resolved once the ids under it are read back.
+            self.pending_select = Some(cursor);
+        }
+        self.cursor = controller.cursor();
 
         self.camera_uniform
-            .update_view_projection(&projection, &mut *camera);
+            .update_view_projection(&projection, &*camera);
+        if let Some(cursor) = controller.take_pivot_request() {
+            let ray = self
+                .camera_uniform
+                .ray(camera::cursor_to_ndc(cursor, viewport));
+            if let Some(hit) = pick::cast_ray(model_db.iter(), &ray) {
+                controller.orbit.set_pivot(&mut camera, hit.position);
+                self.camera_uniform
+                    .update_view_projection(&projection, &*camera);
+            }
+        }
+        drop(controller);
 
-        // Update the light
-        let old_position: nalgebra::Vector3<_> = self.light_uniform.position.into();
-        let isom = na::Isometry3::new(old_position, *na::Vector3::y_axis());
+        self.debug
+            .update(&self.gpu.queue, &settings.debug, &projection);
+        let camera_view = camera.build_view_matrix();
+        drop(camera);
 
-        let queue = &self.gpu.queue;
+        let mut selection = resources.selection.write().unwrap();
+        let mut hovered = resources.hovered.write().unwrap();
+        if let Some(pick) = self.id_buffer.poll(&self.gpu.device) {
+            let model = match pick.hit {
+                Some(hit) => {
+                    if pick.request.select {
+                        log::info!(
+                            "Picked model {} instance {} mesh {} triangle {:?}",
+                            hit.model,
+                            hit.instance,
+                            hit.mesh,
+                            hit.triangle
+                        );
+                    }
+                    Some(hit.model)
+                }
+                // Points have no ids, clicks on them are cast on the CPU.
+                None if pick.request.select => {
+                    let cursor = pick.request.cursor;
+                    let ray = self
+                        .camera_uniform
+                        .ray(camera::cursor_to_ndc(cursor, viewport));
+                    let triangle =
+                        pick::cast_ray(model_db.iter(), &ray).map(|hit| (hit.distance, hit.model));
+                    let point = pick::cast_ray_points(
+                        model_db.iter(),
+                        &ray,
+                        &self.camera_uniform.view_proj(),
+                        viewport,
+                        cursor,
+                        id_buffer::RADIUS,
+                    )
+                    .map(|hit| {
+                        log::info!(
+                            "Picked model {} instance {} mesh {} point {}",
+                            hit.model,
+                            hit.instance,
+                            hit.mesh,
+                            hit.point
+                        );
+                        (hit.distance, hit.model)
+                    });
+                    triangle
+                        .into_iter()
+                        .chain(point)
+                        .min_by(|a, b| a.0.total_cmp(&b.0))
+                        .map(|(_, model)| model)
+                }
+                None => None,
+            };
+            if pick.request.select {
+                // Clicking the background clears the selection.
+                *selection = model;
+            }
+            *hovered = model;
+        }
+        // Forgotten once the model is gone.
+        let exists = |id: &Id| model_db.data.contains_key(id);
+        *selection = selection.filter(exists);
+        *hovered = hovered.filter(exists);
+        self.selection = *selection;
+        self.hovered = *hovered;
+        drop(selection);
+        drop(hovered);
+        drop(model_db);
+
+        let lights = resources.lights.read().unwrap();
+        let (point_caster, directional_caster) = light::shadow_casters(&lights);
+        let raw_lights = lights
+            .iter()
+            .enumerate()
+            .map(|(i, light)| {
+                let shadow = if Some(i) == point_caster {
+                    1
+                } else if Some(i) == directional_caster {
+                    2
+                } else {
+                    0
+                };
+                light.to_raw(shadow)
+            })
+            .collect::<Vec<_>>();
+        self.upload_lights(&raw_lights);
 
-        self.light_uniform.position = isom.translation.into();
-        queue.write_buffer(
-            &self.light_buffer,
-            0,
-            bytemuck::cast_slice(&[self.light_uniform]),
+        let queue = &self.gpu.queue;
+        self.shadow.update(
+            queue,
+            point_caster.map(|i| lights[i].position),
+            directional_caster.map(|i| raw_lights[i].direction),
+            &camera_view,
+            &projection,
+            &settings.shadow,
         );
         queue.write_buffer(
             &self.camera_buffer,
@@ -477,9 +867,57 @@ impl Renderer {
         );
     }
 
+    /// Switches the scene and the passes drawing to the surface to the
+    /// largest sample count up to `samples` supported by their formats.
+    fn set_msaa_samples(&mut self, samples: u32) {
+        let surface_format = self.gpu.get_config_read(|config| config.format);
+        let samples = self.gpu.clamp_msaa_samples(
+            samples,
+            &[
+                self.hdr.format(),
+                texture::Texture::DEPTH_FORMAT,
+                surface_format,
+            ],
+        );
+        if samples == self.msaa_samples {
+            return;
+        }
+
+        self.msaa_samples = samples;
+        self.pipelines = ScenePipelines::new(
+            &self.gpu,
+            &self.pipeline_layouts,
+            &self.debug,
+            self.hdr.format(),
+            samples,
+        );
+        // Recreated with the new sample count on the next render.
+        self.depth_texture = None;
+        self.hdr.set_msaa_samples(&self.gpu, samples);
+        self.id_buffer.set_msaa_samples(&self.gpu, samples);
+        self.gpu.set_msaa_samples(samples);
+    }
+
+    /// Writes `lights` to the light buffer, growing it if needed.
+    fn upload_lights(&mut self, lights: &[light::LightRaw]) {
+        let queue = &self.gpu.queue;
+        if !self.light_buffer.write(queue, lights) {
+            let device = &self.gpu.device;
+            self.light_buffer = light::LightBuffer::new(device, lights.len().next_power_of_two());
+            self.light_bind_group = create_light_bind_group(
+                device,
+                &self.light_bind_group_layout,
+                &self.light_buffer,
+                &self.shadow,
+            );
+            self.light_buffer.write(queue, lights);
+        }
+        self.light_count = lights.len() as u32;
+    }
+
     pub fn render_models<'a>(
         &mut self,
-        models: impl Iterator<Item = &'a ModelEntry>,
+        models: impl Iterator<Item = (Id, &'a ModelEntry)>,
     ) -> Result<(), wgpu::SurfaceError> {
         let view = self.gpu.get_current_view();
         let device = &self.gpu.device;
@@ -493,29 +931,53 @@ impl Renderer {
             self.depth_texture = Some(Texture::create_depth_texture(
                 device,
                 &config,
+                self.msaa_samples,
                 "Depth Texture",
             ));
         }
 
         let depth_tex = self.depth_texture.as_ref().unwrap();
 
+        let models = models.collect::<Vec<_>>();
+        let entries = models.iter().map(|&(_, entry)| entry).collect::<Vec<_>>();
+        let entry = |id: Option<Id>| {
+            let (_, entry) = models.iter().find(|&&(i, _)| Some(i) == id)?;
+            Some(*entry)
+        };
+        let mut highlights = vec![];
+        // The selection wins over the hover.
+        if self.hovered != self.selection {
+            highlights.extend(entry(self.hovered).map(|e| (e, debug::Highlight::Hovered)));
+        }
+        highlights.extend(entry(self.selection).map(|e| (e, debug::Highlight::Selected)));
+
         let mut encoder = self.gpu.create_cmd_encoder();
 
+        self.shadow.render(&mut encoder, &entries);
+        self.id_buffer.write_draws(&self.gpu, &models);
+
         {
+            // The ids have a pass of their own with MSAA.
+            let ids = self.id_buffer.color_attachment();
+            let has_ids = ids.is_some();
+            let color_attachments = [
+                Some(
+                    self.hdr
+                        .color_attachment(wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)),
+                ),
+                ids,
+            ];
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: self.hdr.view(),
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
+                color_attachments: if has_ids {
+                    &color_attachments[..]
+                } else {
+                    &color_attachments[..1]
+                },
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                     view: &depth_tex.view,
                     depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
+                        load: wgpu::LoadOp::Clear(texture::Texture::DEPTH_CLEAR),
                         store: wgpu::StoreOp::Store,
                     }),
                     stencil_ops: None,
@@ -524,34 +986,156 @@ impl Renderer {
                 timestamp_writes: None,
             });
 
-            for entry in models {
-                let model = &entry.model;
-                let instances = &entry.instances;
-                let instane_buffer = &entry.instance_buffer;
-
-                //render_pass.set_pipeline(&self.light_render_pipeline);
-                //render_pass.draw_light_model(model, camera_bind_group, &self.light_bind_group);
-
-                render_pass.set_pipeline(&self.render_pipeline);
-                render_pass.set_vertex_buffer(1, instane_buffer.slice(..));
+            // Not touched by `draw_mesh_instanced`, stays bound for every model.
+            render_pass.set_bind_group(3, &self.ibl_bind_group, &[]);
+
+            // Blended meshes go last so that opaque geometry behind them is visible.
+            for blended in [false, true] {
+                for (entry_index, entry) in entries.iter().enumerate() {
+                    let model = &entry.model;
+                    let instances = &entry.instances;
+                    let instane_buffer = &entry.instance_buffer;
+
+                    render_pass.set_vertex_buffer(1, instane_buffer.slice(..));
+
+                    for (mesh_index, mesh) in model.meshes.iter().enumerate() {
+                        let material = &model.materials[mesh.material];
+                        if (material.data.alpha_mode == AlphaMode::Blend) != blended
+                            || self.debug.replaces(mesh)
+                        {
+                            continue;
+                        }
+
+                        let pipeline = match mesh.topology {
+                            wgpu::PrimitiveTopology::PointList => &self.pipelines.point,
+                            _ if blended => &self.pipelines.blend,
+                            _ => &self.pipelines.render,
+                        };
+                        render_pass.set_pipeline(pipeline);
+                        render_pass
+                            .set_vertex_buffer(2, self.id_buffer.draw_ids(entry_index, mesh_index));
+                        render_pass.draw_mesh_instanced(
+                            mesh,
+                            material,
+                            0..instances.len() as u32,
+                            camera_bind_group,
+                            &self.light_bind_group,
+                        );
+                    }
+                }
+            }
 
-                render_pass.draw_model_instanced(
-                    model,
-                    0..instances.len() as u32,
+            self.debug.draw(
+                &mut render_pass,
+                &self.pipelines.debug,
+                &entries,
+                &self.id_buffer,
+                camera_bind_group,
+            );
+            for &(entry, highlight) in &highlights {
+                self.debug.draw_highlight(
+                    &mut render_pass,
+                    &self.pipelines.debug,
+                    entry,
+                    highlight,
                     camera_bind_group,
-                    &self.light_bind_group,
-                )
+                );
             }
 
-            render_pass.set_pipeline(&self.sky_pipeline);
+            render_pass.set_pipeline(&self.pipelines.light);
+            render_pass.draw_light_model_instanced(
+                &self.light_gizmo,
+                0..self.light_count,
+                camera_bind_group,
+                &self.light_bind_group,
+            );
+
+            render_pass.set_pipeline(&self.pipelines.sky);
             render_pass.set_bind_group(0, &camera_bind_group, &[]);
             render_pass.set_bind_group(1, &self.envoronment_bind_group, &[]);
             render_pass.draw(0..3, 0..1);
         }
 
-        self.hdr.process(&mut encoder, &view);
+        let msaa_view = self.gpu.get_msaa_view();
+        self.hdr.process(&mut encoder, &view, msaa_view.as_ref());
+
+        let request = match (self.pending_select, self.cursor) {
+            (Some(cursor), _) => Some(id_buffer::PickRequest {
+                cursor,
+                select: true,
+            }),
+            (None, Some(cursor)) => Some(id_buffer::PickRequest {
+                cursor,
+                select: false,
+            }),
+            (None, None) => None,
+        };
+        if let Some(request) = request {
+            let accepted = self.id_buffer.copy_region(
+                &self.gpu.queue,
+                &mut encoder,
+                &self.camera_uniform.view_proj(),
+                request,
+                &entries,
+                camera_bind_group,
+            );
+            if accepted && request.select {
+                self.pending_select = None;
+            }
+        }
 
         self.gpu.submit_cmd(encoder.finish());
         Ok(())
     }
+
+    /// Renders the models of `resources` at `width` by `height` and reads
+    /// the frame back, needs a headless [`Gpu`].
+    fn render_to_image(
+        &mut self,
+        resources: &Resources,
+        width: u32,
+        height: u32,
+        output: headless::RenderOutput,
+    ) -> anyhow::Result<headless::Frame> {
+        anyhow::ensure!(
+            self.gpu.is_headless(),
+            "Rendering to an image needs a headless Gpu"
+        );
+        anyhow::ensure!(width > 0 && height > 0, "Empty {width}x{height} image");
+        if (width, height) != (self.size.width, self.size.height) {
+            self.resize(winit::dpi::PhysicalSize::new(width, height));
+        }
+
+        self.update(resources);
+        let model_db = resources.model_db.read().unwrap();
+        self.render_models(model_db.iter())?;
+        drop(model_db);
+        self.gpu.finish();
+
+        match output {
+            headless::RenderOutput::ToneMapped => {
+                let pixels = self.gpu.read_offscreen()?;
+                let image = image::RgbaImage::from_raw(width, height, pixels)
+                    .ok_or_else(|| anyhow::anyhow!("Offscreen target has the wrong size"))?;
+                Ok(headless::Frame::ToneMapped(image))
+            }
+            headless::RenderOutput::Hdr => {
+                let bytes = self.gpu.read_texture(self.hdr.texture(), 8)?;
+                let pixels = bytes
+                    .chunks_exact(8)
+                    .map(|texel| {
+                        let channel = |i: usize| {
+                            headless::f16_to_f32(u16::from_le_bytes([texel[i], texel[i + 1]]))
+                        };
+                        [channel(0), channel(2), channel(4), channel(6)]
+                    })
+                    .collect();
+                Ok(headless::Frame::Hdr {
+                    width,
+                    height,
+                    pixels,
+                })
+            }
+        }
+    }
 }
@@ -1,8 +1,187 @@
+use std::fmt::Display;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightKind {
+    /// Shines in every direction from `position`.
+    Point,
+    /// Shines from `position` along `direction` within a cone.
+    Spot,
+    /// Shines along `direction` from infinitely far away, like the sun.
+    Directional,
+}
+
+impl LightKind {
+    pub const ALL: [LightKind; 3] = [Self::Point, Self::Spot, Self::Directional];
+}
+
+impl Display for LightKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Point => "Point",
+            Self::Spot => "Spot",
+            Self::Directional => "Directional",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// A light of the scene as edited in the UI.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Also where the gizmo of directional lights is drawn.
+    pub position: [f32; 3],
+    /// Unused by point lights.
+    pub direction: [f32; 3],
+    /// Linear RGB.
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Distance at which point and spot lights fade out completely.
+    pub range: f32,
+    /// Half angle of the fully lit cone of spot lights, in radians.
+    pub inner_angle: f32,
+    /// Half angle of the cone of spot lights, in radians.
+    pub outer_angle: f32,
+    pub cast_shadows: bool,
+}
+
+impl Light {
+    pub fn new(kind: LightKind) -> Self {
+        let intensity = match kind {
+            LightKind::Directional => 3.0,
+            _ => 10.0,
+        };
+        Self {
+            kind,
+            position: [2.0, 2.0, 2.0],
+            direction: [-1.0, -1.0, -1.0],
+            color: [1.0; 3],
+            intensity,
+            range: 20.0,
+            inner_angle: 20f32.to_radians(),
+            outer_angle: 30f32.to_radians(),
+            cast_shadows: true,
+        }
+    }
+
+    /// `shadow` is the [`LightRaw::shadow`] map of the light.
+    pub fn to_raw(&self, shadow: u32) -> LightRaw {
+        let direction = na::Vector3::from(self.direction)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(|| -na::Vector3::y());
+        let outer_angle = self.outer_angle.max(0.0);
+        LightRaw {
+            position: self.position,
+            kind: self.kind as u32,
+            direction: direction.into(),
+            range: self.range.max(f32::EPSILON),
+            color: self.color.map(|c| c * self.intensity),
+            shadow,
+            inner_cos: self.inner_angle.clamp(0.0, outer_angle).cos(),
+            outer_cos: outer_angle.cos(),
+            _padding: [0; 2],
+        }
+    }
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self::new(LightKind::Point)
+    }
+}
+
+/// Indices of the lights rendering the point and the directional shadow map.
+///
+/// Only the first shadow casting point or spot light and the first shadow
+/// casting directional light get one.
+pub fn shadow_casters(lights: &[Light]) -> (Option<usize>, Option<usize>) {
+    let casters = |directional: bool| {
+        lights.iter().position(|light| {
+            light.cast_shadows && (light.kind == LightKind::Directional) == directional
+        })
+    };
+    (casters(false), casters(true))
+}
+
+/// Layout of a light in the light storage buffer.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct LightUniform {
+pub struct LightRaw {
     pub position: [f32; 3],
-    pub _padding: u32,
+    /// [`LightKind`] discriminant.
+    pub kind: u32,
+    pub direction: [f32; 3],
+    pub range: f32,
+    /// Color multiplied by intensity.
     pub color: [f32; 3],
-    pub _paddding: u32,
+    /// 0 without shadow, 1 for the point shadow and 2 for the directional shadow.
+    pub shadow: u32,
+    pub inner_cos: f32,
+    pub outer_cos: f32,
+    pub _padding: [u32; 2],
+}
+
+/// Storage buffer holding the light count followed by every [`LightRaw`].
+pub struct LightBuffer {
+    pub buffer: wgpu::Buffer,
+    capacity: usize,
+}
+
+impl LightBuffer {
+    const HEADER_SIZE: usize = 16;
+
+    pub fn new(device: &wgpu::Device, capacity: usize) -> Self {
+        // Bindings of zero size are invalid, there is room for at least one light.
+        let capacity = capacity.max(1);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Buffer"),
+            size: (Self::HEADER_SIZE + capacity * std::mem::size_of::<LightRaw>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self { buffer, capacity }
+    }
+
+    /// Uploads `lights`, returns false if they don't fit and a larger buffer
+    /// is needed.
+    pub fn write(&self, queue: &wgpu::Queue, lights: &[LightRaw]) -> bool {
+        if lights.len() > self.capacity {
+            return false;
+        }
+        let header = [lights.len() as u32, 0, 0, 0];
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&header));
+        if !lights.is_empty() {
+            queue.write_buffer(
+                &self.buffer,
+                Self::HEADER_SIZE as u64,
+                bytemuck::cast_slice(lights),
+            );
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn light_raw_matches_shader_layout() {
+        assert_eq!(std::mem::size_of::<LightRaw>(), 64);
+    }
+
+    #[test]
+    fn first_casters_of_each_kind_get_shadows() {
+        let mut no_shadow = Light::new(LightKind::Point);
+        no_shadow.cast_shadows = false;
+        let lights = [
+            no_shadow,
+            Light::new(LightKind::Directional),
+            Light::new(LightKind::Spot),
+            Light::new(LightKind::Point),
+            Light::new(LightKind::Directional),
+        ];
+        assert_eq!(shadow_casters(&lights), (Some(2), Some(1)));
+        assert_eq!(shadow_casters(&lights[..1]), (None, None));
+    }
 }
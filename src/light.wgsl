@@ -1,16 +1,34 @@
+// Must match `camera::CameraUniform`.
 struct Camera {
+    view_pos: vec4<f32>,
+    view: mat4x4<f32>,
     view_proj: mat4x4<f32>,
+    inv_proj: mat4x4<f32>,
+    inv_view: mat4x4<f32>,
 }
 
 @group(0) @binding(0) // 1.
 var<uniform> camera: Camera;
 
+// Must match `light::LightRaw`.
 struct Light {
     position: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    range: f32,
     color: vec3<f32>,
+    shadow: u32,
+    inner_cos: f32,
+    outer_cos: f32,
 }
+
+struct Lights {
+    count: u32,
+    lights: array<Light>,
+}
+
 @group(1) @binding(0)
-var<uniform> light: Light;
+var<storage, read> lights: Lights;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
@@ -21,14 +39,18 @@ struct VertexOutput {
     @location(0) color: vec3<f32>,
 };
 
+// One instance per light.
 @vertex
 fn vs_main(
     model: VertexInput,
+    @builtin(instance_index) instance: u32,
 ) -> VertexOutput {
+    let light = lights.lights[instance];
     let scale = 0.25;
     var out: VertexOutput;
     out.clip_position = camera.view_proj * vec4<f32>(model.position * scale + light.position, 1.0);
-    out.color = light.color;
+    // The hue of the light, its intensity would blow out the gizmo.
+    out.color = light.color / max(max(light.color.r, light.color.g), max(light.color.b, 1e-4));
     return out;
 }
 
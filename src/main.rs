@@ -1,13 +1,28 @@
 use anyhow::Result;
-use void::app::App;
+use clap::Parser;
+use void::{
+    app::App,
+    cli::{self, Cli},
+};
 use winit::{event_loop::EventLoop, window::WindowBuilder};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
+    let cli = Cli::parse();
+    if let Some(command) = cli.command {
+        return cli::run(command, cli.load.options()).await;
+    }
+
     let event_loop = EventLoop::new()?;
     let window = WindowBuilder::new().build(&event_loop)?;
     let mut app = App::new(window).await;
+    app.set_load_options(cli.load.options());
+    for file in &cli.files {
+        if let Err(msg) = app.handle_file_drop(file).await {
+            log::error!("{}: {msg}", file.display());
+        }
+    }
     app.run(event_loop).await;
     Ok(())
 }
@@ -1,8 +1,15 @@
 use na::*;
 use nalgebra as na;
-use std::{mem, ops::Range};
+use std::{collections::BTreeMap, mem, ops::Range, sync::OnceLock};
 
-use crate::texture;
+use crate::{
+    bvh::Bvh,
+    io::fs::{
+        stats::{BoundingSphere, Bounds},
+        AlphaMode, MaterialData,
+    },
+    texture,
+};
 
 pub trait Vertex {
     fn desc() -> wgpu::VertexBufferLayout<'static>;
@@ -10,16 +17,82 @@ pub trait Vertex {
 
 pub struct Material {
     pub name: String,
+    /// Description the material was created from, kept for export.
+    pub data: MaterialData,
     pub bind_group: wgpu::BindGroup,
+    /// [`MaterialUniform`] of `data`.
+    pub uniform_buffer: wgpu::Buffer,
     pub diffuse_texture: texture::Texture,
+    pub normal_texture: Option<texture::Texture>,
+    pub metallic_roughness_texture: Option<texture::Texture>,
+    pub emissive_texture: Option<texture::Texture>,
+    pub occlusion_texture: Option<texture::Texture>,
+}
+
+/// Factors of the metallic-roughness model, multiplied with the material textures.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    base_color: [f32; 4],
+    emissive: [f32; 3],
+    metallic: f32,
+    roughness: f32,
+    alpha_cutoff: f32,
+    /// 0 opaque, 1 mask, 2 blend
+    alpha_mode: u32,
+    _padding: u32,
+}
+
+impl MaterialUniform {
+    pub fn new(data: &MaterialData) -> Self {
+        let (alpha_mode, alpha_cutoff) = match data.alpha_mode {
+            AlphaMode::Opaque => (0, 0.0),
+            AlphaMode::Mask(cutoff) => (1, cutoff),
+            AlphaMode::Blend => (2, 0.0),
+        };
+        Self {
+            base_color: data.base_color,
+            emissive: data.emissive,
+            metallic: data.metallic,
+            roughness: data.roughness,
+            alpha_cutoff,
+            alpha_mode,
+            _padding: 0,
+        }
+    }
 }
 
 pub struct Mesh {
     pub name: String,
+    /// CPU copy of the geometry uploaded to `vertex_buffer` and `index_buffer`.
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_elements: u32,
     pub material: usize,
+    pub topology: wgpu::PrimitiveTopology,
+    pub scalars: BTreeMap<String, Vec<f32>>,
+    /// Of `vertices`, `None` when there are none.
+    pub bounds: Option<Bounds>,
+    pub bounding_sphere: Option<BoundingSphere>,
+    /// Built by [`Mesh::bvh`].
+    pub bvh: OnceLock<Option<Bvh>>,
+    /// Geometry read by the debug views, see [`crate::debug::geometry_bind_group`].
+    pub geometry_bind_group: Option<wgpu::BindGroup>,
+}
+
+impl Mesh {
+    /// For ray casts, built on the first one, `None` for other topologies
+    /// than triangle lists.
+    pub fn bvh(&self) -> Option<&Bvh> {
+        self.bvh
+            .get_or_init(|| match self.topology {
+                wgpu::PrimitiveTopology::TriangleList => Bvh::new(&self.vertices, &self.indices),
+                _ => None,
+            })
+            .as_ref()
+    }
 }
 
 pub struct Model {
@@ -112,6 +185,10 @@ pub struct ModelVertex {
     pub position: [f32; 3],
     pub tex_coord: [f32; 2],
     pub normal: [f32; 3],
+    /// Linear RGBA multiplied with the material color.
+    pub color: [f32; 4],
+    /// MikkTSpace tangent, `w` is the sign of the bitangent.
+    pub tangent: [f32; 4],
 }
 
 impl Vertex for ModelVertex {
@@ -127,7 +204,7 @@ impl Vertex for ModelVertex {
                     shader_location: 0,
                 },
                 wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
+                    format: wgpu::VertexFormat::Float32x2,
                     offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
                     shader_location: 1,
                 },
@@ -136,6 +213,16 @@ impl Vertex for ModelVertex {
                     offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
                     shader_location: 2,
                 },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                },
             ],
         }
     }
@@ -0,0 +1,242 @@
+//! Ray casts against the loaded models on the CPU.
+
+use crate::{bvh, camera::Ray, db, model::ModelVertex, ModelEntry};
+
+/// Closest surface along a ray.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub model: db::Id,
+    /// Index in [`ModelEntry::instances`].
+    pub instance: usize,
+    /// Index in [`crate::model::Model::meshes`].
+    pub mesh: usize,
+    /// Index in the index buffer of the mesh divided by 3.
+    pub triangle: usize,
+    /// Weights of the three corners of the triangle at `position`.
+    pub barycentrics: na::Vector3<f32>,
+    pub distance: f32,
+    pub position: na::Point3<f32>,
+}
+
+/// Corners of the `triangle`th triangle of the list `indices` into
+/// `vertices`, `None` when an index is out of range.
+pub fn triangle_corners(
+    vertices: &[ModelVertex],
+    indices: &[u32],
+    triangle: usize,
+) -> Option<[na::Point3<f32>; 3]> {
+    let triangle = indices.get(triangle * 3..triangle * 3 + 3)?;
+    let corner = |i: usize| {
+        let vertex = vertices.get(triangle[i] as usize)?;
+        Some(na::Point3::from(vertex.position))
+    };
+    Some([corner(0)?, corner(1)?, corner(2)?])
+}
+
+/// Distance along `ray` to the triangle `a b c`, seen from either side, and
+/// the barycentric coordinates of the hit for `b` and `c`.
+pub fn ray_triangle(ray: &Ray, [a, b, c]: [na::Point3<f32>; 3]) -> Option<(f32, f32, f32)> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = ray.direction.cross(&ac);
+    let det = ab.dot(&p);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+
+    let s = ray.origin - a;
+    let u = s.dot(&p) / det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&ab);
+    let v = ray.direction.dot(&q) / det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = ac.dot(&q) / det;
+    (distance > 0.0).then_some((distance, u, v))
+}
+
+/// Closest triangle of every instance of `models` hit by `ray`.
+pub fn cast_ray<'a>(
+    models: impl IntoIterator<Item = (db::Id, &'a ModelEntry)>,
+    ray: &Ray,
+) -> Option<Hit> {
+    let mut closest: Option<Hit> = None;
+    for (id, entry) in models {
+        for (instance, isometry) in entry.instances.iter().map(|i| i.isometry).enumerate() {
+            // Isometries keep distances, those in model space are the same.
+            let local = Ray {
+                origin: isometry.inverse_transform_point(&ray.origin),
+                direction: isometry.inverse_transform_vector(&ray.direction),
+            };
+            let inverse = local.direction.map(|d| 1.0 / d);
+            for (index, mesh) in entry.model.meshes.iter().enumerate() {
+                let max_distance = closest.map_or(f32::INFINITY, |hit| hit.distance);
+                // Hierarchies are only built for the meshes the ray gets to.
+                let Some(bounds) = &mesh.bounds else {
+                    continue;
+                };
+                match bvh::ray_box(&local, &inverse, bounds) {
+                    Some(distance) if distance <= max_distance => {}
+                    _ => continue,
+                }
+                let Some(bvh) = mesh.bvh() else {
+                    continue;
+                };
+                if let Some(hit) = bvh.cast_ray(&local, &mesh.vertices, &mesh.indices, max_distance)
+                {
+                    closest = Some(Hit {
+                        model: id,
+                        instance,
+                        mesh: index,
+                        triangle: hit.triangle,
+                        barycentrics: hit.barycentrics,
+                        distance: hit.distance,
+                        position: ray.at(hit.distance),
+                    });
+                }
+            }
+        }
+    }
+    closest
+}
+
+/// Point of a mesh without faces, see [`cast_ray_points`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointHit {
+    pub model: db::Id,
+    pub instance: usize,
+    pub mesh: usize,
+    /// Index in the vertices of the mesh.
+    pub point: usize,
+    /// Along the ray, like [`Hit::distance`].
+    pub distance: f32,
+}
+
+/// Whether `position`, seen by a camera of `view_proj` in a viewport of
+/// `viewport` pixels, lies in front of it within `radius` pixels of `cursor`.
+fn near_cursor(
+    view_proj: &na::Matrix4<f32>,
+    viewport: na::Vector2<f32>,
+    cursor: na::Point2<f32>,
+    radius: f32,
+    position: na::Point3<f32>,
+) -> bool {
+    let clip = view_proj * position.to_homogeneous();
+    if clip.w <= 0.0 {
+        return false;
+    }
+    let ndc = clip.xyz() / clip.w;
+    let pixel = na::Point2::new(
+        (ndc.x + 1.0) / 2.0 * viewport.x,
+        (1.0 - ndc.y) / 2.0 * viewport.y,
+    );
+    (0.0..=1.0).contains(&ndc.z) && na::distance(&pixel, &cursor) <= radius
+}
+
+/// Closest point along `ray` of the meshes without faces of `models`, among
+/// those within `radius` pixels of `cursor`, the pixel `ray` goes through.
+/// The meshes have no hierarchies, every point is tested.
+pub fn cast_ray_points<'a>(
+    models: impl IntoIterator<Item = (db::Id, &'a ModelEntry)>,
+    ray: &Ray,
+    view_proj: &na::Matrix4<f32>,
+    viewport: na::Vector2<f32>,
+    cursor: na::Point2<f32>,
+    radius: f32,
+) -> Option<PointHit> {
+    let mut closest: Option<PointHit> = None;
+    for (id, entry) in models {
+        for (instance, isometry) in entry.instances.iter().map(|i| i.isometry).enumerate() {
+            for (index, mesh) in entry.model.meshes.iter().enumerate() {
+                if mesh.topology != wgpu::PrimitiveTopology::PointList {
+                    continue;
+                }
+                for (point, vertex) in mesh.vertices.iter().enumerate() {
+                    let position = isometry * na::Point3::from(vertex.position);
+                    let distance = (position - ray.origin).dot(&ray.direction);
+                    if closest.is_some_and(|hit| hit.distance <= distance)
+                        || !near_cursor(view_proj, viewport, cursor, radius, position)
+                    {
+                        continue;
+                    }
+                    closest = Some(PointHit {
+                        model: id,
+                        instance,
+                        mesh: index,
+                        point,
+                        distance,
+                    });
+                }
+            }
+        }
+    }
+    closest
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::fs::test_util::vertex;
+
+    #[test]
+    fn rays_hit_triangles_from_both_sides() {
+        let triangle = [
+            na::Point3::new(0.0, 0.0, 0.0),
+            na::Point3::new(1.0, 0.0, 0.0),
+            na::Point3::new(0.0, 1.0, 0.0),
+        ];
+        let ray = Ray {
+            origin: na::Point3::new(0.25, 0.5, 2.0),
+            direction: -na::Vector3::z(),
+        };
+        let (distance, u, v) = ray_triangle(&ray, triangle).unwrap();
+        assert!((distance - 2.0).abs() < 1e-6);
+        assert!((u - 0.25).abs() < 1e-6 && (v - 0.5).abs() < 1e-6);
+
+        let behind = Ray {
+            origin: na::Point3::new(0.25, 0.5, -2.0),
+            ..ray
+        };
+        assert!(ray_triangle(&behind, triangle).is_none());
+        let back = Ray {
+            direction: na::Vector3::z(),
+            ..behind
+        };
+        assert!(ray_triangle(&back, triangle).is_some());
+
+        let outside = Ray {
+            origin: na::Point3::new(0.75, 0.5, 2.0),
+            ..ray
+        };
+        assert!(ray_triangle(&outside, triangle).is_none());
+    }
+
+    #[test]
+    fn points_near_the_cursor_are_hit() {
+        // Orthographic, with depths from 0 to 1 ahead of the camera.
+        let view_proj = na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(1.0, 1.0, -0.1));
+        let viewport = na::Vector2::new(200.0, 200.0);
+        let cursor = na::Point2::new(100.0, 100.0);
+        let near = |x: f32, y: f32, z: f32| {
+            near_cursor(&view_proj, viewport, cursor, 4.0, na::Point3::new(x, y, z))
+        };
+        assert!(near(0.0, 0.0, -1.0));
+        // 2 pixels right and up.
+        assert!(near(0.02, 0.02, -1.0));
+        assert!(!near(0.05, 0.0, -1.0));
+        // Behind the camera.
+        assert!(!near(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn corners_out_of_range_are_none() {
+        let vertices = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]].map(vertex);
+        let corners = triangle_corners(&vertices, &[0, 1, 2], 0).unwrap();
+        assert_eq!(corners[2], na::Point3::new(0.0, 1.0, 0.0));
+        assert!(triangle_corners(&vertices, &[0, 1, 3], 0).is_none());
+        assert!(triangle_corners(&vertices, &[0, 1, 2], 1).is_none());
+    }
+}
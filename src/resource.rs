@@ -1,6 +1,14 @@
 use crate::{
+    debug,
     gpu::Gpu,
-    io::fs::{IMeshFile, MeshFile},
+    io::fs::{
+        normals::{generate_normals, keeps_normals, NormalMode},
+        stats::{BoundingSphere, Bounds},
+        tangents::generate_tangents,
+        uv::{generate_uvs, UvMode},
+        weld::{weld, WeldOptions},
+        IMeshFile, MaterialData, MeshData, MeshFile, TextureData,
+    },
     model, texture,
 };
 use image::codecs::hdr::HdrDecoder;
@@ -13,45 +21,368 @@ pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
     Ok(data)
 }
 
-pub async fn load_model(path: PathBuf, gpu: &Gpu) -> anyhow::Result<model::Model> {
-    let (device, queue) = (&gpu.device, &gpu.queue);
+/// Processing applied to meshes as they are loaded.
+#[derive(Clone, Copy, Debug)]
+pub struct LoadOptions {
+    /// Merges the vertices of triangle meshes before the normals are
+    /// generated, `None` keeps them as stored in the file.
+    pub weld: Option<WeldOptions>,
+    pub normal_mode: NormalMode,
+    /// Used for meshes without texture coordinates.
+    pub uv_mode: UvMode,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            weld: Some(WeldOptions::default()),
+            normal_mode: NormalMode::default(),
+            uv_mode: UvMode::default(),
+        }
+    }
+}
+
+/// Loads a mesh file as one model per group of meshes placed together by the
+/// file's scene graph, each with one instance per placement.
+pub async fn load_scene(
+    path: PathBuf,
+    gpu: &Gpu,
+    options: LoadOptions,
+) -> anyhow::Result<Vec<(model::Model, Vec<model::Instance>)>> {
+    let file_name = path.display().to_string();
+    let mesh_file = open_mesh_file(path)?;
+    let materials = mesh_file.get_materials()?;
+    let meshes = load_meshes(&mesh_file, options)?;
+    let nodes = mesh_file.get_nodes()?;
+
+    if nodes.is_empty() {
+        let model = create_model(gpu, &file_name, meshes, &materials)?;
+        return Ok(vec![(model, vec![model::Instance::default()])]);
+    }
+
+    // Nodes placing the same meshes become instances of one model.
+    let mut groups: Vec<(Vec<usize>, Vec<model::Instance>)> = Vec::new();
+    for node in nodes {
+        let instance = model::Instance {
+            isometry: node.isometry,
+        };
+        match groups.iter_mut().find(|(group, _)| *group == node.meshes) {
+            Some((_, instances)) => instances.push(instance),
+            None => groups.push((node.meshes, vec![instance])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(group, instances)| {
+            let group = group
+                .iter()
+                .filter_map(|&i| meshes.get(i).cloned())
+                .collect();
+            let model = create_model(gpu, &file_name, group, &materials)?;
+            Ok((model, instances))
+        })
+        .collect()
+}
+
+/// Loads a mesh file without a GPU, for conversions and stats: meshes are
+/// placed in world space by the file's scene graph, once per placement.
+pub fn load_world_meshes(
+    path: PathBuf,
+    options: LoadOptions,
+) -> anyhow::Result<(Vec<MeshData>, Vec<MaterialData>)> {
+    let mesh_file = open_mesh_file(path)?;
+    let materials = mesh_file.get_materials()?;
+    let meshes = load_meshes(&mesh_file, options)?;
+    let nodes = mesh_file.get_nodes()?;
+
+    if nodes.is_empty() {
+        return Ok((meshes, materials));
+    }
+
+    let placed = nodes
+        .iter()
+        .flat_map(|node| {
+            node.meshes
+                .iter()
+                .filter_map(|&i| meshes.get(i))
+                .map(|mesh| transform_mesh(mesh, &node.isometry))
+        })
+        .collect();
+    Ok((placed, materials))
+}
+
+fn transform_mesh(mesh: &MeshData, isometry: &na::Isometry3<f32>) -> MeshData {
+    let vertices = mesh
+        .vertices
+        .iter()
+        .map(|v| {
+            let tangent =
+                isometry.rotation * na::Vector3::new(v.tangent[0], v.tangent[1], v.tangent[2]);
+            model::ModelVertex {
+                position: (isometry * na::Point3::from(v.position)).into(),
+                normal: (isometry.rotation * na::Vector3::from(v.normal)).into(),
+                tangent: [tangent.x, tangent.y, tangent.z, v.tangent[3]],
+                ..*v
+            }
+        })
+        .collect();
+    MeshData {
+        vertices,
+        ..mesh.clone()
+    }
+}
+
+/// Octahedron drawn at the position of every light.
+pub fn light_gizmo(gpu: &Gpu) -> anyhow::Result<model::Model> {
+    let corners = [
+        [1.0, 0.0, 0.0],
+        [-1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, -1.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [0.0, 0.0, -1.0],
+    ];
+    let vertices = corners
+        .iter()
+        .map(|&position| model::ModelVertex {
+            position,
+            tex_coord: [0.0; 2],
+            normal: position,
+            color: [1.0; 4],
+            tangent: [0.0; 4],
+        })
+        .collect();
+    #[rustfmt::skip]
+    let indices = vec![
+        0, 2, 4, 4, 2, 1, 1, 2, 5, 5, 2, 0,
+        4, 3, 0, 1, 3, 4, 5, 3, 1, 0, 3, 5,
+    ];
+    let mesh = MeshData {
+        name: "Light".to_string(),
+        vertices,
+        indices,
+        material: None,
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        scalars: Default::default(),
+        has_uv: false,
+    };
+    create_model(gpu, "Light", vec![mesh], &[])
+}
+
+fn open_mesh_file(path: PathBuf) -> anyhow::Result<MeshFile> {
     let file_name = path.display().to_string();
     let mesh_file = MeshFile::new(path)?;
 
-    let vertices = mesh_file.get_vertices()?;
-    let indices = mesh_file.get_indices()?;
+    for warning in mesh_file.get_warnings() {
+        log::warn!("{file_name}: {warning}");
+    }
+
+    Ok(mesh_file)
+}
 
-    let default_texture = texture::Texture::random_texture(device, queue)?;
+fn load_meshes(mesh_file: &MeshFile, options: LoadOptions) -> anyhow::Result<Vec<MeshData>> {
+    Ok(mesh_file
+        .get_meshes()?
+        .iter()
+        .map(|mesh| {
+            let mut mesh = match &options.weld {
+                // File normals the mode keeps also keep their vertices apart.
+                Some(weld_options) => {
+                    weld(mesh, weld_options, keeps_normals(mesh, options.normal_mode))
+                }
+                None => mesh.clone(),
+            };
+            mesh = generate_normals(&mesh, options.normal_mode);
+            if !mesh.has_uv {
+                mesh = generate_uvs(&mesh, options.uv_mode);
+            }
+            // Tangents follow the texture coordinates.
+            generate_tangents(&mesh)
+        })
+        .collect())
+}
 
-    let bind_group = texture::Texture::load(gpu, &default_texture);
+fn create_model(
+    gpu: &Gpu,
+    file_name: &str,
+    meshes: Vec<MeshData>,
+    materials: &[MaterialData],
+) -> anyhow::Result<model::Model> {
+    let device = &gpu.device;
 
-    let materials = vec![model::Material {
-        bind_group,
-        diffuse_texture: default_texture,
+    // Only the materials used by `meshes` are uploaded, `None` is the random
+    // default material for meshes without a (valid) one.
+    let mut used_materials: Vec<Option<usize>> = Vec::new();
+    let mut model_meshes = Vec::new();
+
+    for mesh in meshes {
+        if mesh.indices.is_empty() {
+            continue;
+        }
+
+        let name = if mesh.name.is_empty() {
+            file_name.to_string()
+        } else {
+            format!("{}:{}", file_name, mesh.name)
+        };
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Vertex Buffer", name)),
+            contents: bytemuck::cast_slice(&mesh.vertices),
+            // Also read as storage by the debug views.
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Index Buffer", name)),
+            contents: bytemuck::cast_slice(&mesh.indices),
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::STORAGE,
+        });
+        let geometry_bind_group = debug::geometry_bind_group(device, &vertex_buffer, &index_buffer);
+
+        let material = mesh.material.filter(|&material| material < materials.len());
+        let material = match used_materials.iter().position(|&m| m == material) {
+            Some(slot) => slot,
+            None => {
+                used_materials.push(material);
+                used_materials.len() - 1
+            }
+        };
+
+        let positions = || mesh.vertices.iter().map(|v| na::Point3::from(v.position));
+        let bounds = Bounds::from_points(positions());
+        let bounding_sphere = bounds.map(|bounds| BoundingSphere::around(&bounds, positions()));
+
+        model_meshes.push(model::Mesh {
+            name,
+            num_elements: mesh.indices.len() as u32,
+            vertices: mesh.vertices,
+            indices: mesh.indices,
+            vertex_buffer,
+            index_buffer,
+            material,
+            topology: mesh.topology,
+            scalars: mesh.scalars,
+            bounds,
+            bounding_sphere,
+            bvh: Default::default(),
+            geometry_bind_group,
+        });
+    }
+
+    let materials = used_materials
+        .into_iter()
+        .map(|material| match material {
+            Some(material) => load_material(gpu, &materials[material]),
+            None => default_material(gpu),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(model::Model {
+        meshes: model_meshes,
+        materials,
+    })
+}
+
+fn default_material(gpu: &Gpu) -> anyhow::Result<model::Material> {
+    let data = MaterialData {
         name: "Default texture".to_string(),
-    }];
+        ..Default::default()
+    };
+    let default_texture = texture::Texture::random_texture(&gpu.device, &gpu.queue)?;
+    create_material(gpu, &data, default_texture)
+}
 
-    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some(&format!("{:?} Vertex Buffer", file_name)),
-        contents: bytemuck::cast_slice(&vertices),
-        usage: wgpu::BufferUsages::VERTEX,
-    });
+fn load_material(gpu: &Gpu, material: &MaterialData) -> anyhow::Result<model::Material> {
+    let srgb = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    let diffuse_texture = match load_texture(gpu, material.diffuse_texture.as_ref(), srgb) {
+        Some(texture) => texture,
+        // The base color factor is applied in the shader.
+        None => texture::Texture::solid_texture(&gpu.device, &gpu.queue, [255; 4])?,
+    };
+
+    create_material(gpu, material, diffuse_texture)
+}
+
+fn create_material(
+    gpu: &Gpu,
+    material: &MaterialData,
+    diffuse_texture: texture::Texture,
+) -> anyhow::Result<model::Material> {
+    let (device, queue) = (&gpu.device, &gpu.queue);
+    let srgb = wgpu::TextureFormat::Rgba8UnormSrgb;
+    let linear = wgpu::TextureFormat::Rgba8Unorm;
+
+    let normal_texture = load_texture(gpu, material.normal_texture.as_ref(), linear);
+    let metallic_roughness_texture =
+        load_texture(gpu, material.metallic_roughness_texture.as_ref(), linear);
+    let emissive_texture = load_texture(gpu, material.emissive_texture.as_ref(), srgb);
+    let occlusion_texture = load_texture(gpu, material.occlusion_texture.as_ref(), linear);
 
-    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some(&format!("{:?} Index Buffer", file_name)),
-        contents: bytemuck::cast_slice(&indices),
-        usage: wgpu::BufferUsages::INDEX,
+    // Missing maps are replaced by textures that leave the factors unchanged.
+    let white = texture::Texture::solid_texture(device, queue, [255; 4])?;
+    let flat_normal = texture::Texture::flat_normal_texture(device, queue)?;
+
+    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Material Buffer", material.name)),
+        contents: bytemuck::cast_slice(&[model::MaterialUniform::new(material)]),
+        usage: wgpu::BufferUsages::UNIFORM,
     });
 
-    let meshes = vec![model::Mesh {
-        name: file_name.to_string(),
-        vertex_buffer,
-        index_buffer,
-        material: 0,
-        num_elements: indices.len() as u32,
-    }];
+    let bind_group = texture::Texture::load(
+        gpu,
+        [
+            &diffuse_texture,
+            normal_texture.as_ref().unwrap_or(&flat_normal),
+            metallic_roughness_texture.as_ref().unwrap_or(&white),
+            emissive_texture.as_ref().unwrap_or(&white),
+            occlusion_texture.as_ref().unwrap_or(&white),
+        ],
+        &uniform_buffer,
+    );
+
+    Ok(model::Material {
+        bind_group,
+        uniform_buffer,
+        diffuse_texture,
+        normal_texture,
+        metallic_roughness_texture,
+        emissive_texture,
+        occlusion_texture,
+        name: material.name.clone(),
+        data: material.clone(),
+    })
+}
+
+/// Missing or broken textures are logged and left out of the material.
+fn load_texture(
+    gpu: &Gpu,
+    texture: Option<&TextureData>,
+    format: wgpu::TextureFormat,
+) -> Option<texture::Texture> {
+    let texture = texture?;
+    let label = texture.to_string();
+
+    let loaded = texture.to_image().and_then(|img| {
+        texture::Texture::from_image_with_sampler(
+            &gpu.device,
+            &gpu.queue,
+            &img,
+            Some(&label),
+            format,
+            &texture.sampler().descriptor(),
+        )
+    });
 
-    Ok(model::Model { meshes, materials })
+    match loaded {
+        Ok(loaded) => Some(loaded),
+        Err(err) => {
+            log::warn!("Could not load texture {label}: {err}");
+            None
+        }
+    }
 }
 
 pub struct HdrLoader {
@@ -118,8 +449,6 @@ impl HdrLoader {
         dst_size: u32,
         label: Option<&str>,
     ) -> anyhow::Result<texture::CubeTexture> {
-        let device = &gpu.device;
-        let queue = &gpu.queue;
         let hdr_decoder = HdrDecoder::new(Cursor::new(data))?;
         let meta = hdr_decoder.metadata();
 
@@ -145,10 +474,27 @@ impl HdrLoader {
             })
             .collect::<Vec<_>>();
 
+        self.from_equirectangular_pixels(gpu, meta.width, meta.height, &pixels, dst_size, label)
+    }
+
+    /// Same as [`HdrLoader::from_equirectangular_bytes`] for decoded linear
+    /// RGBA `pixels`, row by row from the top.
+    pub fn from_equirectangular_pixels(
+        &self,
+        gpu: &Gpu,
+        width: u32,
+        height: u32,
+        pixels: &[[f32; 4]],
+        dst_size: u32,
+        label: Option<&str>,
+    ) -> anyhow::Result<texture::CubeTexture> {
+        let device = &gpu.device;
+        let queue = &gpu.queue;
+
         let src = texture::Texture::create_2d_texture(
             gpu,
-            meta.width,
-            meta.height,
+            height,
+            width,
             self.texture_format,
             wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
             wgpu::FilterMode::Linear,
@@ -162,7 +508,7 @@ impl HdrLoader {
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            &bytemuck::cast_slice(&pixels),
+            bytemuck::cast_slice(pixels),
             wgpu::ImageDataLayout {
                 offset: 0,
                 bytes_per_row: Some(src.size.width * std::mem::size_of::<[f32; 4]>() as u32),
@@ -224,4 +570,61 @@ impl HdrLoader {
 
         Ok(dst)
     }
+
+    pub async fn from_sky(
+        &self,
+        gpu: &Gpu,
+        sky: Sky<'_>,
+        dst_size: u32,
+        label: Option<&str>,
+    ) -> anyhow::Result<texture::CubeTexture> {
+        match sky {
+            Sky::Default => match load_binary("pure-sky.hdr").await {
+                Ok(bytes) => self.from_equirectangular_bytes(gpu, &bytes, dst_size, label),
+                Err(msg) => {
+                    log::warn!("No pure-sky.hdr, using a gradient sky: {msg}");
+                    let (width, height, pixels) = gradient_sky();
+                    self.from_equirectangular_pixels(gpu, width, height, &pixels, dst_size, label)
+                }
+            },
+            Sky::Hdr(bytes) => self.from_equirectangular_bytes(gpu, bytes, dst_size, label),
+            Sky::Gradient => {
+                let (width, height, pixels) = gradient_sky();
+                self.from_equirectangular_pixels(gpu, width, height, &pixels, dst_size, label)
+            }
+        }
+    }
+}
+
+/// Environment drawn behind the scene and lighting it.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Sky<'a> {
+    /// `models/pure-sky.hdr`, or `Gradient` without it.
+    #[default]
+    Default,
+    /// Equirectangular Radiance HDR image.
+    Hdr(&'a [u8]),
+    /// Blue sky over a gray ground, the same everywhere.
+    Gradient,
+}
+
+/// Equirectangular sky brightest at the horizon, as width, height and pixels.
+fn gradient_sky() -> (u32, u32, Vec<[f32; 4]>) {
+    let (width, height) = (64, 32);
+    let zenith = na::Vector3::new(0.25, 0.45, 0.9);
+    let horizon = na::Vector3::new(0.9, 0.9, 0.95);
+    let ground = na::Vector3::new(0.25, 0.22, 0.2);
+
+    let pixels = (0..height)
+        .flat_map(|y| {
+            let elevation = (0.5 - (y as f32 + 0.5) / height as f32) * std::f32::consts::PI;
+            let color = if elevation > 0.0 {
+                horizon.lerp(&zenith, elevation.sin())
+            } else {
+                ground
+            };
+            std::iter::repeat([color.x, color.y, color.z, 1.0]).take(width as usize)
+        })
+        .collect();
+    (width, height, pixels)
 }
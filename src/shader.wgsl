@@ -1,18 +1,62 @@
 // Vertex shader
+// Must match `camera::CameraUniform`.
 struct CameraUniform {
-    view_proj: mat4x4<f32>,
     view_pos: vec4<f32>,
+    view: mat4x4<f32>,
+    view_proj: mat4x4<f32>,
+    inv_proj: mat4x4<f32>,
+    inv_view: mat4x4<f32>,
 };
 @group(1) @binding(0) // 1.
 var<uniform> camera: CameraUniform;
 
+const LIGHT_POINT: u32 = 0u;
+const LIGHT_SPOT: u32 = 1u;
+const LIGHT_DIRECTIONAL: u32 = 2u;
+const SHADOW_POINT: u32 = 1u;
+const SHADOW_DIRECTIONAL: u32 = 2u;
+const CASCADES: u32 = 4u;
+
+// Must match `light::LightRaw`.
 struct Light {
     position: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    range: f32,
     color: vec3<f32>,
+    shadow: u32,
+    inner_cos: f32,
+    outer_cos: f32,
+}
+
+struct Lights {
+    count: u32,
+    lights: array<Light>,
 }
 
 @group(2) @binding(0)
-var<uniform> light: Light;
+var<storage, read> lights: Lights;
+
+struct Shadow {
+    cascades: array<mat4x4<f32>, 4>,
+    splits: vec4<f32>,
+    near: f32,
+    far: f32,
+    depth_bias: f32,
+    normal_bias: f32,
+    pcf_radius: f32,
+    texel_size: f32,
+    enabled: u32,
+}
+
+@group(2) @binding(1)
+var t_shadow: texture_depth_cube;
+@group(2) @binding(2)
+var s_shadow: sampler_comparison;
+@group(2) @binding(3)
+var<uniform> shadow: Shadow;
+@group(2) @binding(4)
+var t_cascades: texture_depth_2d_array;
 
 struct InstanceInput {
     @location(5) model_matrix_0: vec4<f32>,
@@ -30,6 +74,8 @@ struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
     @location(2) normal: vec3<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) tangent: vec4<f32>,
 }
 
 struct VertexOutput {
@@ -37,14 +83,21 @@ struct VertexOutput {
     @location(0) tex_coords: vec2<f32>,
     @location(1) world_normal: vec3<f32>,
     @location(2) world_position: vec3<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) world_tangent: vec4<f32>,
+    // Model, mesh and instance, see `id_buffer::DrawIds`.
+    @location(5) @interpolate(flat) ids: vec3<u32>,
 }
 
 @vertex
 fn vs_main(
     model: VertexInput,
-    instance: InstanceInput
+    instance: InstanceInput,
+    @location(12) ids: vec2<u32>,
+    @builtin(instance_index) instance_index: u32,
 ) -> VertexOutput {
     var out: VertexOutput;
+    out.ids = vec3(ids, instance_index);
     let model_matrix = mat4x4<f32>(
         instance.model_matrix_0,
         instance.model_matrix_1,
@@ -59,7 +112,9 @@ fn vs_main(
     );
 
     out.tex_coords = model.tex_coords;
+    out.color = model.color;
     out.world_normal = normal_matrix * model.normal;
+    out.world_tangent = vec4<f32>(normal_matrix * model.tangent.xyz, model.tangent.w);
     var world_position: vec4<f32> = model_matrix * vec4<f32>(model.position, 1.0);
     out.world_position = world_position.xyz;
     out.clip_position = camera.view_proj * world_position;
@@ -73,29 +128,296 @@ var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+@group(0) @binding(2)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(3)
+var s_normal: sampler;
+@group(0) @binding(4)
+var t_metallic_roughness: texture_2d<f32>;
+@group(0) @binding(5)
+var s_metallic_roughness: sampler;
+@group(0) @binding(6)
+var t_emissive: texture_2d<f32>;
+@group(0) @binding(7)
+var s_emissive: sampler;
+@group(0) @binding(8)
+var t_occlusion: texture_2d<f32>;
+@group(0) @binding(9)
+var s_occlusion: sampler;
+
+struct Material {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    alpha_cutoff: f32,
+    alpha_mode: u32,
+}
+
+@group(0) @binding(10)
+var<uniform> material: Material;
+
+struct Environment {
+    intensity: f32,
+    rotation: f32,
+    max_lod: f32,
+}
+
+@group(3) @binding(0)
+var t_irradiance: texture_cube<f32>;
+@group(3) @binding(1)
+var t_prefiltered: texture_cube<f32>;
+@group(3) @binding(2)
+var t_brdf_lut: texture_2d<f32>;
+@group(3) @binding(3)
+var s_environment: sampler;
+@group(3) @binding(4)
+var<uniform> environment: Environment;
+
+const PI: f32 = 3.14159265359;
+const ALPHA_MASK: u32 = 1u;
+const ALPHA_BLEND: u32 = 2u;
+
 fn check_coords(in: VertexOutput) -> vec4f {
 	return textureSample(t_diffuse, s_diffuse, in.tex_coords);
 }
 
+// Perturbs the interpolated normal with a sample of the tangent space normal map.
+fn surface_normal(in: VertexOutput, normal_sample: vec4<f32>) -> vec3<f32> {
+    let n = normalize(in.world_normal);
+    let t = in.world_tangent.xyz;
+    if (dot(t, t) < 1e-8) {
+        return n;
+    }
+
+    // Re-orthogonalize, interpolation skews the basis.
+    let tangent = normalize(t - dot(t, n) * n);
+    let bitangent = cross(n, tangent) * in.world_tangent.w;
+    let tangent_normal = normal_sample.xyz * 2.0 - 1.0;
+    return normalize(mat3x3<f32>(tangent, bitangent, n) * tangent_normal);
+}
+
+// Trowbridge-Reitz GGX normal distribution.
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Schlick-GGX masking for one direction, remapped for analytic lights.
+fn geometry_schlick_ggx(n_dot_x: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+// Fresnel of the environment, rough surfaces reflect less at grazing angles.
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    return f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Direction in the unrotated sky cube.
+fn environment_direction(dir: vec3<f32>) -> vec3<f32> {
+    let c = cos(environment.rotation);
+    let s = sin(environment.rotation);
+    return vec3<f32>(c * dir.x - s * dir.z, dir.y, s * dir.x + c * dir.z);
+}
+
+// Fraction of the light reaching `position`, filtered over a 3x3 grid of
+// shadow map texels.
+fn point_shadow(light: Light, position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let d = position + normal * shadow.normal_bias - light.position;
+    // Distance along the axis of the cube face, which is what the face stores.
+    let z = max(abs(d.x), max(abs(d.y), abs(d.z))) - shadow.depth_bias;
+    if (z <= shadow.near || z >= shadow.far) {
+        return 1.0;
+    }
+    let range = shadow.far - shadow.near;
+    let reference = shadow.far / range - shadow.far * shadow.near / (range * z);
+
+    let dir = normalize(d);
+    let up = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(dir.y) > 0.99);
+    let tangent = normalize(cross(up, dir));
+    let bitangent = cross(dir, tangent);
+    let step = shadow.pcf_radius * shadow.texel_size * z;
+
+    var lit = 0.0;
+    for (var i = -1; i <= 1; i++) {
+        for (var j = -1; j <= 1; j++) {
+            let offset = (tangent * f32(i) + bitangent * f32(j)) * step;
+            lit += textureSampleCompareLevel(t_shadow, s_shadow, d + offset, reference);
+        }
+    }
+    return lit / 9.0;
+}
+
+// Same as `point_shadow` for a directional light, from the cascade covering
+// the view depth of `position`.
+fn directional_shadow(light: Light, position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let depth = -(camera.view * vec4<f32>(position, 1.0)).z;
+    var cascade = 0u;
+    while (cascade < CASCADES && depth > shadow.splits[cascade]) {
+        cascade++;
+    }
+    if (cascade == CASCADES) {
+        return 1.0;
+    }
+
+    let biased = position + normal * shadow.normal_bias - light.direction * shadow.depth_bias;
+    let clip = shadow.cascades[cascade] * vec4<f32>(biased, 1.0);
+    let uv = clip.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || clip.z > 1.0) {
+        return 1.0;
+    }
+
+    let step = shadow.pcf_radius / vec2<f32>(textureDimensions(t_cascades));
+    var lit = 0.0;
+    for (var i = -1; i <= 1; i++) {
+        for (var j = -1; j <= 1; j++) {
+            let offset = vec2<f32>(f32(i), f32(j)) * step;
+            lit += textureSampleCompareLevel(t_cascades, s_shadow, uv + offset, cascade, clip.z);
+        }
+    }
+    return lit / 9.0;
+}
+
+struct Incoming {
+    // Direction towards the light.
+    l: vec3<f32>,
+    radiance: vec3<f32>,
+}
+
+// Light arriving at `position`.
+fn incoming_light(light: Light, position: vec3<f32>, normal: vec3<f32>) -> Incoming {
+    if (light.kind == LIGHT_DIRECTIONAL) {
+        var radiance = light.color;
+        if (light.shadow == SHADOW_DIRECTIONAL && shadow.enabled != 0u) {
+            radiance *= directional_shadow(light, position, normal);
+        }
+        return Incoming(-light.direction, radiance);
+    }
+
+    let to_light = light.position - position;
+    let distance = length(to_light);
+    let l = to_light / max(distance, 1e-4);
+
+    // Inverse square falloff, windowed to reach zero at the range.
+    let window = clamp(1.0 - pow(distance / light.range, 4.0), 0.0, 1.0);
+    var radiance = light.color * window * window / max(distance * distance, 1e-4);
+
+    if (light.kind == LIGHT_SPOT) {
+        let cos_angle = dot(-l, light.direction);
+        radiance *= smoothstep(light.outer_cos, light.inner_cos, cos_angle);
+    }
+    if (light.shadow == SHADOW_POINT && shadow.enabled != 0u) {
+        radiance *= point_shadow(light, position, normal);
+    }
+    return Incoming(l, radiance);
+}
+
+// Cook-Torrance reflection of the light coming from `l`.
+fn brdf(n: vec3<f32>, v: vec3<f32>, l: vec3<f32>, base_color: vec3<f32>, f0: vec3<f32>, metallic: f32, roughness: f32) -> vec3<f32> {
+    let h = normalize(v + l);
+    let n_dot_l = max(dot(n, l), 0.0);
+    let n_dot_v = max(dot(n, v), 1e-4);
+    let n_dot_h = max(dot(n, h), 0.0);
+
+    let f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+    let d = distribution_ggx(n_dot_h, roughness);
+    let g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+    let specular = d * g * f / (4.0 * n_dot_v * n_dot_l + 1e-4);
+
+    // Metals have no diffuse reflection.
+    let k_d = (vec3<f32>(1.0) - f) * (1.0 - metallic);
+    let diffuse = k_d * base_color / PI;
+
+    return (diffuse + specular) * n_dot_l;
+}
+
+// Split sum approximation of the light reflected from the environment.
+fn ambient_light(n: vec3<f32>, v: vec3<f32>, n_dot_v: f32, base_color: vec3<f32>, f0: vec3<f32>, metallic: f32, roughness: f32) -> vec3<f32> {
+    let f = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    let k_d = (vec3<f32>(1.0) - f) * (1.0 - metallic);
+
+    // Explicit levels, these lookups happen in non-uniform control flow.
+    let irradiance = textureSampleLevel(t_irradiance, s_environment, environment_direction(n), 0.0).rgb;
+    let r = environment_direction(reflect(-v, n));
+    let prefiltered = textureSampleLevel(t_prefiltered, s_environment, r, roughness * environment.max_lod).rgb;
+    let brdf = textureSampleLevel(t_brdf_lut, s_environment, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+
+    let diffuse = k_d * irradiance * base_color;
+    let specular = prefiltered * (f * brdf.x + brdf.y);
+    return (diffuse + specular) * environment.intensity;
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Must match `id_buffer::NO_PRIMITIVE`.
+const NO_PRIMITIVE: u32 = 0xffffffffu;
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    // Read back by `id_buffer::IdBuffer`, ignored with MSAA.
+    @location(1) ids: vec4<u32>,
+}
+
+fn fragment(in: VertexOutput, primitive: u32) -> FragmentOutput {
+    return FragmentOutput(shade(in), vec4(in.ids, primitive));
+}
+
+// Without `SHADER_PRIMITIVE_INDEX`, see `id_primitive.wgsl`.
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let object_color: vec4<f32> = check_coords(in);
-    let ambient_strength = 0.1;
-    let ambient_color = light.color * ambient_strength;
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    return fragment(in, NO_PRIMITIVE);
+}
+
+fn shade(in: VertexOutput) -> vec4<f32> {
+    // Sampled before branching, textureSample needs uniform control flow.
+    let base_sample = check_coords(in);
+    let normal_sample = textureSample(t_normal, s_normal, in.tex_coords);
+    let metallic_roughness = textureSample(t_metallic_roughness, s_metallic_roughness, in.tex_coords);
+    let emissive_sample = textureSample(t_emissive, s_emissive, in.tex_coords);
+    let occlusion = textureSample(t_occlusion, s_occlusion, in.tex_coords).r;
+
+    let base_color = material.base_color * base_sample * in.color;
+    let emissive = material.emissive * emissive_sample.rgb;
+
+    var alpha = 1.0;
+    if (material.alpha_mode == ALPHA_BLEND) {
+        alpha = base_color.a;
+    } else if (material.alpha_mode == ALPHA_MASK && base_color.a < material.alpha_cutoff) {
+        discard;
+    }
 
-    let light_dir = normalize(light.position - in.world_position);
+    // Point clouds often come without normals, show their color unlit.
+    if (dot(in.world_normal, in.world_normal) < 1e-8) {
+        return vec4<f32>(base_color.rgb + emissive, alpha);
+    }
 
-    let diffuse_strength = max(dot(in.world_normal, light_dir), 0.0);
-    let diffuse_color = light.color * diffuse_strength;
+    // glTF packs roughness in green and metalness in blue.
+    let metallic = material.metallic * metallic_roughness.b;
+    let roughness = clamp(material.roughness * metallic_roughness.g, 0.04, 1.0);
 
-    let view_dir = normalize(camera.view_pos.xyz - in.world_position);
-    let half_dir = normalize(view_dir + light_dir);
-    let reflect_dir = reflect(-light_dir, in.world_normal);
+    let n = surface_normal(in, normal_sample);
+    let v = normalize(camera.view_pos.xyz - in.world_position);
+    let n_dot_v = max(dot(n, v), 1e-4);
 
-    let specular_strength = pow(max(dot(in.world_normal, half_dir), 0.0), 32.0);
-    let specular_color = specular_strength * light.color;
+    // Dielectrics reflect about 4% at normal incidence.
+    let f0 = mix(vec3<f32>(0.04), base_color.rgb, metallic);
 
-    let result = (ambient_color + diffuse_color + specular_color) * object_color.xyz;
+    // Shadows are biased along the geometric normal, normal maps would make
+    // the offset noisy.
+    let geometric_normal = normalize(in.world_normal);
+    var direct = vec3<f32>(0.0);
+    for (var i = 0u; i < lights.count; i++) {
+        let light = lights.lights[i];
+        let incoming = incoming_light(light, in.world_position, geometric_normal);
+        direct += brdf(n, v, incoming.l, base_color.rgb, f0, metallic, roughness) * incoming.radiance;
+    }
+    let ambient = ambient_light(n, v, n_dot_v, base_color.rgb, f0, metallic, roughness) * occlusion;
 
-    return vec4<f32>(result, object_color.a);
+    return vec4<f32>(ambient + direct + emissive, alpha);
 }
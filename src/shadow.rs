@@ -0,0 +1,474 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::Projection,
+    gpu::Gpu,
+    model::{InstanceRaw, ModelVertex, Vertex},
+    texture, ModelEntry,
+};
+
+const SHADOW_SIZE: u32 = 1024;
+const NEAR: f32 = 0.05;
+const FAR: f32 = 100.0;
+
+const CASCADES: usize = 4;
+const CASCADE_SIZE: u32 = 2048;
+/// Blend between logarithmic (1) and uniform (0) cascade splits.
+const CASCADE_SPLIT_LAMBDA: f32 = 0.75;
+/// How far behind a cascade casters are still rendered, in world units.
+const CASCADE_BACK: f32 = 50.0;
+
+/// View direction and up vector of every cube face, in layer order.
+///
+/// Rendering uses a projection with a flipped y axis so that the faces match
+/// the orientation cube samplers expect.
+const FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+];
+
+/// User controls for shadow quality.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    /// Distance, in world units, surfaces are moved towards the light before
+    /// the depth comparison.
+    pub depth_bias: f32,
+    /// Distance, in world units, surfaces are moved along their normal before
+    /// the depth comparison.
+    pub normal_bias: f32,
+    /// Spacing of the percentage closer filtering samples, in shadow map texels.
+    pub pcf_radius: f32,
+    /// View distance covered by the cascades of the directional shadow.
+    pub distance: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            depth_bias: 0.01,
+            normal_bias: 0.02,
+            pcf_radius: 1.5,
+            distance: 50.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    cascades: [[[f32; 4]; 4]; CASCADES],
+    /// View depth at which every cascade ends.
+    splits: [f32; CASCADES],
+    near: f32,
+    far: f32,
+    depth_bias: f32,
+    normal_bias: f32,
+    pcf_radius: f32,
+    /// Size of a texel on a cube face at distance 1 from the light.
+    texel_size: f32,
+    enabled: u32,
+    _padding: u32,
+}
+
+/// Target of one shadow pass, a cube face or a cascade.
+struct ShadowFace {
+    view: wgpu::TextureView,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Shadow maps of the scene lights, a cube map around a point or spot light
+/// and cascades following the camera for a directional light.
+pub struct ShadowMaps {
+    /// Cube view to sample from.
+    cube_view: wgpu::TextureView,
+    cube_faces: Vec<ShadowFace>,
+    /// Array view to sample from.
+    cascade_view: wgpu::TextureView,
+    cascades: Vec<ShadowFace>,
+    sampler: wgpu::Sampler,
+    uniform: ShadowUniform,
+    uniform_buffer: wgpu::Buffer,
+    pipeline: wgpu::RenderPipeline,
+    /// Position of the light rendered into the cube map.
+    point_light: Option<[f32; 3]>,
+    /// Direction of the light rendered into the cascades.
+    directional_light: Option<[f32; 3]>,
+}
+
+fn depth_texture(device: &wgpu::Device, label: &str, size: u32, layers: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: layers,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: texture::Texture::DEPTH_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
+
+const fn depth_entry(
+    binding: u32,
+    view_dimension: wgpu::TextureViewDimension,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension,
+            sample_type: wgpu::TextureSampleType::Depth,
+        },
+        count: None,
+    }
+}
+
+impl ShadowMaps {
+    /// Entries of the light bind group holding the cube map, the comparison
+    /// sampler, the [`ShadowSettings`] uniform and the cascades, starting at
+    /// `binding`.
+    pub const fn layout_entries(binding: u32) -> [wgpu::BindGroupLayoutEntry; 4] {
+        [
+            depth_entry(binding, wgpu::TextureViewDimension::Cube),
+            wgpu::BindGroupLayoutEntry {
+                binding: binding + 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: binding + 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            depth_entry(binding + 3, wgpu::TextureViewDimension::D2Array),
+        ]
+    }
+
+    pub fn new(gpu: &Gpu) -> Self {
+        let device = &gpu.device;
+
+        let face_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("ShadowMaps::face_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let faces = |texture: &wgpu::Texture, layers: u32| {
+            (0..layers)
+                .map(|layer| {
+                    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                        dimension: Some(wgpu::TextureViewDimension::D2),
+                        base_array_layer: layer,
+                        array_layer_count: Some(1),
+                        ..Default::default()
+                    });
+                    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Shadow Face"),
+                        contents: bytemuck::cast_slice(&[[[0.0f32; 4]; 4]]),
+                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    });
+                    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: None,
+                        layout: &face_layout,
+                        entries: &[wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: buffer.as_entire_binding(),
+                        }],
+                    });
+                    ShadowFace {
+                        view,
+                        buffer,
+                        bind_group,
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let cube = depth_texture(device, "Point Shadow", SHADOW_SIZE, 6);
+        let cube_view = cube.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let cube_faces = faces(&cube, 6);
+
+        let cascade_texture =
+            depth_texture(device, "Directional Shadow", CASCADE_SIZE, CASCADES as u32);
+        let cascade_view = cascade_texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let cascades = faces(&cascade_texture, CASCADES as u32);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let uniform = ShadowUniform {
+            cascades: [[[0.0; 4]; 4]; CASCADES],
+            splits: [0.0; CASCADES],
+            near: NEAR,
+            far: FAR,
+            depth_bias: 0.0,
+            normal_bias: 0.0,
+            pcf_radius: 0.0,
+            texel_size: 2.0 / SHADOW_SIZE as f32,
+            enabled: 0,
+            _padding: 0,
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow Uniform"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shadow.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&face_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[ModelVertex::desc(), InstanceRaw::desc()],
+            },
+            // Only depth is written.
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                // The flipped cube projection reverses the winding, and open
+                // meshes cast shadows from both sides anyway.
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                // Slope scaled bias against acne on surfaces at grazing angles.
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            cube_view,
+            cube_faces,
+            cascade_view,
+            cascades,
+            sampler,
+            uniform,
+            uniform_buffer,
+            pipeline,
+            point_light: None,
+            directional_light: None,
+        }
+    }
+
+    /// Entries matching [`ShadowMaps::layout_entries`].
+    pub fn bind_group_entries(&self, binding: u32) -> [wgpu::BindGroupEntry<'_>; 4] {
+        [
+            wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(&self.cube_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: binding + 1,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: binding + 2,
+                resource: self.uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: binding + 3,
+                resource: wgpu::BindingResource::TextureView(&self.cascade_view),
+            },
+        ]
+    }
+
+    /// Places the cube map at `point_light` and fits the cascades around the
+    /// camera for a light shining along `directional_light`.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        point_light: Option<[f32; 3]>,
+        directional_light: Option<[f32; 3]>,
+        camera_view: &na::Matrix4<f32>,
+        projection: &Projection,
+        settings: &ShadowSettings,
+    ) {
+        let enabled = settings.enabled;
+        self.point_light = point_light.filter(|_| enabled);
+        self.directional_light = directional_light.filter(|_| enabled);
+
+        if let Some(position) = self.point_light {
+            // 90° field of view with [0, 1] depth and y pointing down.
+            #[rustfmt::skip]
+            let projection = na::Matrix4::new(
+                1.0, 0.0, 0.0, 0.0,
+                0.0, -1.0, 0.0, 0.0,
+                0.0, 0.0, FAR / (NEAR - FAR), NEAR * FAR / (NEAR - FAR),
+                0.0, 0.0, -1.0, 0.0,
+            );
+            let eye = na::Point3::from(position);
+            for ((direction, up), face) in FACES.iter().zip(&self.cube_faces) {
+                let target = eye + na::Vector3::from(*direction);
+                let view = na::Matrix4::look_at_rh(&eye, &target, &na::Vector3::from(*up));
+                let view_proj: [[f32; 4]; 4] = (projection * view).into();
+                queue.write_buffer(&face.buffer, 0, bytemuck::cast_slice(&[view_proj]));
+            }
+        }
+
+        if let Some(direction) = self.directional_light {
+            let near = projection.positive_znear();
+            let far = projection.zfar().min(settings.distance).max(near);
+            let mut start = near;
+            for (i, face) in self.cascades.iter().enumerate() {
+                let t = (i + 1) as f32 / CASCADES as f32;
+                let end = CASCADE_SPLIT_LAMBDA * near * (far / near).powf(t)
+                    + (1.0 - CASCADE_SPLIT_LAMBDA) * (near + (far - near) * t);
+
+                let corners = projection.slice_corners(camera_view, start, end);
+                let view_proj = cascade_matrix(&corners, direction.into());
+                queue.write_buffer(&face.buffer, 0, bytemuck::cast_slice(&[view_proj]));
+
+                self.uniform.cascades[i] = view_proj;
+                self.uniform.splits[i] = end;
+                start = end;
+            }
+        }
+
+        self.uniform.depth_bias = settings.depth_bias;
+        self.uniform.normal_bias = settings.normal_bias;
+        self.uniform.pcf_radius = settings.pcf_radius;
+        self.uniform.enabled = enabled as u32;
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    /// Renders the depth of every triangle mesh into the maps in use.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, models: &[&ModelEntry]) {
+        let cube_faces = self.point_light.map(|_| &self.cube_faces);
+        let cascades = self.directional_light.map(|_| &self.cascades);
+
+        for face in cube_faces.into_iter().chain(cascades).flatten() {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &face.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &face.bind_group, &[]);
+            for entry in models {
+                pass.set_vertex_buffer(1, entry.instance_buffer.slice(..));
+                for mesh in &entry.model.meshes {
+                    if mesh.topology != wgpu::PrimitiveTopology::TriangleList {
+                        continue;
+                    }
+                    pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    pass.draw_indexed(0..mesh.num_elements, 0, 0..entry.instances.len() as u32);
+                }
+            }
+        }
+    }
+}
+
+/// Orthographic view projection of a light shining along `direction` that
+/// covers the bounding sphere of `corners`.
+///
+/// The sphere keeps the size constant as the camera turns and the origin is
+/// snapped to whole texels, so that shadow edges don't shimmer.
+fn cascade_matrix(corners: &[na::Point3<f32>; 8], direction: na::Vector3<f32>) -> [[f32; 4]; 4] {
+    let direction = direction
+        .try_normalize(f32::EPSILON)
+        .unwrap_or_else(|| -na::Vector3::y());
+    let center = corners.iter().map(|p| p.coords).sum::<na::Vector3<f32>>() / 8.0;
+    let center = na::Point3::from(center);
+    let radius = corners
+        .iter()
+        .map(|p| na::distance(p, &center))
+        .fold(0.0, f32::max);
+    let radius = ((radius * 16.0).ceil() / 16.0).max(f32::EPSILON);
+
+    let up = if direction.y.abs() > 0.99 {
+        na::Vector3::x()
+    } else {
+        na::Vector3::y()
+    };
+    let eye = center - direction * (radius + CASCADE_BACK);
+    let view = na::Matrix4::look_at_rh(&eye, &center, &up);
+
+    let depth = 2.0 * radius + CASCADE_BACK;
+    #[rustfmt::skip]
+    let mut projection = na::Matrix4::new(
+        1.0 / radius, 0.0, 0.0, 0.0,
+        0.0, 1.0 / radius, 0.0, 0.0,
+        0.0, 0.0, -1.0 / depth, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+    );
+
+    let origin = (projection * view).transform_point(&na::Point3::origin());
+    let texels = origin.coords.xy() * CASCADE_SIZE as f32 / 2.0;
+    let offset = (texels.map(f32::round) - texels) * 2.0 / CASCADE_SIZE as f32;
+    projection[(0, 3)] += offset.x;
+    projection[(1, 3)] += offset.y;
+
+    (projection * view).into()
+}
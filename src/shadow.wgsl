@@ -0,0 +1,28 @@
+// Depth of the scene seen from a light, one cube face or cascade per pass.
+
+struct Face {
+    view_proj: mat4x4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> face: Face;
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    instance: InstanceInput,
+) -> @builtin(position) vec4<f32> {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    return face.view_proj * model_matrix * vec4<f32>(position, 1.0);
+}
@@ -16,6 +16,22 @@ var env_map: texture_cube<f32>;
 @binding(1)
 var env_sampler: sampler;
 
+struct Environment {
+    intensity: f32,
+    rotation: f32,
+    max_lod: f32,
+}
+@group(1)
+@binding(2)
+var<uniform> environment: Environment;
+
+// Direction in the unrotated sky cube.
+fn environment_direction(dir: vec3<f32>) -> vec3<f32> {
+    let c = cos(environment.rotation);
+    let s = sin(environment.rotation);
+    return vec3<f32>(c * dir.x - s * dir.z, dir.y, s * dir.x + c * dir.z);
+}
+
 struct VertexOutput {
     @builtin(position) frag_position: vec4<f32>,
     @location(0) clip_position: vec4<f32>,
@@ -31,8 +47,10 @@ fn vs_main(
     ));
     var out: VertexOutput;
     // out.clip_position = vec4(uv * vec2(4.0, -4.0) + vec2(-1.0, 1.0), 0.0, 1.0);
+    // Unprojected from the near plane, the far one is at infinity.
     out.clip_position = vec4(uv * 4.0 - 1.0, 1.0, 1.0);
-    out.frag_position = vec4(uv * 4.0 - 1.0, 1.0, 1.0);
+    // Behind everything with the reversed depth.
+    out.frag_position = vec4(uv * 4.0 - 1.0, 0.0, 1.0);
     return out;
 }
 
@@ -42,7 +60,7 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let view_ray_direction = view_pos_homogeneous.xyz / view_pos_homogeneous.w;
     var ray_direction = normalize((camera.inv_view * vec4(view_ray_direction, 0.0)).xyz);
 
-    let sample = textureSample(env_map, env_sampler, ray_direction);
-    return sample;
+    let sample = textureSample(env_map, env_sampler, environment_direction(ray_direction));
+    return vec4(sample.rgb * environment.intensity, sample.a);
 }
 
@@ -1,6 +1,6 @@
 use std::ops::RangeInclusive;
 
-use crate::gpu::Gpu;
+use crate::{gpu::Gpu, io::fs::SamplerData};
 use anyhow::*;
 use image::{DynamicImage, GenericImageView};
 use image::{ImageBuffer, Rgba};
@@ -9,6 +9,29 @@ use rand::Rng;
 const WHITE: [u8; 4] = [255, 255, 255, 255];
 const BRIGHT_RANGE: RangeInclusive<u8> = 124..=255;
 
+const fn texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    }
+}
+
+const fn sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        // This should match the filterable field of the corresponding texture entry.
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    }
+}
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
@@ -18,46 +41,66 @@ pub struct Texture {
 
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    /// Depth test of the camera passes. The depth is reversed, 1 on the near
+    /// plane and 0 at infinity, see [`crate::camera::Projection`].
+    pub const DEPTH_COMPARE: wgpu::CompareFunction = wgpu::CompareFunction::GreaterEqual;
+    /// Depth the camera depth texture is cleared to, the farthest.
+    pub const DEPTH_CLEAR: f32 = 0.0;
+    /// Material bind group, see [`Texture::load`].
     pub const BIND_GROUP_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor<'static> =
         wgpu::BindGroupLayoutDescriptor {
             label: Some("Texture Bind Group Layout"),
             entries: &[
+                texture_entry(0),
+                sampler_entry(1),
+                texture_entry(2),
+                sampler_entry(3),
+                texture_entry(4),
+                sampler_entry(5),
+                texture_entry(6),
+                sampler_entry(7),
+                texture_entry(8),
+                sampler_entry(9),
                 wgpu::BindGroupLayoutEntry {
-                    binding: 0,
+                    binding: 10,
                     visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
                     },
                     count: None,
                 },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    // This should match the filterable field of the
-                    // corresponding Texture entry above.
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
             ],
         };
-    pub fn load(gpu: &Gpu, texture: &Texture) -> wgpu::BindGroup {
+
+    /// Creates a material bind group from the base color, normal,
+    /// metallic-roughness, emissive and occlusion textures, each followed by
+    /// its sampler, and the material uniform.
+    pub fn load(gpu: &Gpu, textures: [&Texture; 5], uniform: &wgpu::Buffer) -> wgpu::BindGroup {
         let device = &gpu.device;
         let layout = Self::get_bind_group_layout(gpu);
+
+        let mut entries = Vec::with_capacity(textures.len() * 2 + 1);
+        for (i, texture) in textures.iter().enumerate() {
+            entries.push(wgpu::BindGroupEntry {
+                binding: i as u32 * 2,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: i as u32 * 2 + 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            });
+        }
+        entries.push(wgpu::BindGroupEntry {
+            binding: 10,
+            resource: uniform.as_entire_binding(),
+        });
+
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: &layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
-                },
-            ],
+            entries: &entries,
         })
     }
     pub fn create_texture(
@@ -170,13 +213,29 @@ impl Texture {
     pub fn default_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self> {
         let img_buffer = Self::create_default_texture();
         let img = DynamicImage::ImageRgba8(img_buffer);
-        Self::from_image(device, queue, &img, Some("Default texture"))
+        // Repeated for generated texture coordinates wrapped past 1.
+        Self::from_image_with_sampler(
+            device,
+            queue,
+            &img,
+            Some("Default texture"),
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            &SamplerData::default().descriptor(),
+        )
     }
 
     pub fn random_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self> {
         let img_buffer = Self::create_random_texture();
         let img = DynamicImage::ImageRgba8(img_buffer);
-        Self::from_image(device, queue, &img, Some("Default texture"))
+        // Repeated for generated texture coordinates wrapped past 1.
+        Self::from_image_with_sampler(
+            device,
+            queue,
+            &img,
+            Some("Default texture"),
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            &SamplerData::default().descriptor(),
+        )
     }
 
     pub fn from_bytes(
@@ -189,11 +248,73 @@ impl Texture {
         Self::from_image(device, queue, &img, Some(label))
     }
 
+    pub fn solid_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [u8; 4],
+    ) -> Result<Self> {
+        let img_buffer = ImageBuffer::from_pixel(1, 1, Rgba(color));
+        let img = DynamicImage::ImageRgba8(img_buffer);
+        Self::from_image(device, queue, &img, Some("Solid texture"))
+    }
+
+    /// Normal map that leaves the surface normal unchanged.
+    pub fn flat_normal_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self> {
+        let img_buffer = ImageBuffer::from_pixel(1, 1, Rgba([128, 128, 255, 255]));
+        let img = DynamicImage::ImageRgba8(img_buffer);
+        Self::from_image_with_format(
+            device,
+            queue,
+            &img,
+            Some("Flat normal texture"),
+            wgpu::TextureFormat::Rgba8Unorm,
+        )
+    }
+
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
+    ) -> Result<Self> {
+        Self::from_image_with_format(
+            device,
+            queue,
+            img,
+            label,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        )
+    }
+
+    /// Normal, metallic-roughness and occlusion maps hold linear data and
+    /// should use `Rgba8Unorm` instead of the sRGB format of color maps.
+    pub fn from_image_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
+    ) -> Result<Self> {
+        let sampler = wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        };
+        Self::from_image_with_sampler(device, queue, img, label, format, &sampler)
+    }
+
+    /// Material maps, wrapped and filtered as their mesh file says.
+    pub fn from_image_with_sampler(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
+        sampler: &wgpu::SamplerDescriptor,
     ) -> Result<Self> {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
@@ -209,7 +330,7 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format,
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
             view_formats: &[],
         });
@@ -231,15 +352,7 @@ impl Texture {
         );
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
+        let sampler = device.create_sampler(sampler);
 
         Ok(Self {
             texture,
@@ -252,6 +365,7 @@ impl Texture {
     pub fn create_depth_texture(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
         label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
@@ -260,15 +374,20 @@ impl Texture {
             height: config.height,
             depth_or_array_layers: 1,
         };
+        // The GL backend can't create multisampled depth textures that are
+        // also sampled, as with software adapters.
+        let usage = match sample_count {
+            1 => wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            _ => wgpu::TextureUsages::RENDER_ATTACHMENT,
+        };
         let desc = wgpu::TextureDescriptor {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT // 3.
-                | wgpu::TextureUsages::TEXTURE_BINDING,
+            usage, // 3.
             view_formats: &[],
         };
         let texture = device.create_texture(&desc);
@@ -282,7 +401,7 @@ impl Texture {
             mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Nearest,
-            compare: Some(wgpu::CompareFunction::LessEqual), // 5.
+            compare: Some(Self::DEPTH_COMPARE), // 5.
             lod_min_clamp: 0.0,
             lod_max_clamp: 100.0,
             ..Default::default()
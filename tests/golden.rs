@@ -0,0 +1,209 @@
+//! Renders fixture scenes through a headless adapter, a software one when
+//! there is no GPU, and compares them with the references in `tests/golden`
+//! by structural similarity.
+//!
+//! After an intended change of the output, write the references with
+//! `VOID_UPDATE_GOLDEN=1 cargo test --test golden`, see [`golden_images`]. On
+//! a mismatch the frame and an amplified difference are written next to the
+//! test binaries, see `CARGO_TARGET_TMPDIR`.
+
+use std::path::{Path, PathBuf};
+
+use image::RgbaImage;
+use void::{
+    headless::{Frame, HeadlessRenderer, RenderOutput, Sky},
+    DebugView, RenderSettings,
+};
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+/// Lowest mean SSIM accepted, below 1 for differences between adapters.
+const MIN_SSIM: f64 = 0.97;
+
+struct Scene {
+    name: &'static str,
+    files: &'static [&'static str],
+    eye: [f32; 3],
+    target: [f32; 3],
+    settings: fn(&mut RenderSettings),
+}
+
+const SCENES: &[Scene] = &[
+    Scene {
+        name: "cube_textured",
+        files: &["res/cube.obj"],
+        eye: [2.5, 2.0, 3.5],
+        target: [0.0, 0.0, 0.0],
+        settings: |_| {},
+    },
+    Scene {
+        name: "gltf_nodes",
+        files: &["models/triangle.gltf"],
+        eye: [1.5, 1.0, 4.0],
+        target: [1.5, 1.0, -0.5],
+        settings: |_| {},
+    },
+    Scene {
+        name: "cube_wireframe",
+        files: &["res/cube.obj"],
+        eye: [2.5, 2.0, 3.5],
+        target: [0.0, 0.0, 0.0],
+        settings: |settings| settings.debug.view = DebugView::Wireframe,
+    },
+    Scene {
+        name: "cube_normals",
+        files: &["res/cube.obj"],
+        eye: [-3.0, 1.5, 2.5],
+        target: [0.0, 0.0, 0.0],
+        settings: |settings| settings.debug.view = DebugView::FaceNormals,
+    },
+];
+
+/// Blessed with `VOID_UPDATE_GOLDEN=1 cargo test --test golden`, which
+/// overwrites `tests/golden/*.png` with the frames of this adapter.
+#[tokio::test]
+async fn golden_images() {
+    let update = std::env::var_os("VOID_UPDATE_GOLDEN").is_some();
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    std::fs::create_dir_all(&out_dir).unwrap();
+
+    let mut failures = Vec::new();
+    for scene in SCENES {
+        // A renderer per scene so that nothing leaks from one to the next.
+        let mut renderer = HeadlessRenderer::with_sky(WIDTH, HEIGHT, Sky::Gradient)
+            .await
+            .expect("no graphics adapter for the golden images");
+        for file in scene.files {
+            renderer.load_model(&root.join(file)).await.unwrap();
+        }
+        renderer.set_camera(
+            scene.eye.into(),
+            scene.target.into(),
+            nalgebra::Vector3::y(),
+        );
+        let mut settings = RenderSettings::default();
+        (scene.settings)(&mut settings);
+        renderer.set_render_settings(settings);
+
+        let Frame::ToneMapped(actual) = renderer
+            .render(WIDTH, HEIGHT, RenderOutput::ToneMapped)
+            .unwrap()
+        else {
+            unreachable!("asked for the tone mapped frame");
+        };
+
+        let reference_path = root
+            .join("tests/golden")
+            .join(format!("{}.png", scene.name));
+        if update {
+            actual.save(&reference_path).unwrap();
+            continue;
+        }
+
+        let reference = match image::open(&reference_path) {
+            Ok(reference) => reference.to_rgba8(),
+            Err(msg) => {
+                failures.push(format!("{}: no reference ({msg})", scene.name));
+                continue;
+            }
+        };
+        if reference.dimensions() != actual.dimensions() {
+            failures.push(format!(
+                "{}: {:?} frame for a {:?} reference",
+                scene.name,
+                actual.dimensions(),
+                reference.dimensions()
+            ));
+            continue;
+        }
+
+        let score = ssim(&reference, &actual);
+        if score < MIN_SSIM {
+            let actual_path = out_dir.join(format!("{}.png", scene.name));
+            let diff_path = out_dir.join(format!("{}-diff.png", scene.name));
+            actual.save(&actual_path).unwrap();
+            difference(&reference, &actual).save(&diff_path).unwrap();
+            failures.push(format!(
+                "{}: SSIM {score:.4} < {MIN_SSIM}, see {} and {}",
+                scene.name,
+                actual_path.display(),
+                diff_path.display()
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\nRun with VOID_UPDATE_GOLDEN=1 to accept the new output",
+        failures.join("\n")
+    );
+}
+
+fn luma(image: &RgbaImage) -> Vec<f64> {
+    image
+        .pixels()
+        .map(|p| (0.2126 * p[0] as f64 + 0.7152 * p[1] as f64 + 0.0722 * p[2] as f64) / 255.0)
+        .collect()
+}
+
+/// Mean structural similarity of the luma of `a` and `b` over 8x8 windows.
+fn ssim(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    const WINDOW: u32 = 8;
+    const STEP: u32 = 4;
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+
+    let (width, height) = a.dimensions();
+    let (a, b) = (luma(a), luma(b));
+    let mut total = 0.0;
+    let mut windows = 0;
+    for y in (0..=height - WINDOW).step_by(STEP as usize) {
+        for x in (0..=width - WINDOW).step_by(STEP as usize) {
+            let pixels = (y..y + WINDOW)
+                .flat_map(|y| (x..x + WINDOW).map(move |x| (y * width + x) as usize));
+            let n = (WINDOW * WINDOW) as f64;
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+                (0.0, 0.0, 0.0, 0.0, 0.0);
+            for i in pixels {
+                sum_a += a[i];
+                sum_b += b[i];
+                sum_aa += a[i] * a[i];
+                sum_bb += b[i] * b[i];
+                sum_ab += a[i] * b[i];
+            }
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let covariance = sum_ab / n - mean_a * mean_b;
+
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    total / windows as f64
+}
+
+/// Absolute difference of the colors, times 4 to show small changes.
+fn difference(a: &RgbaImage, b: &RgbaImage) -> RgbaImage {
+    RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        let (pa, pb) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        let channel = |i: usize| (pa[i].abs_diff(pb[i]) as u32 * 4).min(255) as u8;
+        image::Rgba([channel(0), channel(1), channel(2), 255])
+    })
+}
+
+#[test]
+fn ssim_of_identical_images_is_one() {
+    let image = RgbaImage::from_fn(32, 32, |x, y| {
+        image::Rgba([(x * 8) as u8, (y * 8) as u8, 0, 255])
+    });
+    assert!((ssim(&image, &image) - 1.0).abs() < 1e-9);
+
+    let mut shifted = image.clone();
+    for pixel in shifted.pixels_mut() {
+        pixel[0] = 255 - pixel[0];
+    }
+    assert!(ssim(&image, &shifted) < MIN_SSIM);
+}